@@ -0,0 +1,57 @@
+//! Criterion latency benchmarks of each capture strategy in the focused
+//! app, next to the `bench` example, which measures several apps and prints
+//! method profiles:
+//!
+//! ```sh
+//! cargo bench --bench capture
+//! ```
+//!
+//! Focus an app with some text selected before it starts. Strategies that
+//! fail there (no permission, not on this platform) are skipped, and the
+//! clipboard strategy presses the copy shortcut every iteration. The HTML
+//! group, what the clipboard path does with a copied web page, runs
+//! anywhere.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use get_selected_text::{CaptureOptions, Strategy};
+
+const STRATEGIES: [Strategy; 4] = [
+    Strategy::AppSpecific,
+    Strategy::Accessibility,
+    Strategy::Clipboard,
+    Strategy::Ocr,
+];
+
+fn capture(options: &CaptureOptions) -> Result<(), Box<dyn std::error::Error>> {
+    get_selected_text::get_selected_text_with_options(options).map(drop)
+}
+
+fn strategies(c: &mut Criterion) {
+    let mut group = c.benchmark_group("capture");
+    group.sample_size(10);
+    for strategy in STRATEGIES {
+        let options = CaptureOptions::new().strategies([strategy]);
+        if let Err(e) = capture(&options) {
+            eprintln!("skipping {strategy:?}: {e}");
+            continue;
+        }
+        group.bench_function(format!("{strategy:?}"), |b| b.iter(|| capture(&options)));
+    }
+    group.finish();
+}
+
+fn html(c: &mut Criterion) {
+    let row = r#"<tr><td><a href="https://example.com/a?b=1&amp;c=2">link</a></td><td>3</td></tr>"#;
+    let page = format!("<p>Some <b>text</b></p><table>{}</table>", row.repeat(200));
+    let mut group = c.benchmark_group("html");
+    group.bench_function("extract_links_from_html", |b| {
+        b.iter(|| get_selected_text::extract_links_from_html(&page))
+    });
+    group.bench_function("extract_tables_from_html", |b| {
+        b.iter(|| get_selected_text::extract_tables_from_html(&page))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, strategies, html);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        // a vendored protoc unless one is given, so building doesn't need one
+        // installed
+        if std::env::var_os("PROTOC").is_none() {
+            std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        }
+        tonic_prost_build::configure()
+            .compile_protos(&["proto/get_selected_text.proto"], &["proto"])
+            .unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+//! Measures each capture strategy on its own in the apps you focus, and
+//! prints a report plus the matching method profiles, ready for
+//! `import_method_profiles`:
+//!
+//! ```sh
+//! cargo run --release --example bench --features cli -- --apps 3 --rounds 20 > profiles.json
+//! ```
+//!
+//! For each app it waits a few seconds for you to focus it with some text
+//! selected, then captures `--rounds` times per strategy. The clipboard
+//! strategy presses the copy shortcut every round.
+
+use std::time::{Duration, Instant};
+
+use get_selected_text::{AppProfile, CaptureOptions, MethodProfile, MethodProfiles, Strategy};
+
+const STRATEGIES: [Strategy; 4] = [
+    Strategy::AppSpecific,
+    Strategy::Accessibility,
+    Strategy::Clipboard,
+    Strategy::Ocr,
+];
+
+struct Args {
+    apps: usize,
+    rounds: u32,
+    delay: Duration,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        apps: 1,
+        rounds: 10,
+        delay: Duration::from_secs(5),
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut number = || {
+            iter.next()
+                .and_then(|n| n.parse::<u64>().ok())
+                .ok_or(format!("{arg} expects a number"))
+        };
+        match arg.as_str() {
+            "--apps" => args.apps = number()? as usize,
+            "--rounds" => args.rounds = number()? as u32,
+            "--delay" => args.delay = Duration::from_secs(number()?),
+            _ => return Err(format!("unknown argument: {arg}")),
+        }
+    }
+    Ok(args)
+}
+
+/// The focused app as the method statistics name it, see `AppId`.
+fn app_id() -> String {
+    get_selected_text::get_window_meta()
+        .map(|meta| meta.executable_path.unwrap_or(meta.app_name))
+        .unwrap_or_default()
+}
+
+/// Whether one capture with only `strategy` found a selection, along with
+/// the app it captured from.
+fn capture(strategy: Strategy) -> (Result<bool, String>, String) {
+    let options = CaptureOptions::new().strategies([strategy]);
+    let result = get_selected_text::get_selected_text_with_options(&options);
+    #[cfg(target_os = "macos")]
+    let app = result
+        .as_ref()
+        .ok()
+        .and_then(|selected| selected.bundle_id.clone())
+        .unwrap_or_else(app_id);
+    #[cfg(not(target_os = "macos"))]
+    let app = app_id();
+    #[cfg(target_os = "macos")]
+    let found = result.map(|selected| selected.text.iter().any(|text| !text.is_empty()));
+    #[cfg(not(target_os = "macos"))]
+    let found = result.map(|text| !text.is_empty());
+    (found.map_err(|e| e.to_string()), app)
+}
+
+fn bench(strategy: Strategy, rounds: u32) -> (MethodProfile, String, Option<String>) {
+    let mut profile = MethodProfile {
+        strategy,
+        found: 0,
+        missed: 0,
+        latency_ms: 0,
+    };
+    let mut latencies = Vec::new();
+    let mut app = String::new();
+    let mut error = None;
+    for _ in 0..rounds {
+        let start = Instant::now();
+        let (found, captured_from) = capture(strategy);
+        latencies.push(start.elapsed());
+        app = captured_from;
+        match found {
+            Ok(true) => profile.found += 1,
+            Ok(false) => profile.missed += 1,
+            Err(e) => {
+                profile.missed += 1;
+                error = Some(e);
+            }
+        }
+    }
+    latencies.sort();
+    profile.latency_ms = latencies
+        .get(latencies.len() / 2)
+        .map_or(0, |latency| latency.as_millis() as u64);
+    (profile, app, error)
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\nUsage: bench [--apps <n>] [--rounds <n>] [--delay <s>]");
+            std::process::exit(2);
+        }
+    };
+
+    let mut profiles = MethodProfiles::default();
+    for n in 1..=args.apps {
+        eprintln!(
+            "Focus app {n} of {} with some text selected, starting in {} s",
+            args.apps,
+            args.delay.as_secs()
+        );
+        std::thread::sleep(args.delay);
+
+        let mut app = AppProfile {
+            app: app_id(),
+            methods: Vec::new(),
+        };
+        eprintln!("{}", app.app);
+        for strategy in STRATEGIES {
+            let (profile, captured_from, error) = bench(strategy, args.rounds);
+            if !captured_from.is_empty() {
+                app.app = captured_from;
+            }
+            eprintln!(
+                "  {:<14} found {:>3}/{:<3} median {:>5} ms{}",
+                format!("{strategy:?}"),
+                profile.found,
+                args.rounds,
+                profile.latency_ms,
+                error.map(|e| format!("  ({e})")).unwrap_or_default()
+            );
+            app.methods.push(profile);
+        }
+        profiles.apps.push(app);
+    }
+    println!("{}", serde_json::to_string_pretty(&profiles).unwrap());
+}
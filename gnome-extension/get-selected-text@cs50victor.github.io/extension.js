@@ -0,0 +1,36 @@
+import Gio from 'gi://Gio';
+import GLib from 'gi://GLib';
+import St from 'gi://St';
+import {Extension} from 'resource:///org/gnome/shell/extensions/extension.js';
+
+const OBJECT_PATH = '/io/github/cs50victor/GetSelectedText';
+
+const INTERFACE = `
+<node>
+  <interface name="io.github.cs50victor.GetSelectedText">
+    <method name="GetSelection">
+      <arg type="s" direction="out" name="text"/>
+    </method>
+  </interface>
+</node>`;
+
+class Service {
+    // the Shell sees the primary selection of every app, whatever has focus
+    GetSelectionAsync(_params, invocation) {
+        St.Clipboard.get_default().get_text(St.ClipboardType.PRIMARY, (_clipboard, text) => {
+            invocation.return_value(new GLib.Variant('(s)', [text ?? '']));
+        });
+    }
+}
+
+export default class GetSelectedTextExtension extends Extension {
+    enable() {
+        this._object = Gio.DBusExportedObject.wrapJSObject(INTERFACE, new Service());
+        this._object.export(Gio.DBus.session, OBJECT_PATH);
+    }
+
+    disable() {
+        this._object.unexport();
+        this._object = null;
+    }
+}
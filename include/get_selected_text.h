@@ -0,0 +1,81 @@
+#ifndef GET_SELECTED_TEXT_H
+#define GET_SELECTED_TEXT_H
+
+/* Generated by cbindgen, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of [`gst_get_selected_text`]. Must be released with
+ * [`gst_free_result`].
+ */
+typedef struct GstResult {
+  /**
+   * `true` when the capture succeeded; `error` is null in that case.
+   */
+  bool ok;
+  /**
+   * `true` when `text` contains file paths (Finder selections on macOS).
+   */
+  bool is_file_paths;
+  /**
+   * Name of the frontmost application, may be an empty string.
+   */
+  char *app_name;
+  /**
+   * Bundle identifier of the frontmost application, null when unknown.
+   */
+  char *bundle_id;
+  /**
+   * Process id of the frontmost application, `-1` when unknown.
+   */
+  int32_t process_id;
+  /**
+   * Short version string of the frontmost application, null when unknown.
+   */
+  char *app_version;
+  /**
+   * Window id of the frontmost window, `0` when unknown.
+   */
+  uint32_t window_id;
+  /**
+   * Array of `text_len` NUL-terminated UTF-8 strings.
+   */
+  char **text;
+  size_t text_len;
+  /**
+   * Error message when `ok` is `false`, null otherwise.
+   */
+  char *error;
+} GstResult;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Captures the current selection. A panic, which mustn't unwind into the
+ * caller, comes back as an error result.
+ *
+ * Never returns null; release the result with [`gst_free_result`].
+ */
+struct GstResult *gst_get_selected_text(void);
+
+/**
+ * Releases a result returned by [`gst_get_selected_text`]. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * `result` must come from [`gst_get_selected_text`] and must not be used afterwards.
+ */
+void gst_free_result(struct GstResult *result);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GET_SELECTED_TEXT_H */
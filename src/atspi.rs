@@ -0,0 +1,248 @@
+//! AT-SPI, the Linux accessibility bus: its `object:text-selection-changed`
+//! events drive the selection watcher, and Chromium selections are read
+//! from it.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use zbus::blocking::fdo::{DBusProxy, PropertiesProxy};
+use zbus::blocking::{connection, Connection, MessageIterator};
+use zbus::message::Type;
+use zbus::zvariant::{OwnedObjectPath, Value};
+use zbus::MatchRule;
+
+const SELECTION_CHANGED: &str = "object:text-selection-changed";
+
+const REGISTRY: &str = "org.a11y.atspi.Registry";
+const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+const TEXT: &str = "org.a11y.atspi.Text";
+
+// bits of the state set `GetState` returns
+const STATE_FOCUSED: u32 = 12;
+const STATE_SHOWING: u32 = 25;
+
+// an app that stopped answering shouldn't hang the capture
+const METHOD_TIMEOUT: Duration = Duration::from_millis(500);
+// pages can have tens of thousands of nodes, only visible ones are visited
+const MAX_VISITED: usize = 2000;
+
+/// An object on the bus: the app's unique name and path.
+type Accessible = (String, OwnedObjectPath);
+
+/// `org.a11y.Status.IsEnabled` before [`enable_accessibility`] set it, `None`
+/// until it ran.
+static WAS_ENABLED: Mutex<Option<bool>> = Mutex::new(None);
+
+/// Chromium and Qt only build their accessibility tree once assistive
+/// technology is announced through `org.a11y.Status.IsEnabled`, which is what
+/// turning on a screen reader sets. Done once, when a Chromium window is
+/// first captured from, so the first capture may still find no tree.
+///
+/// The flag is session-wide: until [`restore_accessibility`] puts it back,
+/// every Chromium and Qt app in the session keeps its tree, which costs them
+/// some memory and speed.
+fn enable_accessibility() {
+    let mut was_enabled = WAS_ENABLED.lock();
+    if was_enabled.is_some() {
+        return;
+    }
+    let enable = || -> Result<bool, Box<dyn std::error::Error>> {
+        let (status, interface) = accessibility_status()?;
+        let enabled = bool::try_from(status.get(interface.clone(), "IsEnabled")?)?;
+        if !enabled {
+            status.set(interface, "IsEnabled", Value::from(true))?;
+        }
+        Ok(enabled)
+    };
+    // tried once either way, like turning it on
+    *was_enabled = Some(enable().unwrap_or(true));
+}
+
+/// Turns `org.a11y.Status.IsEnabled` back off if [`enable_accessibility`]
+/// turned it on, for [`crate::shutdown`].
+pub(crate) fn restore_accessibility() {
+    let mut was_enabled = WAS_ENABLED.lock();
+    if was_enabled.take() == Some(false) {
+        let _ = accessibility_status().and_then(|(status, interface)| {
+            Ok(status.set(interface, "IsEnabled", Value::from(false))?)
+        });
+    }
+}
+
+fn accessibility_status() -> Result<
+    (
+        PropertiesProxy<'static>,
+        zbus::names::InterfaceName<'static>,
+    ),
+    Box<dyn std::error::Error>,
+> {
+    let status = PropertiesProxy::builder(&Connection::session()?)
+        .destination("org.a11y.Bus")?
+        .path("/org/a11y/bus")?
+        .build()?;
+    let interface = zbus::names::InterfaceName::try_from("org.a11y.Status")?;
+    Ok((status, interface))
+}
+
+fn accessibility_bus() -> Result<Connection, Box<dyn std::error::Error>> {
+    let address: String = Connection::session()?
+        .call_method(
+            Some("org.a11y.Bus"),
+            "/org/a11y/bus",
+            Some("org.a11y.Bus"),
+            "GetAddress",
+            &(),
+        )?
+        .body()
+        .deserialize()?;
+    Ok(connection::Builder::address(address.as_str())?
+        .method_timeout(METHOD_TIMEOUT)
+        .build()?)
+}
+
+/// Whether the accessibility bus is running.
+pub(crate) fn is_available() -> bool {
+    accessibility_bus().is_ok()
+}
+
+fn call<R>(
+    bus: &Connection,
+    (name, path): &Accessible,
+    interface: &str,
+    method: &str,
+    body: &(impl serde::Serialize + zbus::zvariant::DynamicType),
+) -> Result<R, Box<dyn std::error::Error>>
+where
+    R: serde::de::DeserializeOwned + zbus::zvariant::Type,
+{
+    Ok(bus
+        .call_method(
+            Some(name.as_str()),
+            path.as_str(),
+            Some(interface),
+            method,
+            body,
+        )?
+        .body()
+        .deserialize()?)
+}
+
+fn has_state(states: &[u32], state: u32) -> bool {
+    states
+        .get((state / 32) as usize)
+        .is_some_and(|word| word & (1 << (state % 32)) != 0)
+}
+
+/// The focused object under `app`, walking the visible part of its tree.
+fn find_focused(bus: &Connection, app: Accessible) -> Option<Accessible> {
+    let mut stack = vec![app];
+    let mut visited = 0;
+    while let Some(accessible) = stack.pop() {
+        visited += 1;
+        if visited > MAX_VISITED {
+            return None;
+        }
+        let states: Vec<u32> = call(bus, &accessible, ACCESSIBLE, "GetState", &()).ok()?;
+        if has_state(&states, STATE_FOCUSED) {
+            return Some(accessible);
+        }
+        // the app itself has no states
+        if visited > 1 && !has_state(&states, STATE_SHOWING) {
+            continue;
+        }
+        let children: Vec<Accessible> =
+            call(bus, &accessible, ACCESSIBLE, "GetChildren", &()).unwrap_or_default();
+        stack.extend(children.into_iter().rev());
+    }
+    None
+}
+
+/// Chrome and Electron apps ship their sandbox helpers next to the
+/// executable.
+fn is_chromium(executable: &std::path::Path) -> bool {
+    ["chrome-sandbox", "chrome_crashpad_handler"]
+        .iter()
+        .any(|helper| executable.with_file_name(helper).exists())
+}
+
+/// Reads the selection of a Chromium (Chrome, Electron) window through
+/// AT-SPI. `None` when it can't tell, so the caller falls back to the
+/// clipboard.
+pub(crate) fn get_chromium_selected_text() -> Option<String> {
+    let window = crate::window::active_window().ok()?;
+    if !is_chromium(std::path::Path::new(&window.process_path?)) {
+        return None;
+    }
+    enable_accessibility();
+
+    let bus = accessibility_bus().ok()?;
+    let root = (
+        REGISTRY.to_string(),
+        OwnedObjectPath::try_from(ROOT_PATH).ok()?,
+    );
+    let apps: Vec<Accessible> = call(&bus, &root, ACCESSIBLE, "GetChildren", &()).ok()?;
+    let dbus = DBusProxy::new(&bus).ok()?;
+    let app = apps.into_iter().find(|(name, _)| {
+        name.as_str().try_into().ok().is_some_and(|name| {
+            dbus.get_connection_unix_process_id(name)
+                .is_ok_and(|pid| pid as u64 == window.process_id)
+        })
+    })?;
+
+    // the tree may not be built yet, try again with the next capture
+    let focused = find_focused(&bus, app)?;
+    let count: i32 = call(&bus, &focused, TEXT, "GetNSelections", &()).ok()?;
+    let mut texts = Vec::new();
+    for index in 0..count {
+        let (start, end): (i32, i32) =
+            call(&bus, &focused, TEXT, "GetSelection", &(index,)).ok()?;
+        let text: String = call(&bus, &focused, TEXT, "GetText", &(start, end)).ok()?;
+        // embedded objects (links, images) stand in the text as U+FFFC
+        texts.push(text.replace('\u{fffc}', ""));
+    }
+    // only the focused object is read, a selection spanning several (e.g.
+    // paragraphs of a page) comes back as the focused one's part
+    let text = texts.join("\n");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Selection-changed notifications of every accessible app on the bus.
+pub(crate) struct SelectionChanges {
+    pub(crate) receiver: mpsc::Receiver<()>,
+}
+
+impl SelectionChanges {
+    pub(crate) fn subscribe() -> Result<Self, Box<dyn std::error::Error>> {
+        let conn = accessibility_bus()?;
+
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface("org.a11y.atspi.Event.Object")?
+            .member("TextSelectionChanged")?
+            .build();
+        let messages = MessageIterator::for_match_rule(rule, &conn, None)?;
+        // toolkits only emit the events someone registered for
+        conn.call_method(
+            Some(REGISTRY),
+            "/org/a11y/atspi/registry",
+            Some(REGISTRY),
+            "RegisterEvent",
+            &(SELECTION_CHANGED,),
+        )?;
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _conn = conn;
+            for message in messages {
+                // ends with the first event after the watcher is gone
+                if message.is_err() || sender.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { receiver })
+    }
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use crate::Strategy;
+
+/// One capture method tried while reading the selection, for bug reports:
+/// see [`crate::SelectedText::attempts`] and [`crate::GetTextError::Failed`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Attempt {
+    pub strategy: Strategy,
+    /// The method itself, e.g. `ax`, `applescript`, `ui-automation`,
+    /// `primary-selection`.
+    pub method: &'static str,
+    pub duration: Duration,
+    /// Why it failed, `None` when it got an answer (possibly empty).
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Attempts(pub(crate) Vec<Attempt>);
+
+impl Attempts {
+    /// Runs `capture`, recording how long it took and how it failed.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    pub(crate) fn record<T>(
+        &mut self,
+        strategy: Strategy,
+        method: &'static str,
+        capture: impl FnOnce() -> Result<T, Box<dyn std::error::Error>>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let start = std::time::Instant::now();
+        let result = capture();
+        self.0.push(Attempt {
+            strategy,
+            method,
+            duration: start.elapsed(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+        result
+    }
+
+    /// Like [`Self::record`] for methods that can only tell they found
+    /// nothing.
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "session-bus")
+    ))]
+    pub(crate) fn record_found<T>(
+        &mut self,
+        strategy: Strategy,
+        method: &'static str,
+        capture: impl FnOnce() -> Option<T>,
+    ) -> Option<T> {
+        self.record(strategy, method, || capture().ok_or("Nothing found".into()))
+            .ok()
+    }
+}
@@ -0,0 +1,775 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use get_selected_text::CaptureOptions;
+use serde_json::{json, Value};
+
+const USAGE: &str = "\
+Usage: get-selected-text [--json | --null | --format <raycast|alfred>] [--ocr] [--watch [--interval <ms>]] [--diagnose] [--self-test] [--ax-tree <depth>]
+       get-selected-text --document
+       get-selected-text replace [--exec <command>] [--allow-empty]
+       get-selected-text --dbus
+       get-selected-text --xpc <mach-service>
+       get-selected-text --native-messaging
+       get-selected-text --native-manifest <extension-id>
+
+    --json             print the selection as a JSON object
+    -0, --null         end each entry (e.g. each Finder file path) with NUL instead of a newline
+    --format <name>    print the selection as list items (title, subtitle, arg) for a Raycast
+                       script or an Alfred Script Filter, errors included
+    --ocr              fall back to text recognition on a screenshot (macOS)
+    --watch            print one JSON object per line (NDJSON) whenever the selection changes
+    --interval <ms>    polling interval for --watch (default: 300, at least 100)
+    --diagnose         print what the session supports and how captures are made, as JSON
+    --self-test        check each capture method against a TextEdit document, as JSON (macOS)
+    --ax-tree <depth>  print the focused accessibility element and its children as JSON (macOS)
+    --document         print the whole focused document instead of the selection (macOS)
+    replace            print the selection, read the text to put over it from stdin
+    --exec <command>   for replace: pipe the selection through a shell command instead
+    --allow-empty      for replace: delete the selection when the replacement is empty
+    --dbus             serve the selection as org.getselectedtext.Daemon on the session bus (Linux)
+    --xpc <name>       answer sandboxed apps' requests on a launch agent's Mach service (macOS)
+
+    --native-messaging          serve a browser extension over stdin/stdout, also picked when a
+                                browser starts it as a native-messaging host
+    --native-manifest <id>      print the host manifest for a Chrome extension id, or a Firefox
+                                add-on id (with an @)
+";
+
+/// The host name extensions pass to `chrome.runtime.connectNative`.
+const NATIVE_HOST_NAME: &str = "io.github.yetone.get_selected_text";
+/// Longest native message read or written. Requests are small, and browsers
+/// disconnect hosts sending them more.
+const MAX_NATIVE_MESSAGE: usize = 1024 * 1024;
+
+/// Launchers whose list items the selection can be printed as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Launcher {
+    Raycast,
+    Alfred,
+}
+
+struct Args {
+    json: bool,
+    null: bool,
+    format: Option<Launcher>,
+    ocr: bool,
+    watch: bool,
+    diagnose: bool,
+    self_test: bool,
+    ax_tree: Option<usize>,
+    document: bool,
+    replace: bool,
+    exec: Option<String>,
+    allow_empty: bool,
+    dbus: bool,
+    xpc: Option<String>,
+    native_messaging: bool,
+    native_manifest: Option<String>,
+    interval: Duration,
+}
+
+fn parse_args(argv: Vec<String>) -> Result<Args, String> {
+    let mut args = Args {
+        json: false,
+        null: false,
+        format: None,
+        ocr: false,
+        watch: false,
+        diagnose: false,
+        self_test: false,
+        ax_tree: None,
+        document: false,
+        replace: false,
+        exec: None,
+        allow_empty: false,
+        dbus: false,
+        xpc: None,
+        native_messaging: false,
+        native_manifest: None,
+        interval: Duration::from_millis(300),
+    };
+    if started_by_browser(&argv) {
+        args.native_messaging = true;
+        return Ok(args);
+    }
+    let mut iter = argv.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--json" => args.json = true,
+            "-0" | "--null" => args.null = true,
+            "--format" => {
+                args.format = match iter.next().as_deref() {
+                    Some("raycast") => Some(Launcher::Raycast),
+                    Some("alfred") => Some(Launcher::Alfred),
+                    _ => return Err("--format expects raycast or alfred".to_string()),
+                };
+            }
+            "--ocr" => args.ocr = true,
+            "--watch" => args.watch = true,
+            "--diagnose" => args.diagnose = true,
+            "--self-test" => args.self_test = true,
+            "--ax-tree" => {
+                let depth = iter
+                    .next()
+                    .and_then(|depth| depth.parse().ok())
+                    .ok_or("--ax-tree expects a depth")?;
+                args.ax_tree = Some(depth);
+            }
+            "--document" => args.document = true,
+            "replace" => args.replace = true,
+            "--exec" => args.exec = Some(iter.next().ok_or("--exec expects a command")?),
+            "--allow-empty" => args.allow_empty = true,
+            "--dbus" => args.dbus = true,
+            "--xpc" => {
+                args.xpc = Some(iter.next().ok_or("--xpc expects a Mach service name")?);
+            }
+            "--native-messaging" => args.native_messaging = true,
+            "--native-manifest" => {
+                args.native_manifest = Some(
+                    iter.next()
+                        .ok_or("--native-manifest expects an extension id")?,
+                );
+            }
+            "--interval" => {
+                let ms = iter
+                    .next()
+                    .and_then(|ms| ms.parse().ok())
+                    .ok_or("--interval expects a number of milliseconds")?;
+                args.interval = Duration::from_millis(ms);
+            }
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ => return Err(format!("unknown argument: {arg}")),
+        }
+    }
+    if args.exec.is_some() && !args.replace {
+        return Err("--exec only goes with replace".to_string());
+    }
+    if args.allow_empty && !args.replace {
+        return Err("--allow-empty only goes with replace".to_string());
+    }
+    Ok(args)
+}
+
+/// Whether `argv` is what browsers start a native-messaging host with:
+/// Chrome the calling extension's origin (and a window handle on Windows),
+/// Firefox the manifest's path and the add-on id.
+fn started_by_browser(argv: &[String]) -> bool {
+    match argv {
+        [origin, rest @ ..] if origin.starts_with("chrome-extension://") => {
+            rest.iter().all(|arg| arg.starts_with("--parent-window="))
+        }
+        [manifest, extension] => {
+            manifest.ends_with(".json")
+                && std::path::Path::new(manifest).is_absolute()
+                // an email-like id or a {UUID}
+                && (extension.contains('@')
+                    || extension.starts_with('{') && extension.ends_with('}'))
+        }
+        _ => false,
+    }
+}
+
+fn watch(interval: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let _handle = get_selected_text::watch_selection(interval, move |event| {
+        let _ = tx.send(event);
+    })?;
+    let mut stdout = std::io::stdout().lock();
+    for event in rx {
+        serde_json::to_writer(&mut stdout, &event)?;
+        // flush per event so consumers reading a pipe see it immediately
+        writeln!(stdout)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// Puts what `exec`, or whoever writes to stdin, makes of the selection over
+/// it.
+fn replace(
+    options: &CaptureOptions,
+    exec: Option<&str>,
+    allow_empty: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let selected = get_selected_text::get_selected_text_with_options(options)?;
+    #[cfg(target_os = "macos")]
+    let text = if selected.is_file_paths {
+        return Err("Selected files can't be replaced".into());
+    } else {
+        selected.text.join("\n")
+    };
+    #[cfg(not(target_os = "macos"))]
+    let text = selected;
+
+    let replacement = match exec {
+        Some(command) => {
+            #[cfg(target_os = "windows")]
+            let (shell, flag) = ("cmd", "/C");
+            #[cfg(not(target_os = "windows"))]
+            let (shell, flag) = ("sh", "-c");
+            let mut child = Command::new(shell)
+                .args([flag, command])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?;
+            // written from a thread, the command may answer before it read
+            // everything
+            let mut stdin = child.stdin.take().ok_or("The command has no stdin")?;
+            let input = text.clone();
+            let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+            let output = child.wait_with_output()?;
+            let _ = writer.join();
+            if !output.status.success() {
+                return Err(format!("The command failed with {}", output.status).into());
+            }
+            String::from_utf8(output.stdout)?
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(text.as_bytes())?;
+            stdout.flush()?;
+            drop(stdout);
+            let mut replacement = String::new();
+            std::io::stdin().read_to_string(&mut replacement)?;
+            replacement
+        }
+    };
+    if let Some(replacement) = final_replacement(&text, replacement, allow_empty)? {
+        get_selected_text::replace_selected_text(&replacement)?;
+    }
+    Ok(())
+}
+
+/// What to put over `text`, `None` when it stays as it is. An empty
+/// replacement, a command printing nothing or stdin closed early, would
+/// delete the selection and is refused unless `allow_empty`.
+fn final_replacement(
+    text: &str,
+    replacement: String,
+    allow_empty: bool,
+) -> Result<Option<String>, String> {
+    let replacement = trim_added_newline(text, replacement);
+    if replacement == text {
+        return Ok(None);
+    }
+    if replacement.is_empty() && !allow_empty {
+        return Err(
+            "The replacement is empty, pass --allow-empty to delete the selection".to_string(),
+        );
+    }
+    Ok(Some(replacement))
+}
+
+/// `replacement` without the line break most tools end their output with,
+/// unless `text` ended with one too.
+fn trim_added_newline(text: &str, mut replacement: String) -> String {
+    if !text.ends_with('\n') && replacement.ends_with('\n') {
+        replacement.pop();
+        if replacement.ends_with('\r') {
+            replacement.pop();
+        }
+    }
+    replacement
+}
+
+/// `{"items": [...]}`, one item per entry, or one for the error, which shows
+/// in the launcher's list rather than failing the script.
+fn launcher_items(
+    launcher: Launcher,
+    selected: Result<get_selected_text::Selection, Box<dyn std::error::Error>>,
+) -> Value {
+    let selected = match selected {
+        Ok(selected) => selected,
+        Err(e) => {
+            let mut item = json!({"title": "No selected text", "subtitle": e.to_string()});
+            if launcher == Launcher::Alfred {
+                item["valid"] = json!(false);
+            }
+            return json!({"items": [item]});
+        }
+    };
+    #[cfg(target_os = "macos")]
+    let (entries, app_name, is_file_paths) =
+        (selected.text, selected.app_name, selected.is_file_paths);
+    #[cfg(not(target_os = "macos"))]
+    let (entries, app_name, is_file_paths) = (
+        vec![selected],
+        get_selected_text::get_window_meta()
+            .map(|meta| meta.app_name)
+            .unwrap_or_default(),
+        false,
+    );
+    list_items(launcher, entries, &app_name, is_file_paths)
+}
+
+fn list_items(
+    launcher: Launcher,
+    entries: Vec<String>,
+    app_name: &str,
+    is_file_paths: bool,
+) -> Value {
+    let items: Vec<Value> = entries
+        .into_iter()
+        .map(|entry| {
+            let (title, subtitle) = if is_file_paths {
+                let name = std::path::Path::new(&entry)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| entry.clone());
+                (name, entry.clone())
+            } else {
+                let first_line = entry.lines().map(str::trim).find(|line| !line.is_empty());
+                let characters = format!("{} characters", entry.chars().count());
+                (
+                    first_line.unwrap_or_default().chars().take(100).collect(),
+                    if app_name.is_empty() {
+                        characters
+                    } else {
+                        format!("{app_name} · {characters}")
+                    },
+                )
+            };
+            let mut item = json!({"title": title, "subtitle": subtitle, "arg": entry});
+            if launcher == Launcher::Alfred {
+                item["text"] = json!({"copy": entry, "largetype": entry});
+                if is_file_paths {
+                    item["type"] = json!("file");
+                }
+            }
+            item
+        })
+        .collect();
+    json!({"items": items})
+}
+
+/// Answers length-prefixed JSON requests on stdin, as browsers talk to
+/// native-messaging hosts: `{"id": 1, "method": "get"}`, `watch` (with an
+/// optional `interval_ms`) and `unwatch`, like the WebSocket server.
+fn native_messaging() -> Result<(), Box<dyn std::error::Error>> {
+    let stdout = Arc::new(Mutex::new(std::io::stdout()));
+    let mut stdin = std::io::stdin().lock();
+    // dropping it stops the watcher when the extension disconnects
+    let mut watch = None;
+    while let Some(message) = read_native_message(&mut stdin)? {
+        let response = match serde_json::from_slice::<Value>(&message) {
+            Ok(request) => answer(&request, &stdout, &mut watch),
+            Err(e) => json!({"id": null, "error": format!("Invalid request: {e}")}),
+        };
+        send_native_message(&stdout, &response)?;
+    }
+    Ok(())
+}
+
+/// The next length-prefixed message, `None` once the browser closed stdin.
+fn read_native_message(reader: &mut impl Read) -> std::io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let len = u32::from_ne_bytes(len) as usize;
+    if len > MAX_NATIVE_MESSAGE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("A {len} byte message is too large"),
+        ));
+    }
+    let mut message = vec![0; len];
+    reader.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+fn answer(
+    request: &Value,
+    stdout: &Arc<Mutex<std::io::Stdout>>,
+    watch: &mut Option<get_selected_text::WatchHandle>,
+) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let result: Result<Value, Box<dyn std::error::Error>> =
+        match request.get("method").and_then(Value::as_str) {
+            Some("get") => get_selected_text::get_selected_text()
+                .and_then(|selection| Ok(serde_json::to_value(selection)?)),
+            Some("watch") => {
+                let interval = request
+                    .get("interval_ms")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(300);
+                let stdout = stdout.clone();
+                get_selected_text::watch_selection(Duration::from_millis(interval), move |event| {
+                    let event = json!({"event": "selection", "data": event});
+                    let _ = send_native_message(&stdout, &event);
+                })
+                .map(|handle| {
+                    *watch = Some(handle);
+                    Value::Null
+                })
+            }
+            Some("unwatch") => {
+                *watch = None;
+                Ok(Value::Null)
+            }
+            Some(method) => Err(format!("Unknown method {method}").into()),
+            None => Err("Missing method".into()),
+        };
+    match result {
+        Ok(result) => json!({"id": id, "result": result}),
+        Err(e) => json!({"id": id, "error": e.to_string()}),
+    }
+}
+
+fn send_native_message(stdout: &Mutex<std::io::Stdout>, message: &Value) -> std::io::Result<()> {
+    let message = encode_native_message(message)?;
+    let mut stdout = stdout.lock().unwrap_or_else(|e| e.into_inner());
+    stdout.write_all(&(message.len() as u32).to_ne_bytes())?;
+    stdout.write_all(&message)?;
+    stdout.flush()
+}
+
+/// `message` as JSON, or an error in its place when a browser wouldn't take
+/// it: a reply with its `id`, an event with its `event`.
+fn encode_native_message(message: &Value) -> serde_json::Result<Vec<u8>> {
+    let encoded = serde_json::to_vec(message)?;
+    if encoded.len() <= MAX_NATIVE_MESSAGE {
+        return Ok(encoded);
+    }
+    let error = format!(
+        "The {} byte message is over the {MAX_NATIVE_MESSAGE} bytes browsers accept",
+        encoded.len()
+    );
+    let mut replacement = json!({ "error": error });
+    for key in ["id", "event"] {
+        if let Some(value) = message.get(key) {
+            replacement[key] = value.clone();
+        }
+    }
+    serde_json::to_vec(&replacement)
+}
+
+/// The manifest registering this binary as the native-messaging host of
+/// `extension`, see the browsers' docs for where it goes.
+fn native_manifest(extension: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut manifest = json!({
+        "name": NATIVE_HOST_NAME,
+        "description": "Reads the selected text in other apps",
+        "path": std::env::current_exe()?,
+        "type": "stdio",
+    });
+    // Firefox add-on ids look like emails
+    if extension.contains('@') {
+        manifest["allowed_extensions"] = json!([extension]);
+    } else {
+        manifest["allowed_origins"] = json!([format!("chrome-extension://{extension}/")]);
+    }
+    Ok(manifest)
+}
+
+#[cfg(target_os = "linux")]
+fn serve_dbus() -> Result<(), Box<dyn std::error::Error>> {
+    let _service = get_selected_text::dbus::serve_dbus()?;
+    eprintln!("Serving {}", get_selected_text::dbus::BUS_NAME);
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn serve_dbus() -> Result<(), Box<dyn std::error::Error>> {
+    Err("The D-Bus service is only available on Linux".into())
+}
+
+#[cfg(target_os = "macos")]
+fn serve_xpc(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let _listener = get_selected_text::xpc::serve_xpc(name, None)?;
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn serve_xpc(_: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err("The XPC helper is only available on macOS".into())
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprint!("{e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let result = if args.replace {
+        replace(
+            &CaptureOptions::new().ocr(args.ocr),
+            args.exec.as_deref(),
+            args.allow_empty,
+        )
+    } else if args.dbus {
+        serve_dbus()
+    } else if let Some(name) = &args.xpc {
+        serve_xpc(name)
+    } else if args.native_messaging {
+        native_messaging()
+    } else if let Some(extension) = &args.native_manifest {
+        native_manifest(extension).and_then(|manifest| {
+            println!("{}", serde_json::to_string_pretty(&manifest)?);
+            Ok(())
+        })
+    } else if args.diagnose {
+        serde_json::to_string_pretty(&get_selected_text::diagnose())
+            .map(|diagnostics| println!("{diagnostics}"))
+            .map_err(Into::into)
+    } else if args.self_test {
+        get_selected_text::self_test().and_then(|results| {
+            println!("{}", serde_json::to_string_pretty(&results)?);
+            Ok(())
+        })
+    } else if let Some(depth) = args.ax_tree {
+        get_selected_text::dump_focused_ax_tree(depth).and_then(|tree| {
+            println!("{}", serde_json::to_string_pretty(&tree)?);
+            Ok(())
+        })
+    } else if args.document {
+        get_selected_text::get_full_document_text().map(|text| println!("{text}"))
+    } else if args.watch {
+        watch(args.interval)
+    } else if let Some(launcher) = args.format {
+        let options = get_selected_text::CaptureOptions::new().ocr(args.ocr);
+        let selected = get_selected_text::get_selected_text_with_options(&options);
+        serde_json::to_string(&launcher_items(launcher, selected))
+            .map(|items| println!("{items}"))
+            .map_err(Into::into)
+    } else {
+        let options = get_selected_text::CaptureOptions::new().ocr(args.ocr);
+        get_selected_text::get_selected_text_with_options(&options).and_then(|selected| {
+            if args.json {
+                println!("{}", serde_json::to_string(&selected)?);
+            } else if args.null {
+                #[cfg(target_os = "macos")]
+                let entries = selected.text;
+                #[cfg(not(target_os = "macos"))]
+                let entries = [selected];
+                let mut stdout = std::io::stdout().lock();
+                for entry in entries {
+                    write!(stdout, "{entry}\0")?;
+                }
+            } else {
+                #[cfg(target_os = "macos")]
+                println!("{}", selected.text.join("\n"));
+                #[cfg(not(target_os = "macos"))]
+                println!("{selected}");
+            }
+            Ok(())
+        })
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()).collect())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&["--watch", "--interval", "500"]).unwrap();
+        assert!(parsed.watch && !parsed.native_messaging);
+        assert_eq!(parsed.interval, Duration::from_millis(500));
+        assert!(args(&["--allow-empty"]).is_err());
+        assert!(args(&["replace", "--allow-empty"]).unwrap().allow_empty);
+        assert!(args(&["--exec", "tr a-z A-Z"]).is_err());
+
+        // as Chrome and Firefox start native-messaging hosts
+        assert!(
+            args(&["chrome-extension://abcdefghijklmnop/"])
+                .unwrap()
+                .native_messaging
+        );
+        assert!(
+            args(&[
+                "chrome-extension://abcdefghijklmnop/",
+                "--parent-window=1234"
+            ])
+            .unwrap()
+            .native_messaging
+        );
+        #[cfg(not(windows))]
+        let manifest =
+            "/home/me/.mozilla/native-messaging-hosts/io.github.yetone.get_selected_text.json";
+        #[cfg(windows)]
+        let manifest = r"C:\Users\me\io.github.yetone.get_selected_text.json";
+        assert!(
+            args(&[manifest, "helper@example.com"])
+                .unwrap()
+                .native_messaging
+        );
+        assert!(
+            args(&[manifest, "{6f1e4a5c-0000-4000-8000-000000000000}"])
+                .unwrap()
+                .native_messaging
+        );
+
+        // not just anything that ends in .json
+        assert!(args(&["config.json"]).is_err());
+        assert!(args(&["--json", "out.json"]).is_err());
+        assert!(args(&[manifest]).is_err());
+    }
+
+    fn native_message(message: &[u8]) -> Vec<u8> {
+        let mut bytes = (message.len() as u32).to_ne_bytes().to_vec();
+        bytes.extend(message);
+        bytes
+    }
+
+    #[test]
+    fn test_launcher_items() {
+        let error = launcher_items(Launcher::Raycast, Err("Accessibility access is off".into()));
+        assert_eq!(
+            error,
+            json!({"items": [{"title": "No selected text", "subtitle": "Accessibility access is off"}]})
+        );
+        let error = launcher_items(Launcher::Alfred, Err("nothing".into()));
+        assert_eq!(error["items"][0]["valid"], false);
+
+        let text = "\n  Hello, world  \nsecond line".to_string();
+        let items = list_items(Launcher::Raycast, vec![text.clone()], "Notes", false);
+        assert_eq!(
+            items,
+            json!({"items": [{"title": "Hello, world", "subtitle": "Notes · 29 characters", "arg": text}]})
+        );
+        let items = list_items(Launcher::Raycast, vec!["é".repeat(150)], "", false);
+        assert_eq!(
+            items["items"][0]["title"].as_str().unwrap().chars().count(),
+            100
+        );
+        assert_eq!(items["items"][0]["subtitle"], "150 characters");
+
+        let items = list_items(
+            Launcher::Alfred,
+            vec!["/Users/me/a.txt".to_string(), "/Users/me/b.txt".to_string()],
+            "Finder",
+            true,
+        );
+        assert_eq!(
+            items["items"][1],
+            json!({
+                "title": "b.txt",
+                "subtitle": "/Users/me/b.txt",
+                "arg": "/Users/me/b.txt",
+                "text": {"copy": "/Users/me/b.txt", "largetype": "/Users/me/b.txt"},
+                "type": "file",
+            })
+        );
+    }
+
+    #[test]
+    fn test_trim_added_newline() {
+        let trim = |text: &str, replacement: &str| trim_added_newline(text, replacement.into());
+        assert_eq!(trim("hello", "HELLO\n"), "HELLO");
+        assert_eq!(trim("hello", "HELLO\r\n"), "HELLO");
+        assert_eq!(trim("hello\n", "HELLO\n"), "HELLO\n");
+        // only the one the tool added
+        assert_eq!(trim("hello", "HELLO\n\n"), "HELLO\n");
+        assert_eq!(trim("hello", "HELLO"), "HELLO");
+        assert_eq!(trim("", "\n"), "");
+    }
+
+    #[test]
+    fn test_final_replacement() {
+        assert_eq!(
+            final_replacement("hello", "HELLO\n".into(), false),
+            Ok(Some("HELLO".to_string()))
+        );
+        assert_eq!(
+            final_replacement("hello", "hello\n".into(), false),
+            Ok(None)
+        );
+        // a command printing nothing, or stdin closed before anything was read
+        assert!(final_replacement("hello", "".into(), false).is_err());
+        assert!(final_replacement("hello", "\n".into(), false).is_err());
+        assert_eq!(
+            final_replacement("hello", "".into(), true),
+            Ok(Some(String::new()))
+        );
+        assert_eq!(final_replacement("", "".into(), false), Ok(None));
+    }
+
+    #[test]
+    fn test_native_manifest() {
+        let chrome = native_manifest("abcdefghijklmnopabcdefghijklmnop").unwrap();
+        assert_eq!(chrome["name"], NATIVE_HOST_NAME);
+        assert_eq!(chrome["type"], "stdio");
+        assert_eq!(chrome["path"], json!(std::env::current_exe().unwrap()));
+        assert_eq!(
+            chrome["allowed_origins"],
+            json!(["chrome-extension://abcdefghijklmnopabcdefghijklmnop/"])
+        );
+        assert!(chrome.get("allowed_extensions").is_none());
+
+        let firefox = native_manifest("helper@example.com").unwrap();
+        assert_eq!(firefox["allowed_extensions"], json!(["helper@example.com"]));
+        assert!(firefox.get("allowed_origins").is_none());
+    }
+
+    #[test]
+    fn test_read_native_message() {
+        let mut input = native_message(br#"{"id": 1}"#);
+        input.extend(native_message(b"{}"));
+        let mut reader = &input[..];
+        assert_eq!(
+            read_native_message(&mut reader).unwrap().as_deref(),
+            Some(&br#"{"id": 1}"#[..])
+        );
+        assert_eq!(
+            read_native_message(&mut reader).unwrap().as_deref(),
+            Some(&b"{}"[..])
+        );
+        assert_eq!(read_native_message(&mut reader).unwrap(), None);
+
+        // refused before anything is allocated for it
+        let huge = u32::MAX.to_ne_bytes();
+        assert!(read_native_message(&mut &huge[..]).is_err());
+    }
+
+    #[test]
+    fn test_encode_native_message() {
+        let reply = json!({"id": 1, "result": "hello"});
+        assert_eq!(
+            encode_native_message(&reply).unwrap(),
+            serde_json::to_vec(&reply).unwrap()
+        );
+
+        let selection = "x".repeat(MAX_NATIVE_MESSAGE);
+        let reply: Value = serde_json::from_slice(
+            &encode_native_message(&json!({"id": 2, "result": selection})).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(reply["id"], 2);
+        assert!(reply.get("result").is_none());
+        assert!(reply["error"]
+            .as_str()
+            .unwrap()
+            .contains("bytes browsers accept"));
+
+        let event: Value = serde_json::from_slice(
+            &encode_native_message(&json!({"event": "selection", "data": selection})).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(event["event"], "selection");
+        assert!(event.get("data").is_none());
+    }
+}
@@ -0,0 +1,207 @@
+//! Cheap heuristics telling apart source code from prose, for consumers that
+//! route the two to different pipelines.
+
+/// Result of [`detect_code`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CodeDetection {
+    pub is_code: bool,
+    /// Lowercase language name (`"rust"`, `"python"`, ...), when one stands out.
+    pub language: Option<&'static str>,
+    /// Share of non-empty lines that look like code, between 0 and 1.
+    pub score: f32,
+}
+
+// (language, extensions seen in editor window titles, tokens typical of it)
+const LANGUAGES: &[(&str, &[&str], &[&str])] = &[
+    (
+        "rust",
+        &["rs"],
+        &[
+            "fn ", "let mut ", "impl ", "pub fn ", "::", "&str", "-> ", "#[", "println!",
+        ],
+    ),
+    (
+        "python",
+        &["py"],
+        &[
+            "def ",
+            "import ",
+            "self.",
+            "elif ",
+            "print(",
+            "from ",
+            "None",
+            "    return ",
+        ],
+    ),
+    (
+        "javascript",
+        &["js", "jsx", "mjs"],
+        &[
+            "const ",
+            "function ",
+            "=> ",
+            "console.",
+            "let ",
+            "require(",
+            "===",
+            "export ",
+        ],
+    ),
+    (
+        "typescript",
+        &["ts", "tsx"],
+        &[
+            "interface ",
+            ": string",
+            ": number",
+            "const ",
+            "=> ",
+            "export ",
+            "type ",
+        ],
+    ),
+    (
+        "go",
+        &["go"],
+        &["func ", ":= ", "package ", "fmt.", "err != nil", "import ("],
+    ),
+    (
+        "java",
+        &["java", "kt"],
+        &[
+            "public class ",
+            "private ",
+            "System.out",
+            "void ",
+            "new ",
+            "@Override",
+        ],
+    ),
+    (
+        "c",
+        &["c", "h", "cpp", "cc", "hpp"],
+        &[
+            "#include", "int main", "std::", "printf(", "->", "NULL", "sizeof(",
+        ],
+    ),
+    (
+        "shell",
+        &["sh", "bash", "zsh"],
+        &["#!/bin/", "echo ", "$(", "fi", "then", "export ", "sudo "],
+    ),
+    (
+        "sql",
+        &["sql"],
+        &[
+            "SELECT ",
+            "FROM ",
+            "WHERE ",
+            "INSERT INTO",
+            "JOIN ",
+            "GROUP BY",
+        ],
+    ),
+    (
+        "html",
+        &["html", "vue", "svelte"],
+        &["<div", "</", "<span", "class=\"", "<a href"],
+    ),
+];
+
+fn looks_like_code(line: &str) -> bool {
+    let line = line.trim();
+    line.ends_with(';')
+        || line.ends_with('{')
+        || line == "}"
+        || line.ends_with("):")
+        || line.starts_with("//")
+        || line.starts_with("#include")
+        || line.starts_with("</")
+        || ["=>", "->", "::", "==", "!=", ":=", "&&", "||"]
+            .iter()
+            .any(|op| line.contains(op))
+}
+
+fn language_from_title(window_title: &str) -> Option<&'static str> {
+    window_title
+        .split(|c: char| c.is_whitespace() || "—–|()[]".contains(c))
+        .filter_map(|word| word.rsplit_once('.').map(|(_, ext)| ext))
+        .find_map(|ext| {
+            LANGUAGES
+                .iter()
+                .find(|(_, extensions, _)| extensions.contains(&ext))
+                .map(|(language, _, _)| *language)
+        })
+}
+
+fn language_from_tokens(text: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .map(|(language, _, tokens)| {
+            let hits = tokens.iter().filter(|token| text.contains(*token)).count();
+            (hits, *language)
+        })
+        .filter(|(hits, _)| *hits > 0)
+        // first listed wins ties, e.g. javascript over typescript
+        .fold(None, |best: Option<(usize, &str)>, candidate| match best {
+            Some(best) if best.0 >= candidate.0 => Some(best),
+            _ => Some(candidate),
+        })
+        .map(|(_, language)| language)
+}
+
+/// Guesses whether `text` is source code, and in which language.
+///
+/// `window_title` is an optional hint: editors usually show the file name
+/// (`main.rs — project`) and its extension beats any syntax heuristic.
+///
+/// ```
+/// let detection = get_selected_text::detect_code("fn main() {\n    println!(\"hi\");\n}", None);
+/// assert!(detection.is_code);
+/// assert_eq!(detection.language, Some("rust"));
+/// ```
+pub fn detect_code(text: &str, window_title: Option<&str>) -> CodeDetection {
+    let lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        return CodeDetection {
+            is_code: false,
+            language: None,
+            score: 0.0,
+        };
+    }
+    let code_lines = lines.iter().filter(|line| looks_like_code(line)).count();
+    let score = code_lines as f32 / lines.len() as f32;
+    let title_language = window_title.and_then(language_from_title);
+    let token_language = language_from_tokens(text);
+    // an editor title alone isn't enough, people select comments and docs too
+    let is_code = score >= 0.5 || (score >= 0.25 && (title_language.or(token_language)).is_some());
+    CodeDetection {
+        is_code,
+        language: is_code.then(|| title_language.or(token_language)).flatten(),
+        score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_code() {
+        let prose = "Thanks for the update. I'll take a look tomorrow morning.";
+        assert!(!detect_code(prose, None).is_code);
+
+        let python = "def greet(name):\n    if name == \"\":\n        return None\n    print(name)";
+        let detection = detect_code(python, None);
+        assert!(detection.is_code);
+        assert_eq!(detection.language, Some("python"));
+
+        let snippet = "x := compute(y)\nif err != nil {\n    return err\n}";
+        let detection = detect_code(snippet, Some("handler.go — server — Visual Studio Code"));
+        assert_eq!(detection.language, Some("go"));
+    }
+}
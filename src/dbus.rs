@@ -0,0 +1,105 @@
+//! The selection as a session bus service on Linux, enabled with the `dbus`
+//! feature, for GNOME and KDE applets and shell scripts:
+//!
+//! ```sh
+//! busctl --user call org.getselectedtext.Daemon /org/getselectedtext/Daemon \
+//!     org.getselectedtext.Daemon Get
+//! ```
+//!
+//! `Get()` returns the selection, `Watch(u interval_ms)` makes the service
+//! emit `SelectionChanged(s app_name, s text)` on every change until
+//! `Unwatch()`. The watcher is shared: signals are broadcast, and one
+//! client's `Unwatch` stops them for everyone.
+
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use zbus::blocking::{connection, Connection};
+
+use crate::WatchHandle;
+
+pub const BUS_NAME: &str = "org.getselectedtext.Daemon";
+pub const OBJECT_PATH: &str = "/org/getselectedtext/Daemon";
+pub const INTERFACE: &str = "org.getselectedtext.Daemon";
+
+/// Releases the bus name and stops the watcher when dropped or on
+/// [`shutdown`](crate::shutdown).
+#[derive(Debug)]
+pub struct DbusService {
+    connection: Connection,
+    _running: crate::lifecycle::Running,
+}
+
+impl DbusService {
+    /// The connection serving the name, e.g. to serve more interfaces on it.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+/// Claims [`BUS_NAME`] on the session bus and serves the selection at
+/// [`OBJECT_PATH`], until the returned service is dropped. Fails when
+/// another process holds the name.
+pub fn serve_dbus() -> Result<DbusService, Box<dyn std::error::Error>> {
+    let connection = connection::Builder::session()?
+        .serve_at(OBJECT_PATH, Daemon::default())?
+        .build()?;
+    connection.request_name_with_flags(BUS_NAME, zbus::fdo::RequestNameFlags::DoNotQueue.into())?;
+    let running = {
+        let connection = connection.clone();
+        crate::lifecycle::Running::new(move || {
+            let _ = connection.release_name(BUS_NAME);
+            let _ = connection.object_server().remove::<Daemon, _>(OBJECT_PATH);
+        })
+    };
+    Ok(DbusService {
+        connection,
+        _running: running,
+    })
+}
+
+#[derive(Default)]
+struct Daemon {
+    watch: Mutex<Option<WatchHandle>>,
+}
+
+#[zbus::interface(name = "org.getselectedtext.Daemon")]
+impl Daemon {
+    #[zbus(out_args("text"))]
+    fn get(&self) -> zbus::fdo::Result<String> {
+        crate::get_selected_text().map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+    }
+
+    fn watch(
+        &self,
+        interval_ms: u32,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> zbus::fdo::Result<()> {
+        let connection = Connection::from(connection.clone());
+        let handle =
+            crate::watch_selection(Duration::from_millis(interval_ms.into()), move |event| {
+                let _ = connection.emit_signal(
+                    None::<&str>,
+                    OBJECT_PATH,
+                    INTERFACE,
+                    "SelectionChanged",
+                    &(event.app_name, event.text),
+                );
+            })
+            .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
+        *self.watch.lock() = Some(handle);
+        Ok(())
+    }
+
+    fn unwatch(&self) {
+        *self.watch.lock() = None;
+    }
+
+    /// Only declared for introspection, the watcher emits it.
+    #[zbus(signal)]
+    async fn selection_changed(
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+        app_name: &str,
+        text: &str,
+    ) -> zbus::Result<()>;
+}
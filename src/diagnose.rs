@@ -0,0 +1,150 @@
+//! What the session supports and which method captures go through, for
+//! answering "why does it work on sway but not on GNOME".
+
+/// How [`get_selected_text`](crate::get_selected_text) captures in this
+/// session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaptureMethod {
+    /// The accessibility API (AX on macOS, UI Automation on Windows), with a
+    /// copy through the clipboard as fallback.
+    Accessibility,
+    /// The primary selection, read through Wayland data-control.
+    DataControl,
+    /// The primary selection, read by the companion GNOME Shell extension.
+    GnomeShellExtension,
+    /// ctrl+c and the clipboard through the RemoteDesktop and Clipboard
+    /// portals.
+    Portal,
+    /// ctrl+c sent through the Wayland virtual-keyboard protocol.
+    VirtualKeyboard,
+    /// ctrl+c sent from a /dev/uinput keyboard device.
+    Uinput,
+    /// ctrl+c sent through XTest, which on Wayland only reaches XWayland apps.
+    #[serde(rename = "xtest")]
+    XTest,
+    Unsupported,
+}
+
+/// A protocol or service captures can use.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Capability {
+    /// e.g. `data-control`, `virtual-keyboard`, `at-spi`.
+    pub name: String,
+    pub available: bool,
+}
+
+/// Returned by [`diagnose`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostics {
+    /// As in `std::env::consts::OS`.
+    pub os: String,
+    /// `wayland` or `x11` on Linux.
+    pub session_type: Option<String>,
+    /// `XDG_CURRENT_DESKTOP` on Linux, e.g. `GNOME` or `sway`.
+    pub desktop: Option<String>,
+    /// Inside a Flatpak or Snap sandbox.
+    pub sandboxed: bool,
+    pub capabilities: Vec<Capability>,
+    pub capture_method: CaptureMethod,
+    /// What to change for captures to work in every app.
+    pub hints: Vec<String>,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(capture_method: CaptureMethod) -> Self {
+        Self {
+            os: std::env::consts::OS.to_string(),
+            session_type: None,
+            desktop: None,
+            sandboxed: false,
+            capabilities: Vec::new(),
+            capture_method,
+            hints: Vec::new(),
+        }
+    }
+
+    #[cfg_attr(not(any(target_os = "linux", target_os = "macos")), allow(dead_code))]
+    pub(crate) fn capability(&mut self, name: &str, available: bool) -> bool {
+        self.capabilities.push(Capability {
+            name: name.to_string(),
+            available,
+        });
+        available
+    }
+}
+
+/// Probes the session: desktop, available protocols, the capture method they
+/// lead to, and hints when that method can't reach every app. Meant to be
+/// attached to bug reports.
+///
+/// ```no_run
+/// let diagnostics = get_selected_text::diagnose();
+/// println!("{:?} {:?}", diagnostics.desktop, diagnostics.capture_method);
+/// for hint in &diagnostics.hints {
+///     println!("{hint}");
+/// }
+/// ```
+pub fn diagnose() -> Diagnostics {
+    #[cfg(target_os = "linux")]
+    return crate::linux::diagnose();
+    #[cfg(target_os = "macos")]
+    return crate::macos::diagnose();
+    #[cfg(target_os = "windows")]
+    return Diagnostics::new(CaptureMethod::Accessibility);
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    return Diagnostics::new(CaptureMethod::Unsupported);
+}
+
+/// The text [`self_test`] selects and expects every method to read back.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const SELF_TEST_TEXT: &str = "get-selected-text self-test";
+
+/// How one strategy did in [`self_test`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SelfTestResult {
+    pub strategy: crate::Strategy,
+    pub passed: bool,
+    /// Why it didn't pass.
+    pub error: Option<String>,
+}
+
+impl SelfTestResult {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn new(
+        strategy: crate::Strategy,
+        read: Result<String, Box<dyn std::error::Error>>,
+    ) -> Self {
+        let error = match read {
+            Ok(text) if text.trim() == SELF_TEST_TEXT => None,
+            Ok(text) => Some(format!("Read {text:?} instead of the test text")),
+            Err(e) => Some(e.to_string()),
+        };
+        Self {
+            strategy,
+            passed: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// Checks that the capture methods actually work in this session, e.g. in
+/// an onboarding flow right after the user granted the permissions: selects
+/// a known text in a new TextEdit document, reads it with each method and
+/// closes the document again, focusing back the app that was in front.
+///
+/// Only supported on macOS, where it needs the Automation permission for
+/// TextEdit and System Events on top of Accessibility access. Fails when
+/// the test document couldn't be set up.
+///
+/// ```no_run
+/// for result in get_selected_text::self_test().unwrap() {
+///     println!("{:?}: {}", result.strategy, if result.passed { "ok" } else { "failed" });
+/// }
+/// ```
+pub fn self_test() -> Result<Vec<SelfTestResult>, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    return crate::macos::self_test();
+    #[cfg(not(target_os = "macos"))]
+    return Err(crate::unsupported_error());
+}
@@ -0,0 +1,233 @@
+//! Typed entities (links, email addresses, phone numbers) found in a
+//! selection, for "user selected a phone number → offer to call it" actions.
+
+/// What an [`Entity`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum EntityKind {
+    Url,
+    Email,
+    PhoneNumber,
+}
+
+/// An entity found by [`extract_entities`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Entity {
+    pub kind: EntityKind,
+    /// Byte range of the match in the searched text.
+    pub range: std::ops::Range<usize>,
+    /// The matched text, as it appears.
+    pub text: String,
+    /// Ready to act upon: URLs get a scheme (`www.rust-lang.org` becomes
+    /// `https://www.rust-lang.org`) and phone numbers keep only their digits
+    /// and leading `+`.
+    pub value: String,
+}
+
+const URL_PREFIXES: &[&str] = &[
+    "https://", "http://", "ftp://", "file://", "mailto:", "www.",
+];
+
+// punctuation the sentence puts around an entity rather than part of it
+const LEADING: &[char] = &['(', '[', '{', '<', '"', '\'', '«', '“', '‘'];
+const TRAILING: &[char] = &[
+    '.', ',', ';', ':', '!', '?', ')', ']', '}', '>', '"', '\'', '»', '”', '’',
+];
+
+/// Whitespace-separated words of `text` with their byte offsets, surrounding
+/// punctuation trimmed.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_whitespace().filter_map(move |word| {
+        let offset = word.as_ptr() as usize - text.as_ptr() as usize;
+        let leading = word.len() - word.trim_start_matches(LEADING).len();
+        let word = &word[leading..];
+        let mut end = word.trim_end_matches(TRAILING).len();
+        // keep a closing paren that belongs to the word, like in Wikipedia URLs
+        if word[end..].starts_with(')')
+            && word[..end].matches('(').count() > word[..end].matches(')').count()
+        {
+            end += 1;
+        }
+        (end > 0).then(|| (offset + leading, &word[..end]))
+    })
+}
+
+fn is_domain(domain: &str) -> bool {
+    let labels = domain.split('.').collect::<Vec<_>>();
+    labels.len() > 1
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+        && labels
+            .last()
+            .is_some_and(|tld| tld.chars().count() >= 2 && tld.chars().all(char::is_alphabetic))
+}
+
+fn url_value(word: &str) -> Option<String> {
+    let lower = word.to_ascii_lowercase();
+    let prefix = URL_PREFIXES
+        .iter()
+        .find(|prefix| lower.starts_with(*prefix))?;
+    let rest = &word[prefix.len()..];
+    if rest.is_empty() || (*prefix == "mailto:" && !is_email(rest)) {
+        return None;
+    }
+    if *prefix == "www." {
+        let host = rest.split(['/', '?', '#', ':']).next().unwrap_or_default();
+        return is_domain(host).then(|| format!("https://{word}"));
+    }
+    Some(word.to_string())
+}
+
+fn is_email(word: &str) -> bool {
+    let Some((local, domain)) = word.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && local
+            .chars()
+            .all(|c| c.is_alphanumeric() || "._%+-".contains(c))
+        && is_domain(domain)
+}
+
+/// Phone numbers span several words ("+1 (555) 010-9999"), so they're found
+/// on the whole text: runs of digits and separators with 7 to 15 digits
+/// (E.164's maximum). Short runs need a `+` or the `555-0199` shape so dates,
+/// prices and version numbers don't qualify.
+fn phone_numbers(text: &str) -> Vec<(std::ops::Range<usize>, String)> {
+    let mut numbers = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    let mut previous = None;
+    while let Some((start, ch)) = chars.next() {
+        let at_word_start = !previous.is_some_and(|c: char| c.is_alphanumeric() || c == '.');
+        previous = Some(ch);
+        if !at_word_start || !(ch == '+' || ch == '(' || ch.is_ascii_digit()) {
+            continue;
+        }
+        let mut end = start + ch.len_utf8();
+        while let Some(&(index, next)) = chars.peek() {
+            if !(next.is_ascii_digit() || " -.()".contains(next)) {
+                break;
+            }
+            // a single space between groups, not the gap between two numbers
+            if next == ' ' && text[index + 1..].starts_with([' ', '\n']) {
+                break;
+            }
+            end = index + next.len_utf8();
+            previous = Some(next);
+            chars.next();
+        }
+        let candidate = text[start..end].trim_end_matches([' ', '-', '.', '(']);
+        let candidate = if candidate.ends_with(')') && !candidate.contains('(') {
+            &candidate[..candidate.len() - 1]
+        } else {
+            candidate
+        };
+        let followed_by_word = text[start + candidate.len()..]
+            .chars()
+            .next()
+            .is_some_and(char::is_alphanumeric);
+        let digits = candidate.chars().filter(char::is_ascii_digit).count();
+        let dots = candidate.matches('.').count();
+        let local_shape = candidate.len() == 8 && candidate.as_bytes()[3] == b'-' && digits == 7;
+        let plausible = (7..=15).contains(&digits)
+            && dots <= 2
+            && (digits >= 10 || candidate.starts_with('+') || local_shape);
+        if plausible && !followed_by_word {
+            let value = candidate
+                .chars()
+                .enumerate()
+                .filter(|(i, c)| c.is_ascii_digit() || (*i == 0 && *c == '+'))
+                .map(|(_, c)| c)
+                .collect();
+            numbers.push((start..start + candidate.len(), value));
+        }
+    }
+    numbers
+}
+
+/// Finds the URLs, email addresses and phone numbers in `text`, in order of
+/// appearance. Heuristic and locale-agnostic: it favors missing an odd format
+/// over flagging dates or version numbers.
+///
+/// ```
+/// use get_selected_text::{extract_entities, EntityKind};
+///
+/// let entities = extract_entities("Mail ada@example.com or call +44 20 7946 0958.");
+/// assert_eq!(entities[0].kind, EntityKind::Email);
+/// assert_eq!(entities[1].kind, EntityKind::PhoneNumber);
+/// assert_eq!(entities[1].value, "+442079460958");
+/// ```
+pub fn extract_entities(text: &str) -> Vec<Entity> {
+    let mut entities = Vec::new();
+    for (start, word) in words(text) {
+        let (kind, value) = if let Some(url) = url_value(word) {
+            (EntityKind::Url, url)
+        } else if is_email(word) {
+            (EntityKind::Email, word.to_string())
+        } else {
+            continue;
+        };
+        entities.push(Entity {
+            kind,
+            range: start..start + word.len(),
+            text: word.to_string(),
+            value,
+        });
+    }
+    for (range, value) in phone_numbers(text) {
+        // digits inside a URL (query strings, paths) aren't phone numbers
+        if entities
+            .iter()
+            .any(|entity| entity.range.start < range.end && range.start < entity.range.end)
+        {
+            continue;
+        }
+        entities.push(Entity {
+            kind: EntityKind::PhoneNumber,
+            text: text[range.clone()].to_string(),
+            range,
+            value,
+        });
+    }
+    entities.sort_by_key(|entity| entity.range.start);
+    entities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_entities() {
+        let text = "Docs at (https://en.wikipedia.org/wiki/Rust_(programming_language)), \
+                    see www.rust-lang.org. Call (555) 010-9999 or 555-0199 before 2024-01-15, \
+                    version 1.2.3, costs 1,299.";
+        let entities = extract_entities(text);
+        let found = entities
+            .iter()
+            .map(|entity| (entity.kind, entity.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    EntityKind::Url,
+                    "https://en.wikipedia.org/wiki/Rust_(programming_language)"
+                ),
+                (EntityKind::Url, "https://www.rust-lang.org"),
+                (EntityKind::PhoneNumber, "5550109999"),
+                (EntityKind::PhoneNumber, "5550199"),
+            ]
+        );
+        for entity in &entities {
+            assert_eq!(&text[entity.range.clone()], entity.text);
+        }
+
+        assert!(extract_entities("not@an-email and @handle").is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+use std::error::Error;
+
+/// Errors raised by the capture backends themselves. They come boxed in the
+/// `Box<dyn Error>` every function returns: downcast to match on them, the
+/// underlying `AXError` or `io::Error` stays reachable through
+/// [`Error::source`].
+///
+/// ```no_run
+/// match get_selected_text::get_selected_text() {
+///     Ok(_) => {}
+///     Err(e) => match e.downcast_ref::<get_selected_text::GetTextError>() {
+///         Some(get_selected_text::GetTextError::AppleScript { stderr, .. }) => {
+///             eprintln!("osascript: {stderr}")
+///         }
+///         _ => eprintln!("{e}"),
+///     },
+/// }
+/// ```
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum GetTextError {
+    /// Reading an accessibility attribute failed.
+    #[error("Reading {attribute} failed")]
+    Accessibility {
+        /// e.g. `AXFocusedUIElement`.
+        attribute: &'static str,
+        #[source]
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// A helper program couldn't be started.
+    #[error("Couldn't run {program}")]
+    Spawn {
+        program: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+    /// `osascript` exited unsuccessfully, `kind` tells what went wrong.
+    #[error("AppleScript failed ({status}): {stderr}")]
+    AppleScript {
+        status: std::process::ExitStatus,
+        stderr: String,
+        kind: AppleScriptErrorKind,
+        /// The AppleScript error number, e.g. `-1743`.
+        code: Option<i32>,
+    },
+    /// Capturing failed, `source` is the last method's error.
+    #[error("Capturing failed after trying {}", attempts.iter().map(|a| a.method).collect::<Vec<_>>().join(", "))]
+    Failed {
+        attempts: Vec<crate::Attempt>,
+        #[source]
+        source: Box<dyn Error>,
+    },
+    /// The focus is on a control (a button, a slider) that can't hold a
+    /// selection, so the copy keystroke wasn't sent: apps give cmd+c their
+    /// own meaning on some of them.
+    #[error("Nothing is selected, the focused element is an {role}")]
+    NoSelection { role: String },
+    /// A modal dialog or a sheet is in front, where a keystroke could press
+    /// one of its buttons, so none was sent.
+    #[error("A dialog is in front, no keys were pressed")]
+    DialogInFront,
+    /// The focused app is a terminal emulator and nothing read its selection
+    /// without ctrl+c, which would interrupt the program running in it.
+    #[error("{app} is a terminal, copying with ctrl+c would interrupt it")]
+    Terminal { app: String },
+    /// Another capture or paste is going through the clipboard right now.
+    /// Nothing was done, try again once it returns.
+    #[error("Another capture is using the clipboard")]
+    Busy,
+    /// The redactor withheld the capture, see the `redact` module.
+    #[error("The selection was withheld by the redaction rules")]
+    Redacted,
+    /// A helper program printed something that isn't UTF-8.
+    #[error("{program} printed invalid UTF-8")]
+    InvalidOutput {
+        program: &'static str,
+        #[source]
+        source: std::string::FromUtf8Error,
+    },
+}
+
+impl GetTextError {
+    /// Whether it's about the moment (the focus, another capture) rather than
+    /// the app, which may well be readable a moment later.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    pub(crate) fn is_momentary(&self) -> bool {
+        match self {
+            Self::NoSelection { .. } | Self::DialogInFront | Self::Terminal { .. } | Self::Busy => {
+                true
+            }
+            Self::AppleScript { kind, .. } => kind.is_transient(),
+            _ => false,
+        }
+    }
+
+    /// Whether a permission is missing (Accessibility, Automation), which
+    /// the user may grant any moment.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    pub(crate) fn is_permission(&self) -> bool {
+        match self {
+            Self::AppleScript { kind, .. } => matches!(
+                kind,
+                AppleScriptErrorKind::NotAuthorized | AppleScriptErrorKind::NoAccessibility
+            ),
+            #[cfg(target_os = "macos")]
+            Self::Accessibility { .. } => {
+                !macos_accessibility_client::accessibility::application_is_trusted()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Why `osascript` failed, from the error number it printed, see
+/// [`GetTextError::AppleScript`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum AppleScriptErrorKind {
+    /// The app has no Automation permission for System Events (or the app
+    /// scripted): Privacy & Security > Automation (-1743).
+    NotAuthorized,
+    /// The app has no Accessibility access, keystrokes can't be sent
+    /// (-1719, 1002).
+    NoAccessibility,
+    /// The user turned down a prompt (-128).
+    UserCanceled,
+    /// An app didn't answer in time, e.g. while busy (-1712).
+    Timeout,
+    /// System Events (or the app scripted) isn't running or quit meanwhile
+    /// (-600, -609).
+    NotRunning,
+    Other,
+}
+
+impl AppleScriptErrorKind {
+    /// Classifies `osascript`'s stderr, e.g. `0:45: execution error: Not
+    /// authorized to send Apple events to System Events. (-1743)`, also
+    /// returning the error number.
+    pub fn from_stderr(stderr: &str) -> (Self, Option<i32>) {
+        let code = stderr
+            .trim_end()
+            .strip_suffix(')')
+            .and_then(|rest| rest.rsplit_once('('))
+            .and_then(|(_, code)| code.parse().ok());
+        let kind = match code {
+            Some(-1743) => Self::NotAuthorized,
+            Some(-1719 | 1002) => Self::NoAccessibility,
+            Some(-128) => Self::UserCanceled,
+            Some(-1712) => Self::Timeout,
+            Some(-600 | -609) => Self::NotRunning,
+            _ => Self::Other,
+        };
+        (kind, code)
+    }
+
+    /// Whether trying again later may work without the user doing anything.
+    pub fn is_transient(self) -> bool {
+        matches!(self, Self::Timeout | Self::NotRunning)
+    }
+}
+
+/// `error: source: source's source`, for bindings and UIs that only pass a
+/// message on.
+pub fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apple_script_error_kind() {
+        assert_eq!(
+            AppleScriptErrorKind::from_stderr(
+                "0:245: execution error: Not authorized to send Apple events to System Events. (-1743)\n"
+            ),
+            (AppleScriptErrorKind::NotAuthorized, Some(-1743))
+        );
+        assert_eq!(
+            AppleScriptErrorKind::from_stderr(
+                "execution error: System Events got an error: osascript is not allowed to send keystrokes. (1002)"
+            ),
+            (AppleScriptErrorKind::NoAccessibility, Some(1002))
+        );
+        assert_eq!(
+            AppleScriptErrorKind::from_stderr("execution error: AppleEvent timed out. (-1712)"),
+            (AppleScriptErrorKind::Timeout, Some(-1712))
+        );
+        assert_eq!(
+            AppleScriptErrorKind::from_stderr("syntax error: Expected end of line (see (1))"),
+            (AppleScriptErrorKind::Other, None)
+        );
+    }
+
+    #[test]
+    fn test_error_chain() {
+        let error = GetTextError::Spawn {
+            program: "osascript",
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "No such file"),
+        };
+        assert_eq!(error_chain(&error), "Couldn't run osascript: No such file");
+    }
+}
@@ -0,0 +1,144 @@
+//! Growing the selection to the sentence or paragraph around it, so grammar
+//! tools can work on whole sentences when a fragment was selected.
+
+use std::ops::Range;
+
+use crate::ExpandedSelection;
+
+/// UTF-16 units read on each side of the selection at first, four times as
+/// many each time the sentence or paragraph runs past them.
+const CONTEXT: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TextUnit {
+    Sentence,
+    Paragraph,
+}
+
+/// Converts a UTF-16 offset into `text` to a byte offset.
+pub(crate) fn utf16_to_byte_offset(text: &str, offset: usize) -> usize {
+    let mut units = 0;
+    for (index, ch) in text.char_indices() {
+        if units >= offset {
+            return index;
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
+
+/// `selection`, UTF-16 offsets into a text `len` units long as the
+/// accessibility APIs count, grown to the units `segment` finds around its
+/// ends. `read` returns a range of the text, `segment` the range of the unit
+/// at an offset into what was read, both in UTF-16 units.
+pub(crate) fn expand(
+    selection: Range<usize>,
+    len: usize,
+    mut read: impl FnMut(Range<usize>) -> Result<String, Box<dyn std::error::Error>>,
+    segment: impl Fn(&str, usize) -> Option<Range<usize>>,
+) -> Result<ExpandedSelection, Box<dyn std::error::Error>> {
+    let selection = selection.start.min(len)..selection.end.clamp(selection.start, len);
+    let mut context = CONTEXT;
+    loop {
+        let start = selection.start.saturating_sub(context);
+        let end = selection.end.saturating_add(context).min(len);
+        let text = read(start..end)?;
+        let read_len: usize = text.chars().map(char::len_utf16).sum();
+        if read_len == 0 {
+            return Ok(ExpandedSelection::default());
+        }
+        let first = selection.start - start;
+        let last = (selection.end - start).max(first + 1) - 1;
+        let unit_start =
+            segment(&text, first.min(read_len - 1)).map_or(first, |unit| unit.start.min(first));
+        let unit_end = segment(&text, last.min(read_len - 1))
+            .map_or(selection.end - start, |unit| {
+                unit.end.max(selection.end - start)
+            });
+
+        // a unit reaching the edge of what was read may go on past it
+        let cut_off = (unit_start == 0 && start > 0) || (unit_end >= read_len && end < len);
+        if cut_off {
+            context = context.saturating_mul(4);
+            continue;
+        }
+
+        let bytes = utf16_to_byte_offset(&text, unit_start)..utf16_to_byte_offset(&text, unit_end);
+        let selected =
+            utf16_to_byte_offset(&text, first)..utf16_to_byte_offset(&text, selection.end - start);
+        let unit = &text[bytes.clone()];
+        let selection = selected.start - bytes.start..selected.end - bytes.start;
+        // trailing spaces and line breaks, unless selected
+        let text_end = unit.trim_end().len().max(selection.end);
+        return Ok(ExpandedSelection {
+            text: unit[..text_end].to_string(),
+            selection,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sentences end after ". ", paragraphs after a line break.
+    fn naive_segment(text: &str, offset: usize, unit: TextUnit) -> Option<Range<usize>> {
+        let units: Vec<u16> = text.encode_utf16().collect();
+        let ends_unit = |i: usize| match unit {
+            TextUnit::Sentence => {
+                units[i] == u16::from(b' ') && i > 0 && units[i - 1] == u16::from(b'.')
+            }
+            TextUnit::Paragraph => units[i] == u16::from(b'\n'),
+        };
+        let start = (0..offset)
+            .rev()
+            .find(|&i| ends_unit(i))
+            .map_or(0, |i| i + 1);
+        let end = (offset..units.len())
+            .find(|&i| ends_unit(i))
+            .map_or(units.len(), |i| i + 1);
+        Some(start..end)
+    }
+
+    fn expand_in(text: &str, selected: &str, unit: TextUnit) -> (ExpandedSelection, usize) {
+        let units: Vec<u16> = text.encode_utf16().collect();
+        let start = text[..text.find(selected).unwrap()].encode_utf16().count();
+        let selection = start..start + selected.encode_utf16().count();
+        let mut reads = 0;
+        let expanded = expand(
+            selection,
+            units.len(),
+            |range| {
+                reads += 1;
+                Ok(String::from_utf16(&units[range]).unwrap())
+            },
+            |text, offset| naive_segment(text, offset, unit),
+        )
+        .unwrap();
+        (expanded, reads)
+    }
+
+    #[test]
+    fn test_expand_sentence() {
+        let text = "Première phrase. The quick brown fox jumps. Über den Hund.";
+        let (expanded, reads) = expand_in(text, "brown", TextUnit::Sentence);
+        assert_eq!(expanded.text, "The quick brown fox jumps.");
+        assert_eq!(&expanded.text[expanded.selection.clone()], "brown");
+        assert_eq!(reads, 1);
+
+        // a selection across sentences gets both
+        let (expanded, _) = expand_in(text, "jumps. Über", TextUnit::Sentence);
+        assert_eq!(expanded.text, "The quick brown fox jumps. Über den Hund.");
+        assert_eq!(&expanded.text[expanded.selection], "jumps. Über");
+    }
+
+    #[test]
+    fn test_expand_grows_context() {
+        let long = "word ".repeat(500);
+        let text = format!("Intro.\n{long}🦊 middle {long}end.\nOutro.");
+        let (expanded, reads) = expand_in(&text, "middle", TextUnit::Paragraph);
+        assert_eq!(expanded.text, format!("{long}🦊 middle {long}end."));
+        assert_eq!(&expanded.text[expanded.selection], "middle");
+        assert_eq!(reads, 2);
+    }
+}
@@ -0,0 +1,186 @@
+//! C ABI for consumers that can't link Rust directly (Swift, C++, Objective-C).
+//!
+//! The header lives in `include/get_selected_text.h` and is generated with
+//! `cbindgen --config cbindgen.toml --output include/get_selected_text.h`.
+//! Build the library with `cargo rustc --release --lib --features ffi
+//! --crate-type staticlib` (or `cdylib`), so plain Rust dependents don't build
+//! either.
+
+use std::ffi::{c_char, CString};
+use std::ptr;
+
+/// Result of [`gst_get_selected_text`]. Must be released with
+/// [`gst_free_result`].
+#[repr(C)]
+pub struct GstResult {
+    /// `true` when the capture succeeded; `error` is null in that case.
+    pub ok: bool,
+    /// `true` when `text` contains file paths (Finder selections on macOS).
+    pub is_file_paths: bool,
+    /// Name of the frontmost application, may be an empty string.
+    pub app_name: *mut c_char,
+    /// Bundle identifier of the frontmost application, null when unknown.
+    pub bundle_id: *mut c_char,
+    /// Process id of the frontmost application, `-1` when unknown.
+    pub process_id: i32,
+    /// Short version string of the frontmost application, null when unknown.
+    pub app_version: *mut c_char,
+    /// Window id of the frontmost window, `0` when unknown.
+    pub window_id: u32,
+    /// Array of `text_len` NUL-terminated UTF-8 strings.
+    pub text: *mut *mut c_char,
+    pub text_len: usize,
+    /// Error message when `ok` is `false`, null otherwise.
+    pub error: *mut c_char,
+}
+
+fn to_c_string(s: &str) -> *mut c_char {
+    // interior NULs can't be represented, drop them rather than failing the whole capture
+    CString::new(s.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+fn to_c_string_opt(s: Option<&str>) -> *mut c_char {
+    s.map_or(ptr::null_mut(), to_c_string)
+}
+
+fn to_c_strings(items: Vec<String>) -> (*mut *mut c_char, usize) {
+    let items = items
+        .iter()
+        .map(|item| to_c_string(item))
+        .collect::<Vec<_>>()
+        .into_boxed_slice();
+    let len = items.len();
+    (Box::into_raw(items) as *mut *mut c_char, len)
+}
+
+/// `"The capture panicked: <message>"`.
+fn panicked(panic: Box<dyn std::any::Any + Send>) -> Box<dyn std::error::Error> {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown");
+    format!("The capture panicked: {message}").into()
+}
+
+/// Captures the current selection. A panic, which mustn't unwind into the
+/// caller, comes back as an error result.
+///
+/// Never returns null; release the result with [`gst_free_result`].
+#[no_mangle]
+pub extern "C" fn gst_get_selected_text() -> *mut GstResult {
+    let selected = std::panic::catch_unwind(crate::get_selected_text)
+        .unwrap_or_else(|panic| Err(panicked(panic)));
+    let result = match selected {
+        #[cfg(target_os = "macos")]
+        Ok(selected) => {
+            let (text, text_len) = to_c_strings(selected.text);
+            GstResult {
+                ok: true,
+                is_file_paths: selected.is_file_paths,
+                app_name: to_c_string(&selected.app_name),
+                bundle_id: to_c_string_opt(selected.bundle_id.as_deref()),
+                process_id: selected.process_id.unwrap_or(-1),
+                app_version: to_c_string_opt(selected.app_version.as_deref()),
+                window_id: selected.window_id.unwrap_or(0),
+                text,
+                text_len,
+                error: ptr::null_mut(),
+            }
+        }
+        #[cfg(not(target_os = "macos"))]
+        Ok(selected) => {
+            let (text, text_len) = to_c_strings(vec![selected]);
+            GstResult {
+                ok: true,
+                is_file_paths: false,
+                app_name: to_c_string(&crate::active_app_name()),
+                bundle_id: to_c_string_opt(None),
+                process_id: -1,
+                app_version: to_c_string_opt(None),
+                window_id: 0,
+                text,
+                text_len,
+                error: ptr::null_mut(),
+            }
+        }
+        Err(e) => GstResult {
+            ok: false,
+            is_file_paths: false,
+            app_name: ptr::null_mut(),
+            bundle_id: ptr::null_mut(),
+            process_id: -1,
+            app_version: ptr::null_mut(),
+            window_id: 0,
+            text: ptr::null_mut(),
+            text_len: 0,
+            error: to_c_string(&crate::error::error_chain(&*e)),
+        },
+    };
+    Box::into_raw(Box::new(result))
+}
+
+/// Releases a result returned by [`gst_get_selected_text`]. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `result` must come from [`gst_get_selected_text`] and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn gst_free_result(result: *mut GstResult) {
+    if result.is_null() {
+        return;
+    }
+    let result = Box::from_raw(result);
+    free_c_string(result.app_name);
+    free_c_string(result.bundle_id);
+    free_c_string(result.app_version);
+    free_c_string(result.error);
+    if !result.text.is_null() {
+        let items = Box::from_raw(ptr::slice_from_raw_parts_mut(result.text, result.text_len));
+        for item in items.iter() {
+            free_c_string(*item);
+        }
+    }
+}
+
+unsafe fn free_c_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    #[test]
+    fn test_gst_get_selected_text() {
+        let _mock = crate::mock::lock();
+        crate::mock::set_selection("hello");
+        crate::mock::set_app_name("Notes");
+        crate::mock::fail_next("no access");
+
+        let result = gst_get_selected_text();
+        unsafe {
+            assert!(!(*result).ok);
+            assert!((*result).text.is_null());
+            assert_eq!(CStr::from_ptr((*result).error).to_str(), Ok("no access"));
+            gst_free_result(result);
+        }
+
+        let result = gst_get_selected_text();
+        unsafe {
+            assert!((*result).ok && (*result).error.is_null());
+            assert_eq!(CStr::from_ptr((*result).app_name).to_str(), Ok("Notes"));
+            assert_eq!((*result).text_len, 1);
+            assert_eq!(CStr::from_ptr(*(*result).text).to_str(), Ok("hello"));
+            gst_free_result(result);
+            gst_free_result(ptr::null_mut());
+        }
+        crate::mock::reset();
+    }
+}
@@ -0,0 +1,97 @@
+/// Splits the Finder script's output: one percent-encoded POSIX path per
+/// line, so names holding spaces, quotes or newlines come through intact.
+pub(crate) fn parse_file_paths(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(percent_decode)
+        .collect()
+}
+
+/// Decodes `%XX` escapes, leaving malformed ones as they are. Bytes that
+/// don't decode to UTF-8 become U+FFFD.
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if let (b'%', Some(&[high, low])) = (bytes[i], bytes.get(i + 1..i + 3)) {
+            if let (Some(high), Some(low)) = (hex_digit(high), hex_digit(low)) {
+                decoded.push(high << 4 | low);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the script does with `URLPathAllowedCharacterSet`.
+    fn percent_encode(path: &str) -> String {
+        path.bytes()
+            .map(|byte| match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{byte:02X}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_file_paths() {
+        assert_eq!(
+            parse_file_paths("/Users/me/It%27s%20%22here%22.txt\n/tmp/two%0Alines\n"),
+            vec!["/Users/me/It's \"here\".txt", "/tmp/two\nlines"]
+        );
+        assert_eq!(parse_file_paths("/tmp/caf%C3%A9"), vec!["/tmp/café"]);
+        assert_eq!(
+            parse_file_paths("/tmp/%FF%zz%4"),
+            vec!["/tmp/\u{FFFD}%zz%4"]
+        );
+        // legal on macOS: colons (slashes in Finder), trailing spaces, NFD
+        assert_eq!(
+            parse_file_paths("/tmp/a%3Ab%20\n/tmp/e%CC%81"),
+            vec!["/tmp/a:b ", "/tmp/e\u{301}"]
+        );
+        assert!(parse_file_paths("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_file_paths_round_trips() {
+        let alphabet: Vec<char> = "ab /'\"\n\r\t%\\:;,é😀\u{0}".chars().collect();
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..500 {
+            let paths: Vec<String> = (0..next() % 4 + 1)
+                .map(|_| {
+                    let name: String = (0..next() % 12 + 1)
+                        .map(|_| alphabet[next() as usize % alphabet.len()])
+                        .collect();
+                    format!("/tmp/{name}")
+                })
+                .collect();
+            let output = paths
+                .iter()
+                .map(|path| percent_encode(path))
+                .collect::<Vec<_>>()
+                .join("\n");
+            assert_eq!(parse_file_paths(&output), paths);
+        }
+    }
+}
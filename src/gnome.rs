@@ -0,0 +1,53 @@
+//! Client of the companion GNOME Shell extension (`gnome-extension/`), the
+//! only way to read the selection on GNOME Wayland, which has neither
+//! data-control nor a virtual keyboard.
+
+use std::collections::HashMap;
+
+use zbus::blocking::Connection;
+use zbus::zvariant::OwnedValue;
+
+const EXTENSION_UUID: &str = "get-selected-text@cs50victor.github.io";
+
+const SHELL: &str = "org.gnome.Shell";
+const OBJECT_PATH: &str = "/io/github/cs50victor/GetSelectedText";
+const INTERFACE: &str = "io.github.cs50victor.GetSelectedText";
+
+// `ExtensionState.ACTIVE` (`ENABLED` before GNOME 45)
+const EXTENSION_ACTIVE: f64 = 1.0;
+
+/// Whether the extension is installed and enabled, asked from the Shell.
+pub(crate) fn extension_enabled() -> bool {
+    if !crate::linux::is_desktop("GNOME") {
+        return false;
+    }
+    let state = || -> Result<f64, Box<dyn std::error::Error>> {
+        let reply = Connection::session()?.call_method(
+            Some(SHELL),
+            "/org/gnome/Shell",
+            Some("org.gnome.Shell.Extensions"),
+            "GetExtensionInfo",
+            &(EXTENSION_UUID,),
+        )?;
+        let info: HashMap<String, OwnedValue> = reply.body().deserialize()?;
+        // empty for extensions that aren't installed
+        Ok(info
+            .get("state")
+            .map(f64::try_from)
+            .transpose()?
+            .unwrap_or_default())
+    };
+    state().is_ok_and(|state| state == EXTENSION_ACTIVE)
+}
+
+/// The primary selection, as read by the Shell.
+pub(crate) fn get_selected_text() -> Result<String, Box<dyn std::error::Error>> {
+    let reply = Connection::session()?.call_method(
+        Some(SHELL),
+        OBJECT_PATH,
+        Some(INTERFACE),
+        "GetSelection",
+        &(),
+    )?;
+    Ok(reply.body().deserialize()?)
+}
@@ -0,0 +1,276 @@
+//! The daemon's gRPC service, enabled with the `grpc` feature, for services
+//! that don't speak Rust. The contract is `proto/get_selected_text.proto`:
+//! `Get` captures once, `Watch` streams selection changes until the call is
+//! cancelled, `Replace` pastes over the selection. Calls carry an
+//! `authorization: Bearer <token>` header.
+//!
+//! ```no_run
+//! let server = get_selected_text::grpc::serve_grpc("127.0.0.1:50051", "s3cret").unwrap();
+//! println!("listening on {}", server.local_addr());
+//! ```
+//!
+//! Apps running a tonic server of their own can add
+//! `DaemonServer::with_interceptor(GrpcDaemon, ...)` to it instead, with
+//! authentication of their own: anything on the machine can reach it.
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::{TcpListenerStream, UnboundedReceiverStream};
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+use crate::WatchHandle;
+
+/// The messages and the client and server generated from the proto.
+pub mod proto {
+    tonic::include_proto!("get_selected_text.v1");
+}
+
+use proto::daemon_server::{Daemon, DaemonServer};
+
+/// Stops the server when dropped or on [`shutdown`](crate::shutdown),
+/// ending the open calls.
+#[derive(Debug)]
+pub struct GrpcServer {
+    local_addr: SocketAddr,
+    _running: crate::lifecycle::Running,
+}
+
+impl GrpcServer {
+    /// Where it listens, with the port picked when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+/// Serves [`GrpcDaemon`] on `addr`, which has to be a loopback address, to
+/// clients sending `token` as a bearer token; the others get
+/// `UNAUTHENTICATED`. Pick a random token and hand it to the client out of
+/// band: any process on the machine can reach the port. Runs on a thread of
+/// its own with its own Tokio runtime.
+pub fn serve_grpc(
+    addr: impl ToSocketAddrs,
+    token: &str,
+) -> Result<GrpcServer, Box<dyn std::error::Error>> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    if addrs.is_empty() || !addrs.iter().all(|addr| addr.ip().is_loopback()) {
+        return Err("The gRPC server only listens on loopback addresses".into());
+    }
+    if token.is_empty() {
+        return Err("The gRPC server needs a token".into());
+    }
+    let authorization = format!("Bearer {token}");
+    let check_token = move |request: Request<()>| {
+        let sent = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        if sent == Some(authorization.as_str()) {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("Wrong or missing token"))
+        }
+    };
+    // bound here, so failing to bind is reported to the caller
+    let listener = std::net::TcpListener::bind(&addrs[..])?;
+    listener.set_nonblocking(true)?;
+    let local_addr = listener.local_addr()?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_io()
+        .build()?;
+    let (shutdown, stopped) = oneshot::channel::<()>();
+    std::thread::spawn(move || {
+        runtime.block_on(async move {
+            let Ok(listener) = tokio::net::TcpListener::from_std(listener) else {
+                return;
+            };
+            let _ = tonic::transport::Server::builder()
+                .add_service(DaemonServer::with_interceptor(GrpcDaemon, check_token))
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                    let _ = stopped.await;
+                })
+                .await;
+        });
+    });
+    Ok(GrpcServer {
+        local_addr,
+        _running: crate::lifecycle::Running::new(move || {
+            let _ = shutdown.send(());
+        }),
+    })
+}
+
+/// The `Daemon` service, capturing on Tokio's blocking threads.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcDaemon;
+
+#[cfg(target_os = "macos")]
+fn to_selection(selected: crate::SelectedText) -> proto::Selection {
+    proto::Selection {
+        text: selected.text,
+        is_file_paths: selected.is_file_paths,
+        app_name: selected.app_name,
+        bundle_id: selected.bundle_id.unwrap_or_default(),
+        process_id: selected.process_id,
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn to_selection(text: String) -> proto::Selection {
+    proto::Selection {
+        text: vec![text],
+        is_file_paths: false,
+        app_name: crate::active_app_name(),
+        bundle_id: String::new(),
+        process_id: None,
+    }
+}
+
+fn to_event(event: crate::SelectionEvent) -> proto::SelectionEvent {
+    proto::SelectionEvent {
+        app_name: event.app_name,
+        text: event.text,
+        mouse_position: event.mouse_position.map(|point| proto::Point {
+            x: point.x,
+            y: point.y,
+        }),
+    }
+}
+
+/// Runs `f` on a blocking thread, its error as the status message.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, Box<dyn std::error::Error>> + Send + 'static,
+) -> Result<T, Status> {
+    tokio::task::spawn_blocking(move || f().map_err(|e| crate::error::error_chain(&*e)))
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(Status::internal)
+}
+
+/// The selection events of a `Watch` call, stopping the watcher when the
+/// client goes away and the stream is dropped.
+#[derive(Debug)]
+pub struct WatchStream {
+    events: UnboundedReceiverStream<Result<proto::SelectionEvent, Status>>,
+    _watch: WatchHandle,
+}
+
+impl Stream for WatchStream {
+    type Item = Result<proto::SelectionEvent, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+#[tonic::async_trait]
+impl Daemon for GrpcDaemon {
+    async fn get(
+        &self,
+        request: Request<proto::GetRequest>,
+    ) -> Result<Response<proto::Selection>, Status> {
+        let options = crate::CaptureOptions::new().ocr(request.into_inner().ocr);
+        let selected = blocking(move || crate::get_selected_text_with_options(&options)).await?;
+        Ok(Response::new(to_selection(selected)))
+    }
+
+    type WatchStream = WatchStream;
+
+    async fn watch(
+        &self,
+        request: Request<proto::WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let interval = match request.into_inner().interval_ms {
+            0 => Duration::from_millis(300),
+            ms => Duration::from_millis(ms.into()),
+        };
+        let (sender, events) = mpsc::unbounded_channel();
+        let watch = crate::watch_selection(interval, move |event| {
+            let _ = sender.send(Ok(to_event(event)));
+        })
+        .map_err(|e| Status::unimplemented(e.to_string()))?;
+        Ok(Response::new(WatchStream {
+            events: UnboundedReceiverStream::new(events),
+            _watch: watch,
+        }))
+    }
+
+    async fn replace(
+        &self,
+        request: Request<proto::ReplaceRequest>,
+    ) -> Result<Response<proto::ReplaceResponse>, Status> {
+        let text = request.into_inner().text;
+        blocking(move || crate::replace_selected_text(&text)).await?;
+        Ok(Response::new(proto::ReplaceResponse {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::daemon_client::DaemonClient;
+
+    #[test]
+    fn test_serve_grpc() {
+        assert!(serve_grpc("0.0.0.0:0", "s3cret").is_err());
+        assert!(serve_grpc("127.0.0.1:0", "").is_err());
+        let server = serve_grpc("127.0.0.1:0", "s3cret").unwrap();
+        #[cfg(feature = "mock")]
+        let _mock = crate::mock::lock();
+        #[cfg(feature = "mock")]
+        crate::mock::set_selection("hello");
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let channel =
+                tonic::transport::Endpoint::from_shared(format!("http://{}", server.local_addr()))
+                    .unwrap()
+                    .connect()
+                    .await
+                    .unwrap();
+
+            let mut stranger = DaemonClient::new(channel.clone());
+            let status = stranger
+                .replace(proto::ReplaceRequest {
+                    text: "pwned".into(),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+            let mut client = DaemonClient::with_interceptor(channel, |mut request: Request<()>| {
+                let token = "Bearer s3cret".parse().unwrap();
+                request.metadata_mut().insert("authorization", token);
+                Ok(request)
+            });
+            let selection = client.get(proto::GetRequest { ocr: false }).await;
+            #[cfg(feature = "mock")]
+            {
+                use tokio_stream::StreamExt;
+
+                assert_eq!(selection.unwrap().into_inner().text, ["hello"]);
+                let mut events = client
+                    .watch(proto::WatchRequest { interval_ms: 0 })
+                    .await
+                    .unwrap()
+                    .into_inner();
+                let event = events.next().await.unwrap().unwrap();
+                assert_eq!(event.text, "hello");
+            }
+            // a failed capture comes back as a status, not a transport error
+            #[cfg(not(feature = "mock"))]
+            if let Err(status) = selection {
+                assert_eq!(status.code(), tonic::Code::Internal);
+            }
+        });
+        #[cfg(feature = "mock")]
+        crate::mock::reset();
+    }
+}
@@ -0,0 +1,652 @@
+//! A local selection history in SQLite, enabled with the `history` feature,
+//! for clipboard-history-style apps, through rusqlite. The system's
+//! `libsqlite3` is linked on macOS and Linux unless the `bundled-sqlite`
+//! feature builds SQLite from source, which Windows always does.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! use get_selected_text::history::{History, HistoryQuery};
+//!
+//! let history = History::open("history.sqlite3")
+//!     .unwrap()
+//!     .max_entries(500)
+//!     .max_age(Duration::from_secs(30 * 24 * 60 * 60));
+//! let history = Arc::new(history);
+//! // every capture that finds a selection from now on
+//! get_selected_text::history::set_capture_history(Some(history.clone()));
+//!
+//! let _ = get_selected_text::get_selected_text();
+//! for entry in history.query(&HistoryQuery::new().contains("fox").limit(10)).unwrap() {
+//!     println!("{} in {}", entry.text, entry.app_name);
+//! }
+//! ```
+//!
+//! With the `history-encryption` feature the text and app names can be
+//! encrypted at rest (ChaCha20-Poly1305), with a key kept in the OS
+//! keystore. Ids and capture times stay readable.
+//!
+//! ```no_run
+//! # #[cfg(feature = "history-encryption")] {
+//! use get_selected_text::history::{EncryptionKey, History};
+//!
+//! let key = EncryptionKey::from_keystore("com.example.clipboard").unwrap();
+//! let history = History::open_encrypted("history.sqlite3", &key).unwrap();
+//! # }
+//! ```
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "history-encryption")]
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+#[cfg(feature = "history-encryption")]
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use parking_lot::Mutex;
+use rusqlite::types::Value;
+#[cfg(feature = "history-encryption")]
+use rusqlite::TransactionBehavior;
+use rusqlite::{params, Connection, OptionalExtension};
+
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    PRAGMA synchronous = NORMAL;
+    CREATE TABLE IF NOT EXISTS selections (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        captured_at INTEGER NOT NULL,
+        app_name TEXT NOT NULL,
+        text TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS selections_captured_at ON selections (captured_at);
+    CREATE TABLE IF NOT EXISTS meta (
+        name TEXT PRIMARY KEY,
+        value BLOB NOT NULL
+    );
+";
+
+/// Sealed with the key of an encrypted history, to tell it's the right one.
+const KEY_CHECK: &str = "key_check";
+
+static CAPTURE_HISTORY: Mutex<Option<Arc<History>>> = Mutex::new(None);
+
+/// Records every capture that finds a selection to `history` from now on,
+/// or stops with `None`. Failing writes are skipped, the capture still
+/// returns.
+pub fn set_capture_history(history: Option<Arc<History>>) {
+    *CAPTURE_HISTORY.lock() = history;
+}
+
+pub(crate) fn record_capture(app_name: &str, text: &str) {
+    let history = CAPTURE_HISTORY.lock().clone();
+    if let Some(history) = history {
+        let _ = history.record(app_name, text);
+    }
+}
+
+/// One recorded selection, file paths one per line.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub text: String,
+    pub app_name: String,
+    pub captured_at: SystemTime,
+}
+
+/// Which entries [`History::query`] returns, newest first.
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    contains: Option<String>,
+    app_name: Option<String>,
+    since: Option<SystemTime>,
+    limit: usize,
+}
+
+impl HistoryQuery {
+    /// The latest 100 entries.
+    pub fn new() -> Self {
+        Self {
+            contains: None,
+            app_name: None,
+            since: None,
+            limit: 100,
+        }
+    }
+
+    /// Entries whose text contains `text`, case-sensitively.
+    pub fn contains(mut self, text: impl Into<String>) -> Self {
+        self.contains = Some(text.into());
+        self
+    }
+
+    pub fn app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.app_name = Some(app_name.into());
+        self
+    }
+
+    pub fn since(mut self, since: SystemTime) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.contains
+            .as_ref()
+            .is_none_or(|contains| entry.text.contains(contains.as_str()))
+            && self
+                .app_name
+                .as_ref()
+                .is_none_or(|app_name| entry.app_name == *app_name)
+    }
+}
+
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A key for [`History::open_encrypted`].
+#[cfg(feature = "history-encryption")]
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+#[cfg(feature = "history-encryption")]
+impl EncryptionKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// A random key, for apps keeping it themselves.
+    pub fn generate() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut key = [0; 32];
+        getrandom::fill(&mut key)?;
+        Ok(Self(key))
+    }
+
+    /// The key kept under `service` (e.g. the app's bundle identifier) in the
+    /// Keychain on macOS, the Secret Service on Linux, or a DPAPI-protected
+    /// file on Windows, made and stored there on first use. The keyring may
+    /// ask the user to unlock it.
+    pub fn from_keystore(service: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        crate::keystore::load_or_create(service).map(Self)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "history-encryption")]
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// A history database. Keeps the latest 1000 entries unless told otherwise,
+/// older ones are deleted as new ones come in.
+pub struct History {
+    db: Mutex<Connection>,
+    #[cfg(feature = "history-encryption")]
+    key: Option<EncryptionKey>,
+    max_entries: usize,
+    max_age: Option<Duration>,
+}
+
+impl std::fmt::Debug for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("History")
+            .field("max_entries", &self.max_entries)
+            .field("max_age", &self.max_age)
+            .field("encrypted", &self.is_encrypted())
+            .finish_non_exhaustive()
+    }
+}
+
+impl History {
+    /// Opens the database at `path`, creating it when missing. Encrypted ones
+    /// are refused.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let db = open_database(path.as_ref())?;
+        if meta(&db, KEY_CHECK)?.is_some() {
+            return Err(format!(
+                "{} is encrypted, open it with History::open_encrypted",
+                path.as_ref().display()
+            )
+            .into());
+        }
+        Ok(Self {
+            db: Mutex::new(db),
+            #[cfg(feature = "history-encryption")]
+            key: None,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_age: None,
+        })
+    }
+
+    /// Opens the database at `path` with its text and app names encrypted
+    /// with `key`, creating it when missing. Entries of a plaintext one are
+    /// encrypted on the first open, one encrypted with another key is
+    /// refused.
+    #[cfg(feature = "history-encryption")]
+    pub fn open_encrypted(
+        path: impl AsRef<Path>,
+        key: &EncryptionKey,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut db = open_database(path.as_ref())?;
+        // so deleted plaintext doesn't linger in free pages
+        db.pragma_update(None, "secure_delete", true)?;
+        if meta(&db, KEY_CHECK)?.is_none() {
+            // rolled back when dropped on an error
+            let transaction = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            encrypt_entries(&transaction, key)?;
+            transaction.commit()?;
+            db.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        }
+        let check = meta(&db, KEY_CHECK)?.unwrap_or_default();
+        if open(key, KEY_CHECK.as_bytes(), &check).is_none() {
+            return Err(
+                format!("{} is encrypted with another key", path.as_ref().display()).into(),
+            );
+        }
+        Ok(Self {
+            db: Mutex::new(db),
+            key: Some(key.clone()),
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_age: None,
+        })
+    }
+
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Deletes entries older than `max_age` as new ones come in.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Adds a selection, returning its id, unless it's empty or the same as
+    /// the latest entry, from the same app.
+    pub fn record(
+        &self,
+        app_name: &str,
+        text: &str,
+    ) -> Result<Option<i64>, Box<dyn std::error::Error>> {
+        if text.is_empty() {
+            return Ok(None);
+        }
+        let mut db = self.db.lock();
+        let latest = db
+            .query_row(
+                "SELECT id, app_name, text FROM selections ORDER BY id DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        if let Some((latest_id, latest_app_name, latest_text)) = latest {
+            if self.read_column(latest_app_name, "app_name", latest_id)? == app_name
+                && self.read_column(latest_text, "text", latest_id)? == text
+            {
+                return Ok(None);
+            }
+        }
+
+        let now = SystemTime::now();
+        // rolled back when dropped on an error
+        let db = db.transaction()?;
+        // the values are sealed with the id, which is only known once inserted
+        db.execute(
+            "INSERT INTO selections (captured_at, app_name, text) VALUES (?1, '', '')",
+            [unix_ms(now)],
+        )?;
+        let id = db.last_insert_rowid();
+        db.execute(
+            "UPDATE selections SET app_name = ?1, text = ?2 WHERE id = ?3",
+            params![
+                self.column_value("app_name", id, app_name)?,
+                self.column_value("text", id, text)?,
+                id,
+            ],
+        )?;
+
+        db.execute(
+            "DELETE FROM selections WHERE id NOT IN (SELECT id FROM selections ORDER BY id DESC LIMIT ?1)",
+            [i64::try_from(self.max_entries).unwrap_or(i64::MAX)],
+        )?;
+        if let Some(max_age) = self.max_age {
+            db.execute(
+                "DELETE FROM selections WHERE captured_at < ?1",
+                [unix_ms(now.checked_sub(max_age).unwrap_or(UNIX_EPOCH))],
+            )?;
+        }
+        db.commit()?;
+        Ok(Some(id))
+    }
+
+    pub fn query(
+        &self,
+        query: &HistoryQuery,
+    ) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+        let db = self.db.lock();
+        let mut select = db.prepare(
+            "SELECT id, captured_at, app_name, text FROM selections
+             WHERE (?1 IS NULL OR instr(text, ?1) > 0)
+               AND (?2 IS NULL OR app_name = ?2)
+               AND captured_at >= ?3
+             ORDER BY id DESC LIMIT ?4",
+        )?;
+        // encrypted text and app names are matched once decrypted
+        let encrypted = self.is_encrypted();
+        let limit = i64::try_from(query.limit).unwrap_or(i64::MAX);
+        let mut rows = select.query(params![
+            query.contains.as_deref().filter(|_| !encrypted),
+            query.app_name.as_deref().filter(|_| !encrypted),
+            query.since.map_or(0, unix_ms),
+            if encrypted { -1 } else { limit },
+        ])?;
+
+        let mut entries = Vec::new();
+        while entries.len() < query.limit {
+            let Some(row) = rows.next()? else {
+                break;
+            };
+            let id = row.get(0)?;
+            let entry = HistoryEntry {
+                id,
+                captured_at: UNIX_EPOCH
+                    + Duration::from_millis(row.get::<_, i64>(1)?.max(0) as u64),
+                app_name: self.read_column(row.get(2)?, "app_name", id)?,
+                text: self.read_column(row.get(3)?, "text", id)?,
+            };
+            if query.matches(&entry) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn delete(&self, id: i64) -> Result<(), Box<dyn std::error::Error>> {
+        self.db
+            .lock()
+            .execute("DELETE FROM selections WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.lock().execute("DELETE FROM selections", [])?;
+        Ok(())
+    }
+
+    fn is_encrypted(&self) -> bool {
+        #[cfg(feature = "history-encryption")]
+        return self.key.is_some();
+        #[cfg(not(feature = "history-encryption"))]
+        false
+    }
+
+    /// `value` as stored in `column` of the entry `id`, sealed with both when
+    /// encrypted.
+    #[cfg_attr(not(feature = "history-encryption"), allow(unused_variables))]
+    fn column_value(
+        &self,
+        column: &str,
+        id: i64,
+        value: &str,
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        #[cfg(feature = "history-encryption")]
+        if let Some(key) = &self.key {
+            let sealed = seal(key, &entry_aad(column, id), value.as_bytes())?;
+            return Ok(Value::Blob(sealed));
+        }
+        Ok(Value::Text(value.to_string()))
+    }
+
+    #[cfg_attr(not(feature = "history-encryption"), allow(unused_variables))]
+    fn read_column(
+        &self,
+        value: Value,
+        column: &str,
+        id: i64,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        #[cfg(feature = "history-encryption")]
+        if let Some(key) = &self.key {
+            let Value::Blob(sealed) = value else {
+                return Err("A history entry isn't encrypted".into());
+            };
+            let text = open(key, &entry_aad(column, id), &sealed)
+                .ok_or("A history entry doesn't decrypt, it was changed or damaged")?;
+            return Ok(String::from_utf8_lossy(&text).into_owned());
+        }
+        match value {
+            Value::Text(text) => Ok(text),
+            Value::Blob(blob) => Ok(String::from_utf8_lossy(&blob).into_owned()),
+            _ => Ok(String::new()),
+        }
+    }
+}
+
+/// Seals the entries of a plaintext history with `key` and marks it as
+/// encrypted, unless another process did first.
+#[cfg(feature = "history-encryption")]
+fn encrypt_entries(db: &Connection, key: &EncryptionKey) -> Result<(), Box<dyn std::error::Error>> {
+    if meta(db, KEY_CHECK)?.is_some() {
+        return Ok(());
+    }
+    let entries = db
+        .prepare("SELECT id, app_name, text FROM selections")?
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut update = db.prepare("UPDATE selections SET app_name = ?1, text = ?2 WHERE id = ?3")?;
+    for (id, app_name, text) in entries {
+        update.execute(params![
+            seal(key, &entry_aad("app_name", id), app_name.as_bytes())?,
+            seal(key, &entry_aad("text", id), text.as_bytes())?,
+            id,
+        ])?;
+    }
+    db.execute(
+        "INSERT INTO meta (name, value) VALUES (?1, ?2)",
+        params![KEY_CHECK, seal(key, KEY_CHECK.as_bytes(), &[])?],
+    )?;
+    Ok(())
+}
+
+/// The associated data of a sealed column, so a value moved to another
+/// column or entry doesn't decrypt. Ids aren't reused (AUTOINCREMENT).
+#[cfg(feature = "history-encryption")]
+fn entry_aad(column: &str, id: i64) -> Vec<u8> {
+    format!("{column}:{id}").into_bytes()
+}
+
+/// `plaintext` sealed with ChaCha20-Poly1305 under a random nonce, as
+/// `nonce || ciphertext || tag`.
+#[cfg(feature = "history-encryption")]
+fn seal(
+    key: &EncryptionKey,
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut nonce = [0; 12];
+    getrandom::fill(&mut nonce)?;
+    let ciphertext = ChaCha20Poly1305::new(&key.0.into())
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| "Encrypting a history entry failed")?;
+    Ok([&nonce[..], &ciphertext].concat())
+}
+
+/// The plaintext of [`seal`]'s output, `None` when it was changed or sealed
+/// with another key or associated data.
+#[cfg(feature = "history-encryption")]
+fn open(key: &EncryptionKey, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < 12 {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(12);
+    ChaCha20Poly1305::new(&key.0.into())
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .ok()
+}
+
+fn open_database(path: &Path) -> Result<Connection, Box<dyn std::error::Error>> {
+    let db = Connection::open(path)?;
+    db.execute_batch(SCHEMA)?;
+    Ok(db)
+}
+
+fn meta(db: &Connection, name: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    Ok(db
+        .query_row("SELECT value FROM meta WHERE name = ?1", [name], |row| {
+            row.get(0)
+        })
+        .optional()?)
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path no other test, run or process uses.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!(
+            "get-selected-text-{name}-{}-{nanos}.sqlite3",
+            std::process::id()
+        ))
+    }
+
+    fn remove_database(path: &Path) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let path = temp_path("history");
+        let history = History::open(&path).unwrap().max_entries(3);
+
+        assert!(history.record("Notes", "the quick").unwrap().is_some());
+        // the same selection again, e.g. from a watcher
+        assert!(history.record("Notes", "the quick").unwrap().is_none());
+        assert!(history.record("Notes", "").unwrap().is_none());
+        history.record("Safari", "brown fox").unwrap();
+        history.record("Notes", "jumps over").unwrap();
+        let newest = history.record("Notes", "the lazy dog").unwrap().unwrap();
+
+        let all = history.query(&HistoryQuery::new()).unwrap();
+        let texts: Vec<&str> = all.iter().map(|entry| entry.text.as_str()).collect();
+        assert_eq!(texts, ["the lazy dog", "jumps over", "brown fox"]);
+        assert_eq!(all[0].id, newest);
+
+        let notes = history
+            .query(&HistoryQuery::new().app_name("Notes").contains("the"))
+            .unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].text, "the lazy dog");
+        assert!(history
+            .query(&HistoryQuery::new().since(SystemTime::now() + Duration::from_secs(60)))
+            .unwrap()
+            .is_empty());
+
+        history.delete(newest).unwrap();
+        assert_eq!(history.query(&HistoryQuery::new()).unwrap().len(), 2);
+        history.clear().unwrap();
+        assert!(history.query(&HistoryQuery::new()).unwrap().is_empty());
+
+        drop(history);
+        remove_database(&path);
+    }
+
+    #[cfg(feature = "history-encryption")]
+    #[test]
+    fn test_encrypted_history() {
+        let path = temp_path("encrypted");
+        History::open(&path)
+            .unwrap()
+            .record("Notes", "kept from before")
+            .unwrap();
+
+        let key = EncryptionKey::from_bytes([7; 32]);
+        let history = History::open_encrypted(&path, &key).unwrap();
+        history.record("Mail", "the secret plan").unwrap();
+        assert!(history.record("Mail", "the secret plan").unwrap().is_none());
+        let texts: Vec<String> = history
+            .query(&HistoryQuery::new())
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.text)
+            .collect();
+        assert_eq!(texts, ["the secret plan", "kept from before"]);
+        let mail = history
+            .query(&HistoryQuery::new().app_name("Mail").contains("plan"))
+            .unwrap();
+        assert_eq!(mail.len(), 1);
+
+        // while open, with the latest pages still in the WAL
+        assert!(Path::new(&format!("{}-wal", path.display())).exists());
+        for suffix in ["", "-wal", "-shm"] {
+            let Ok(file) = std::fs::read(format!("{}{suffix}", path.display())) else {
+                continue;
+            };
+            for needle in ["secret plan", "kept from before", "Notes"] {
+                let needle = needle.as_bytes();
+                assert!(!file.windows(needle.len()).any(|window| window == needle));
+            }
+        }
+        drop(history);
+        assert!(History::open(&path).is_err());
+        assert!(History::open_encrypted(&path, &EncryptionKey::from_bytes([8; 32])).is_err());
+
+        // a sealed text copied over another entry's doesn't decrypt
+        Connection::open(&path)
+            .unwrap()
+            .execute(
+                "UPDATE selections SET text = (SELECT text FROM selections WHERE id = ?1) WHERE id = ?2",
+                params![mail[0].id, mail[0].id - 1],
+            )
+            .unwrap();
+        let history = History::open_encrypted(&path, &key).unwrap();
+        assert!(history.query(&HistoryQuery::new()).is_err());
+        drop(history);
+
+        remove_database(&path);
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(target_os = "macos")]
+type Selection = crate::SelectedText;
+#[cfg(not(target_os = "macos"))]
+type Selection = String;
+
+/// A global key combination for [`on_hotkey`], parsed from strings like
+/// `"ctrl+shift+g"`, `"alt+space"` or `"win+f9"`.
+///
+/// The modifiers are `ctrl`, `alt`, `shift` and `win` (also `super`, `meta`
+/// or `cmd`), the key a letter, a digit, `space` or `f1` to `f24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub win: bool,
+    pub key: Key,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// An ASCII letter or digit, letters uppercase.
+    Char(char),
+    Space,
+    /// `F(1)` to `F(24)`.
+    F(u8),
+}
+
+impl Key {
+    /// The Windows virtual-key code.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub(crate) fn virtual_key(self) -> u32 {
+        match self {
+            // VK_0..VK_9 and VK_A..VK_Z are the ASCII codes
+            Key::Char(c) => c as u32,
+            Key::Space => 0x20,
+            Key::F(n) => 0x70 + u32::from(n) - 1,
+        }
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut hotkey = Hotkey {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            win: false,
+            key: Key::Space,
+        };
+        let mut key = None;
+        for part in s.split('+').map(str::trim) {
+            let modifier = match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut hotkey.ctrl,
+                "alt" | "option" => &mut hotkey.alt,
+                "shift" => &mut hotkey.shift,
+                "win" | "super" | "meta" | "cmd" => &mut hotkey.win,
+                _ if key.is_some() => return Err(format!("More than one key in {s:?}")),
+                _ => {
+                    key = Some(parse_key(part).ok_or_else(|| format!("Unknown key {part:?}"))?);
+                    continue;
+                }
+            };
+            *modifier = true;
+        }
+        hotkey.key = key.ok_or_else(|| format!("No key in {s:?}"))?;
+        Ok(hotkey)
+    }
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    let name = name.to_ascii_uppercase();
+    let mut chars = name.chars();
+    match (chars.next(), chars.as_str()) {
+        (Some(c), "") if c.is_ascii_alphanumeric() => Some(Key::Char(c)),
+        (Some('F'), n) => n.parse().ok().filter(|n| (1..=24).contains(n)).map(Key::F),
+        _ if name == "SPACE" => Some(Key::Space),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (pressed, name) in [
+            (self.ctrl, "ctrl+"),
+            (self.alt, "alt+"),
+            (self.shift, "shift+"),
+            (self.win, "win+"),
+        ] {
+            if pressed {
+                f.write_str(name)?;
+            }
+        }
+        match self.key {
+            Key::Char(c) => write!(f, "{}", c.to_ascii_lowercase()),
+            Key::Space => f.write_str("space"),
+            Key::F(n) => write!(f, "f{n}"),
+        }
+    }
+}
+
+/// Unregisters the hotkey when dropped, when [`HotkeyHandle::stop`] is
+/// called or on [`shutdown`](crate::shutdown).
+#[derive(Debug)]
+pub struct HotkeyHandle {
+    #[cfg(target_os = "windows")]
+    registration: Option<crate::lifecycle::Running>,
+}
+
+impl HotkeyHandle {
+    pub fn stop(&mut self) {
+        #[cfg(target_os = "windows")]
+        self.registration.take();
+    }
+}
+
+/// Captures the selection with [`get_selected_text`](crate::get_selected_text)
+/// whenever `hotkey` is pressed anywhere, and passes the result to
+/// `callback`. Only supported on Windows (through `RegisterHotKey`), fails
+/// when another app already registered the same combination.
+///
+/// # Example
+///
+/// ```no_run
+/// let handle = get_selected_text::on_hotkey("ctrl+shift+g".parse().unwrap(), |selection| {
+///     println!("{:?}", selection.map_err(|e| e.to_string()));
+/// })
+/// .unwrap();
+/// // ...
+/// drop(handle);
+/// ```
+pub fn on_hotkey<F>(
+    hotkey: Hotkey,
+    mut callback: F,
+) -> Result<HotkeyHandle, Box<dyn std::error::Error>>
+where
+    F: FnMut(Result<Selection, Box<dyn std::error::Error>>) + Send + 'static,
+{
+    #[cfg(target_os = "windows")]
+    {
+        let registration = crate::windows::HotkeyRegistration::register(hotkey, move || {
+            callback(crate::get_selected_text())
+        })?;
+        Ok(HotkeyHandle {
+            registration: Some(crate::lifecycle::Running::new(move || drop(registration))),
+        })
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = (hotkey, &mut callback);
+        Err(crate::unsupported_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hotkey() {
+        let hotkey: Hotkey = "Ctrl+Shift+g".parse().unwrap();
+        assert!(hotkey.ctrl && hotkey.shift && !hotkey.alt && !hotkey.win);
+        assert_eq!(hotkey.key, Key::Char('G'));
+        assert_eq!(hotkey.key.virtual_key(), 0x47);
+        assert_eq!(hotkey.to_string(), "ctrl+shift+g");
+
+        let hotkey: Hotkey = "super + F9".parse().unwrap();
+        assert!(hotkey.win);
+        assert_eq!(hotkey.key.virtual_key(), 0x78);
+        assert_eq!("alt+space".parse::<Hotkey>().unwrap().key, Key::Space);
+
+        assert!("ctrl+shift".parse::<Hotkey>().is_err());
+        assert!("ctrl+a+b".parse::<Hotkey>().is_err());
+        assert!("ctrl+f25".parse::<Hotkey>().is_err());
+        assert!("ctrl+é".parse::<Hotkey>().is_err());
+    }
+}
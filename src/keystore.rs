@@ -0,0 +1,391 @@
+//! Keeping the history's encryption key in the OS keystore: the Keychain on
+//! macOS, the Secret Service (GNOME Keyring, KWallet) on Linux, and a
+//! DPAPI-protected file in the local app data on Windows.
+
+/// What the key is stored as, next to the caller's service name.
+const ACCOUNT: &str = "history-encryption-key";
+
+/// The key stored under `service`, made and stored on first use.
+pub(crate) fn load_or_create(service: &str) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    if let Some(key) = platform::load(service)? {
+        return key
+            .try_into()
+            .map_err(|_| format!("The key stored for {service} isn't 32 bytes").into());
+    }
+    let mut key = [0; 32];
+    getrandom::fill(&mut key)?;
+    platform::store(service, &key)?;
+    // another process may have stored one first
+    match platform::load(service)? {
+        Some(stored) if stored.len() == key.len() => Ok(stored.try_into().unwrap()),
+        _ => Err(format!("The key for {service} couldn't be stored").into()),
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use core_foundation::base::{CFType, CFTypeRef, TCFType};
+    use core_foundation::boolean::CFBoolean;
+    use core_foundation::data::{CFData, CFDataRef};
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::string::{CFString, CFStringRef};
+
+    use super::ACCOUNT;
+
+    const ERR_SEC_ITEM_NOT_FOUND: i32 = -25300;
+    const ERR_SEC_DUPLICATE_ITEM: i32 = -25299;
+
+    #[link(name = "Security", kind = "framework")]
+    extern "C" {
+        static kSecClass: CFStringRef;
+        static kSecClassGenericPassword: CFStringRef;
+        static kSecAttrService: CFStringRef;
+        static kSecAttrAccount: CFStringRef;
+        static kSecAttrAccessible: CFStringRef;
+        static kSecAttrAccessibleAfterFirstUnlockThisDeviceOnly: CFStringRef;
+        static kSecValueData: CFStringRef;
+        static kSecReturnData: CFStringRef;
+        fn SecItemCopyMatching(query: CFDictionaryRef, result: *mut CFTypeRef) -> i32;
+        fn SecItemAdd(attributes: CFDictionaryRef, result: *mut CFTypeRef) -> i32;
+    }
+
+    fn key(key: CFStringRef) -> CFString {
+        unsafe { CFString::wrap_under_get_rule(key) }
+    }
+
+    fn item(service: &str, extra: &[(CFString, CFType)]) -> CFDictionary<CFString, CFType> {
+        let mut pairs = vec![
+            (
+                key(unsafe { kSecClass }),
+                key(unsafe { kSecClassGenericPassword }).as_CFType(),
+            ),
+            (
+                key(unsafe { kSecAttrService }),
+                CFString::new(service).as_CFType(),
+            ),
+            (
+                key(unsafe { kSecAttrAccount }),
+                CFString::new(ACCOUNT).as_CFType(),
+            ),
+        ];
+        pairs.extend_from_slice(extra);
+        CFDictionary::from_CFType_pairs(&pairs)
+    }
+
+    fn error(status: i32) -> Box<dyn std::error::Error> {
+        format!("Keychain error {status}").into()
+    }
+
+    pub(super) fn load(service: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        let query = item(
+            service,
+            &[(
+                key(unsafe { kSecReturnData }),
+                CFBoolean::true_value().as_CFType(),
+            )],
+        );
+        let mut result: CFTypeRef = std::ptr::null();
+        match unsafe { SecItemCopyMatching(query.as_concrete_TypeRef(), &mut result) } {
+            0 if !result.is_null() => {
+                let data = unsafe { CFData::wrap_under_create_rule(result as CFDataRef) };
+                Ok(Some(data.bytes().to_vec()))
+            }
+            ERR_SEC_ITEM_NOT_FOUND => Ok(None),
+            status => Err(error(status)),
+        }
+    }
+
+    pub(super) fn store(service: &str, secret: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let attributes = item(
+            service,
+            &[
+                (
+                    key(unsafe { kSecValueData }),
+                    CFData::from_buffer(secret).as_CFType(),
+                ),
+                (
+                    key(unsafe { kSecAttrAccessible }),
+                    key(unsafe { kSecAttrAccessibleAfterFirstUnlockThisDeviceOnly }).as_CFType(),
+                ),
+            ],
+        );
+        match unsafe { SecItemAdd(attributes.as_concrete_TypeRef(), std::ptr::null_mut()) } {
+            0 | ERR_SEC_DUPLICATE_ITEM => Ok(()),
+            status => Err(error(status)),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::collections::HashMap;
+
+    use zbus::blocking::{Connection, MessageIterator};
+    use zbus::message::Type;
+    use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+    use zbus::MatchRule;
+
+    use super::ACCOUNT;
+
+    const SECRETS: &str = "org.freedesktop.secrets";
+    const SERVICE: &str = "org.freedesktop.Secret.Service";
+    const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+
+    /// `(session, parameters, value, content type)`
+    type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+    fn attributes(service: &str) -> HashMap<&str, &str> {
+        HashMap::from([("service", service), ("account", ACCOUNT)])
+    }
+
+    /// A session passing secrets as they are, rather than encrypted with
+    /// `dh-ietf1024-sha256-aes128-cbc-pkcs7`. The secret only crosses the
+    /// session bus, whose socket only the user's own processes can open, and
+    /// any of them could just as well call `GetSecret` itself; the keyring
+    /// daemons treat both sessions the same.
+    fn open_session(bus: &Connection) -> Result<OwnedObjectPath, Box<dyn std::error::Error>> {
+        let (_, session): (OwnedValue, OwnedObjectPath) = bus
+            .call_method(
+                Some(SECRETS),
+                "/org/freedesktop/secrets",
+                Some(SERVICE),
+                "OpenSession",
+                &("plain", Value::from("")),
+            )?
+            .body()
+            .deserialize()?;
+        Ok(session)
+    }
+
+    /// Shows the unlock or confirmation dialog at `prompt`, if there's one,
+    /// and waits for the user.
+    fn prompt(bus: &Connection, prompt: &ObjectPath<'_>) -> Result<(), Box<dyn std::error::Error>> {
+        if prompt.as_str() == "/" {
+            return Ok(());
+        }
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface("org.freedesktop.Secret.Prompt")?
+            .member("Completed")?
+            .path(prompt.clone())?
+            .build();
+        let mut completed = MessageIterator::for_match_rule(rule, bus, Some(1))?;
+        bus.call_method(
+            Some(SECRETS),
+            prompt,
+            Some("org.freedesktop.Secret.Prompt"),
+            "Prompt",
+            &"",
+        )?;
+        let message = completed
+            .next()
+            .ok_or("The Secret Service closed the prompt")??;
+        let (dismissed, _): (bool, OwnedValue) = message.body().deserialize()?;
+        if dismissed {
+            return Err("Unlocking the keyring was dismissed".into());
+        }
+        Ok(())
+    }
+
+    pub(super) fn load(service: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        let bus = Connection::session()?;
+        let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = bus
+            .call_method(
+                Some(SECRETS),
+                "/org/freedesktop/secrets",
+                Some(SERVICE),
+                "SearchItems",
+                &attributes(service),
+            )?
+            .body()
+            .deserialize()?;
+        let item = match (unlocked.first(), locked.first()) {
+            (Some(item), _) => item.clone(),
+            (None, Some(item)) => {
+                let (_, unlock): (Vec<OwnedObjectPath>, OwnedObjectPath) = bus
+                    .call_method(
+                        Some(SECRETS),
+                        "/org/freedesktop/secrets",
+                        Some(SERVICE),
+                        "Unlock",
+                        &(vec![item],),
+                    )?
+                    .body()
+                    .deserialize()?;
+                prompt(&bus, &unlock)?;
+                item.clone()
+            }
+            (None, None) => return Ok(None),
+        };
+        let session = open_session(&bus)?;
+        let (_, _, value, _): Secret = bus
+            .call_method(
+                Some(SECRETS),
+                &item,
+                Some("org.freedesktop.Secret.Item"),
+                "GetSecret",
+                &session,
+            )?
+            .body()
+            .deserialize()?;
+        Ok(Some(value))
+    }
+
+    pub(super) fn store(service: &str, secret: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let bus = Connection::session()?;
+        let session = open_session(&bus)?;
+        let label = format!("{service} history key");
+        let properties = HashMap::from([
+            ("org.freedesktop.Secret.Item.Label", Value::from(label)),
+            (
+                "org.freedesktop.Secret.Item.Attributes",
+                Value::from(attributes(service)),
+            ),
+        ]);
+        let secret: Secret = (
+            session,
+            Vec::new(),
+            secret.to_vec(),
+            "application/octet-stream".to_string(),
+        );
+        let (_, created): (OwnedObjectPath, OwnedObjectPath) = bus
+            .call_method(
+                Some(SECRETS),
+                DEFAULT_COLLECTION,
+                Some("org.freedesktop.Secret.Collection"),
+                "CreateItem",
+                &(properties, secret, false),
+            )?
+            .body()
+            .deserialize()?;
+        prompt(&bus, &created)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::path::PathBuf;
+
+    use windows::Win32::Foundation::{LocalFree, ERROR_ALREADY_EXISTS, ERROR_FILE_EXISTS, HLOCAL};
+    use windows::Win32::Security::Cryptography::{
+        CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+    };
+    use windows::Win32::Storage::FileSystem::{MoveFileExW, MOVEFILE_WRITE_THROUGH};
+    use windows_core::{HSTRING, PCWSTR};
+
+    use super::ACCOUNT;
+
+    /// `%LOCALAPPDATA%\get-selected-text\<service>.key`
+    fn key_file(service: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        if service.contains(['/', '\\', ':']) {
+            return Err(format!("{service} can't be used as a file name").into());
+        }
+        let local = std::env::var_os("LOCALAPPDATA").ok_or("LOCALAPPDATA isn't set")?;
+        Ok(PathBuf::from(local)
+            .join("get-selected-text")
+            .join(format!("{service}.key")))
+    }
+
+    fn blob(data: &[u8]) -> CRYPT_INTEGER_BLOB {
+        CRYPT_INTEGER_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr().cast_mut(),
+        }
+    }
+
+    /// Copies `blob` out of the memory DPAPI allocated for it.
+    fn take(blob: CRYPT_INTEGER_BLOB) -> Vec<u8> {
+        let data =
+            unsafe { std::slice::from_raw_parts(blob.pbData, blob.cbData as usize) }.to_vec();
+        unsafe { LocalFree(Some(HLOCAL(blob.pbData.cast()))) };
+        data
+    }
+
+    pub(super) fn load(service: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        let protected = match std::fs::read(key_file(service)?) {
+            Ok(protected) => protected,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut out = CRYPT_INTEGER_BLOB::default();
+        unsafe {
+            CryptUnprotectData(
+                &blob(&protected),
+                None,
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut out,
+            )?
+        };
+        Ok(Some(take(out)))
+    }
+
+    pub(super) fn store(service: &str, secret: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let path = key_file(service)?;
+        let description: Vec<u16> = ACCOUNT.encode_utf16().chain([0]).collect();
+        let mut out = CRYPT_INTEGER_BLOB::default();
+        unsafe {
+            CryptProtectData(
+                &blob(secret),
+                PCWSTR(description.as_ptr()),
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut out,
+            )?
+        };
+        let protected = take(out);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // written next to it and moved into place, so a crash never leaves a
+        // partial key behind
+        let temp = path.with_file_name(format!("{service}.key.{}.tmp", std::process::id()));
+        let mut file = std::fs::File::create(&temp)?;
+        let written =
+            std::io::Write::write_all(&mut file, &protected).and_then(|_| file.sync_all());
+        drop(file);
+        let moved = written.map_err(Into::into).and_then(|_| {
+            // without MOVEFILE_REPLACE_EXISTING, keeping a key another
+            // process stored first
+            match unsafe {
+                MoveFileExW(
+                    &HSTRING::from(temp.as_path()),
+                    &HSTRING::from(path.as_path()),
+                    MOVEFILE_WRITE_THROUGH,
+                )
+            } {
+                Err(err)
+                    if err.code() == ERROR_ALREADY_EXISTS.to_hresult()
+                        || err.code() == ERROR_FILE_EXISTS.to_hresult() =>
+                {
+                    Ok(())
+                }
+                moved => moved.map_err(Into::into),
+            }
+        });
+        let _ = std::fs::remove_file(&temp);
+        moved
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+mod platform {
+    fn unsupported() -> Box<dyn std::error::Error> {
+        Box::new(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "There's no keystore on this platform",
+        ))
+    }
+
+    pub(super) fn load(_service: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        Err(unsupported())
+    }
+
+    pub(super) fn store(_service: &str, _secret: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Err(unsupported())
+    }
+}
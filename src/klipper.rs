@@ -0,0 +1,33 @@
+//! Klipper, Plasma's clipboard manager, over D-Bus. It takes ownership of
+//! whatever is copied and may put its own history back, so restores go
+//! through it instead of fighting it.
+
+use zbus::blocking::{fdo::DBusProxy, Connection};
+
+const SERVICE: &str = "org.kde.klipper";
+const OBJECT_PATH: &str = "/klipper";
+const INTERFACE: &str = "org.kde.klipper.klipper";
+
+pub(crate) fn is_running() -> bool {
+    if !crate::linux::is_desktop("KDE") {
+        return false;
+    }
+    let has_owner = || -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = Connection::session()?;
+        Ok(DBusProxy::new(&conn)?.name_has_owner(SERVICE.try_into()?)?)
+    };
+    has_owner().unwrap_or(false)
+}
+
+/// Makes `text` the clipboard and the top of Klipper's history, which keeps
+/// serving it after this process exits.
+pub(crate) fn set_clipboard_contents(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Connection::session()?.call_method(
+        Some(SERVICE),
+        OBJECT_PATH,
+        Some(INTERFACE),
+        "setClipboardContents",
+        &(text,),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+
+static LAST_SELECTION: Mutex<Option<LastSelection>> = Mutex::new(None);
+
+/// The most recent capture that found a selection, see
+/// [`get_last_selection`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct LastSelection {
+    /// One entry per file when `is_file_paths`, otherwise the text.
+    pub text: Vec<String>,
+    pub is_file_paths: bool,
+    pub app_name: String,
+    pub captured_at: SystemTime,
+}
+
+/// What [`crate::get_selected_text`] (or [`crate::get_selected_text_with_options`])
+/// last found in this process, when and in which app, without capturing
+/// again. For popups that want "what was selected when I was opened" after
+/// focus already moved to them.
+///
+/// ```no_run
+/// // on the hotkey
+/// let _ = get_selected_text::get_selected_text();
+/// // later, in the popup
+/// if let Some(last) = get_selected_text::get_last_selection() {
+///     println!("{} in {}", last.text.join("\n"), last.app_name);
+/// }
+/// ```
+pub fn get_last_selection() -> Option<LastSelection> {
+    LAST_SELECTION.lock().clone()
+}
+
+/// Keeps a capture for [`get_last_selection`] when it found something.
+pub(crate) fn remember(text: &[String], is_file_paths: bool, app_name: impl FnOnce() -> String) {
+    if text.iter().all(|text| text.is_empty()) {
+        return;
+    }
+    let app_name = app_name();
+    #[cfg(feature = "history")]
+    crate::history::record_capture(&app_name, &text.join("\n"));
+    *LAST_SELECTION.lock() = Some(LastSelection {
+        text: text.to_vec(),
+        is_file_paths,
+        app_name,
+        captured_at: SystemTime::now(),
+    });
+}
+
+pub(crate) fn forget() {
+    LAST_SELECTION.lock().take();
+}
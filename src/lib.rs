@@ -1,20 +1,201 @@
-#[cfg(not(target_os = "macos"))]
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+mod terminal;
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 mod utils;
 
+#[cfg(all(target_os = "linux", feature = "session-bus"))]
+mod atspi;
+#[cfg(all(target_os = "linux", feature = "session-bus"))]
+mod gnome;
+#[cfg(feature = "history-encryption")]
+mod keystore;
+#[cfg(all(target_os = "linux", feature = "session-bus"))]
+mod klipper;
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+#[cfg(all(target_os = "linux", feature = "session-bus"))]
+mod portal;
+#[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+mod unsupported;
+#[cfg(target_os = "linux")]
+mod wayland;
 #[cfg(target_os = "windows")]
 mod windows;
+#[cfg(target_os = "linux")]
+mod x11;
+
+mod attempt;
+mod cancel;
+mod classify;
+mod diagnose;
+mod entities;
+mod error;
+#[cfg(any(target_os = "macos", test))]
+mod expand;
+#[cfg(any(target_os = "macos", test))]
+mod file_paths;
+mod hotkey;
+mod last;
+mod lifecycle;
+mod links;
+#[cfg(target_os = "macos")]
+mod ocr;
+#[cfg(target_os = "windows")]
+mod office;
+mod options;
+mod provider;
+mod round_trip;
+mod stats;
+mod table;
+mod watcher;
+mod window;
+
+#[cfg(target_os = "macos")]
+pub mod services;
+
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+pub mod dbus;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "history")]
+pub mod history;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "ax-record")]
+pub mod recording;
+#[cfg(feature = "redact")]
+pub mod redact;
+#[cfg(feature = "uniffi")]
+pub mod uniffi_bindings;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(all(target_os = "macos", feature = "xpc"))]
+pub mod xpc;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
 
 #[cfg(target_os = "linux")]
 use crate::linux::get_selected_text as _get_selected_text;
 #[cfg(target_os = "macos")]
 use crate::macos::get_selected_text as _get_selected_text;
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+use crate::unsupported::get_selected_text as _get_selected_text;
 #[cfg(target_os = "windows")]
 use crate::windows::get_selected_text as _get_selected_text;
 
+#[cfg(target_os = "linux")]
+pub use crate::linux::get_clipboard_kind;
+#[cfg(target_os = "linux")]
+use crate::linux::{
+    get_clipboard_files as _get_clipboard_files, get_clipboard_text as _get_clipboard_text,
+};
+#[cfg(target_os = "macos")]
+pub use crate::macos::get_clipboard_kind;
+#[cfg(target_os = "macos")]
+use crate::macos::{
+    get_clipboard_files as _get_clipboard_files, get_clipboard_text as _get_clipboard_text,
+};
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+pub use crate::unsupported::get_clipboard_kind;
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+use crate::unsupported::{
+    get_clipboard_files as _get_clipboard_files, get_clipboard_text as _get_clipboard_text,
+};
+#[cfg(target_os = "windows")]
+pub use crate::windows::get_clipboard_kind;
+#[cfg(target_os = "windows")]
+use crate::windows::{
+    get_clipboard_files as _get_clipboard_files, get_clipboard_text as _get_clipboard_text,
+};
+
+#[cfg(target_os = "linux")]
+pub use crate::linux::{displays, mouse_position};
+#[cfg(target_os = "macos")]
+pub use crate::macos::{displays, mouse_position};
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+pub use crate::unsupported::{displays, mouse_position};
+#[cfg(target_os = "windows")]
+pub use crate::windows::{displays, mouse_position};
+
+#[cfg(target_os = "linux")]
+use crate::linux::replace_selected_text as _replace_selected_text;
+#[cfg(target_os = "macos")]
+use crate::macos::replace_selected_text as _replace_selected_text;
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+use crate::unsupported::replace_selected_text as _replace_selected_text;
+#[cfg(target_os = "windows")]
+use crate::windows::replace_selected_text as _replace_selected_text;
+
+#[cfg(target_os = "linux")]
+use crate::linux::set_selected_text as _set_selected_text;
+#[cfg(target_os = "linux")]
+use crate::linux::type_text as _type_text;
+#[cfg(target_os = "macos")]
+use crate::macos::type_text as _type_text;
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+use crate::unsupported::type_text as _type_text;
+#[cfg(target_os = "windows")]
+use crate::windows::type_text as _type_text;
+
+#[cfg(target_os = "macos")]
+use crate::macos::set_selected_text as _set_selected_text;
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+use crate::unsupported::set_selected_text as _set_selected_text;
+#[cfg(target_os = "windows")]
+use crate::windows::set_selected_text as _set_selected_text;
+
+#[cfg(target_os = "linux")]
+use crate::linux::get_selected_text_passively as _get_selected_text_passively;
+#[cfg(target_os = "macos")]
+use crate::macos::get_selected_text_passively as _get_selected_text_passively;
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+use crate::unsupported::get_selected_text_passively as _get_selected_text_passively;
+#[cfg(target_os = "windows")]
+use crate::windows::get_selected_text_passively as _get_selected_text_passively;
+
+#[cfg(target_os = "linux")]
+use crate::linux::get_selected_text_and_html as _get_selected_text_and_html;
+#[cfg(target_os = "macos")]
+use crate::macos::get_selected_text_and_html as _get_selected_text_and_html;
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+use crate::unsupported::get_selected_text_and_html as _get_selected_text_and_html;
+#[cfg(target_os = "windows")]
+use crate::windows::get_selected_text_and_html as _get_selected_text_and_html;
+
+pub use crate::attempt::Attempt;
+pub use crate::cancel::CancellationToken;
+pub use crate::classify::{detect_code, CodeDetection};
+pub use crate::diagnose::{
+    diagnose, self_test, Capability, CaptureMethod, Diagnostics, SelfTestResult,
+};
+pub use crate::entities::{extract_entities, Entity, EntityKind};
+pub use crate::error::{error_chain, AppleScriptErrorKind, GetTextError};
+pub use crate::hotkey::{on_hotkey, Hotkey, HotkeyHandle, Key};
+pub use crate::last::{get_last_selection, LastSelection};
+pub use crate::lifecycle::{init, shutdown};
+pub use crate::links::{extract_links_from_html, Link};
+pub use crate::options::{
+    set_default_capture_options, CaptureOptions, OcrOptions, RecognitionLevel, Strategy,
+};
+pub use crate::provider::{Capturer, Selection, SelectionProvider, SystemProvider};
+pub use crate::round_trip::set_cross_process_lock;
+pub use crate::stats::{
+    export_method_profiles, import_method_profiles, prewarm, set_method_cache_capacity, AppId,
+    AppProfile, MethodProfile, MethodProfiles,
+};
+pub use crate::table::{extract_tables_from_html, parse_table, Table};
+pub use crate::watcher::{watch_selection, SelectionEvent, WatchHandle, MIN_WATCH_INTERVAL};
+pub use crate::window::{display_at, get_window_meta, Display, Point, Rect, WindowMeta};
+
 /// # Example
 ///
 /// ```
@@ -25,19 +206,722 @@ use crate::windows::get_selected_text as _get_selected_text;
 /// ```
 #[cfg(not(target_os = "macos"))]
 pub fn get_selected_text() -> Result<String, Box<dyn std::error::Error>> {
-    _get_selected_text()
+    get_selected_text_with_options(&CaptureOptions::new())
 }
 
 #[cfg(target_os = "macos")]
 pub fn get_selected_text() -> Result<SelectedText, Box<dyn std::error::Error>> {
-    _get_selected_text()
+    get_selected_text_with_options(&CaptureOptions::new())
+}
+
+/// Like [`get_selected_text`], with the capture tuned by `options`.
+///
+/// ```no_run
+/// use get_selected_text::{get_selected_text_with_options, CaptureOptions};
+///
+/// let selected = get_selected_text_with_options(&CaptureOptions::new().ocr(true)).unwrap();
+/// ```
+#[cfg(not(target_os = "macos"))]
+pub fn get_selected_text_with_options(
+    options: &CaptureOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if options.cancel.is_cancelled() {
+        return Err(cancel::cancelled_error());
+    }
+    #[cfg(feature = "mock")]
+    let result = if mock::is_enabled() {
+        mock::get_selected_text()
+    } else {
+        capture(options)
+    };
+    #[cfg(not(feature = "mock"))]
+    let result = capture(options);
+    #[cfg(feature = "redact")]
+    let result = result.and_then(|text| redact::redact_text(text, redact::App::active));
+    if let Ok(text) = &result {
+        last::remember(std::slice::from_ref(text), false, active_app_name);
+    }
+    result
+}
+
+#[cfg(not(target_os = "macos"))]
+fn capture(options: &CaptureOptions) -> Result<String, Box<dyn std::error::Error>> {
+    let mut attempts = attempt::Attempts::default();
+    let result = _get_selected_text(options, &mut attempts);
+    result.map_err(|e| failed(e, attempts, options))
+}
+
+#[cfg(target_os = "macos")]
+pub fn get_selected_text_with_options(
+    options: &CaptureOptions,
+) -> Result<SelectedText, Box<dyn std::error::Error>> {
+    if options.cancel.is_cancelled() {
+        return Err(cancel::cancelled_error());
+    }
+    #[cfg(feature = "mock")]
+    let result = if mock::is_enabled() {
+        mock::get_selected_text()
+    } else {
+        capture(options)
+    };
+    #[cfg(not(feature = "mock"))]
+    let result = capture(options);
+    #[cfg(feature = "redact")]
+    let result = result.and_then(redact::redact_selected);
+    if let Ok(selected) = &result {
+        last::remember(&selected.text, selected.is_file_paths, || {
+            selected.app_name.clone()
+        });
+    }
+    result
+}
+
+#[cfg(target_os = "macos")]
+fn capture(options: &CaptureOptions) -> Result<SelectedText, Box<dyn std::error::Error>> {
+    let mut attempts = attempt::Attempts::default();
+    match _get_selected_text(options, &mut attempts) {
+        Ok(selected) => Ok(SelectedText {
+            attempts: attempts.0,
+            ..selected
+        }),
+        Err(e) => Err(failed(e, attempts, options)),
+    }
+}
+
+/// Attaches what was tried to a failed capture, unless it was cancelled or
+/// failed before trying anything.
+fn failed(
+    error: Box<dyn std::error::Error>,
+    attempts: attempt::Attempts,
+    options: &CaptureOptions,
+) -> Box<dyn std::error::Error> {
+    if attempts.0.is_empty() || options.cancel.is_cancelled() {
+        return error;
+    }
+    Box::new(GetTextError::Failed {
+        attempts: attempts.0,
+        source: error,
+    })
+}
+
+/// Reads the selection only through the methods answering right away (the
+/// accessibility API, and the PRIMARY selection on Linux), never simulating
+/// key presses or touching the clipboard.
+///
+/// Fails with [`std::io::ErrorKind::WouldBlock`] when they find nothing, so
+/// only [`get_selected_text`]'s copy through the clipboard could read the
+/// selection. An empty selection looks the same.
+///
+/// ```no_run
+/// use std::io::ErrorKind;
+///
+/// match get_selected_text::try_get_selected_text() {
+///     Ok(text) => println!("{text}"),
+///     Err(e) if e
+///         .downcast_ref::<std::io::Error>()
+///         .is_some_and(|e| e.kind() == ErrorKind::WouldBlock) =>
+///     {
+///         // capture with get_selected_text() later, off the hot path
+///     }
+///     Err(e) => eprintln!("{e}"),
+/// }
+/// ```
+pub fn try_get_selected_text() -> Result<String, Box<dyn std::error::Error>> {
+    match get_selected_text_passively() {
+        #[cfg(feature = "redact")]
+        Ok(text) if !text.is_empty() => redact::redact_text(text, redact::App::active),
+        #[cfg(not(feature = "redact"))]
+        Ok(text) if !text.is_empty() => Ok(text),
+        Err(e) if !passive_read_supported() => Err(e),
+        _ => Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::WouldBlock,
+            "The selection can only be read by copying it",
+        ))),
+    }
+}
+
+/// A hash of the focused app and its selection, for pollers that only need
+/// to know whether the selection changed since the last tick. Equal
+/// fingerprints mean nothing changed; they're only comparable within one
+/// process.
+///
+/// On macOS it hashes the focused element and the selected range without
+/// reading the text, so a selection replaced in place by as many characters
+/// looks unchanged. Elsewhere it hashes what [`try_get_selected_text`] reads.
+/// Never simulates key presses.
+///
+/// ```no_run
+/// let mut last = None;
+/// loop {
+///     let fingerprint = get_selected_text::selection_fingerprint().ok();
+///     if fingerprint != last {
+///         last = fingerprint;
+///         // read the selection
+///     }
+///     std::thread::sleep(std::time::Duration::from_millis(200));
+/// }
+/// ```
+pub fn selection_fingerprint() -> Result<u64, Box<dyn std::error::Error>> {
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        return passive_read_fingerprint();
+    }
+    #[cfg(target_os = "macos")]
+    return macos::selection_fingerprint();
+    #[cfg(not(target_os = "macos"))]
+    passive_read_fingerprint()
+}
+
+#[cfg(any(feature = "mock", not(target_os = "macos")))]
+fn passive_read_fingerprint() -> Result<u64, Box<dyn std::error::Error>> {
+    use std::hash::{Hash, Hasher};
+
+    let text = get_selected_text_passively()?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (active_app_name(), text).hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// Copies the selection once and returns both its plain text and, when the
+/// app provides one (browsers, office suites, rich editors), its HTML flavor.
+/// Cheaper than capturing twice when both are needed, e.g. to keep links and
+/// tables around.
+///
+/// Always goes through the clipboard, which is restored afterwards.
+///
+/// ```no_run
+/// let selection = get_selected_text::get_selected_text_and_html().unwrap();
+/// if let Some(html) = &selection.html {
+///     let links = get_selected_text::extract_links_from_html(html);
+/// }
+/// ```
+pub fn get_selected_text_and_html() -> Result<HtmlSelection, Box<dyn std::error::Error>> {
+    #[cfg(feature = "mock")]
+    let (text, html) = if mock::is_enabled() {
+        (mock::get_selected_text_passively()?, None)
+    } else {
+        _get_selected_text_and_html()?
+    };
+    #[cfg(not(feature = "mock"))]
+    let (text, html) = _get_selected_text_and_html()?;
+    // HTML can't be redacted reliably, it goes when the text changed
+    #[cfg(feature = "redact")]
+    let (text, html) = {
+        let redacted = redact::redact_text(text.clone(), redact::App::active)?;
+        let html = html.filter(|_| redacted == text);
+        (redacted, html)
+    };
+    Ok(HtmlSelection { text, html })
+}
+
+/// The whole text of the focused document or text field, for "summarize
+/// this document" features.
+///
+/// Read from the focused element's accessibility value when the app exposes
+/// one. Otherwise the document is selected with cmd+a and copied, and the
+/// selection and clipboard are put back; apps exposing no selected range
+/// through the accessibility API are left with everything selected. Only
+/// supported on macOS.
+pub fn get_full_document_text() -> Result<String, Box<dyn std::error::Error>> {
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        // the mocked document is the selection
+        let text = mock::get_selected_text_passively()?;
+        #[cfg(feature = "redact")]
+        let text = redact::redact_text(text, redact::App::active)?;
+        return Ok(text);
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let text = macos::get_full_document_text()?;
+        #[cfg(feature = "redact")]
+        let text = redact::redact_text(text, redact::App::active)?;
+        Ok(text)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Err(unsupported_error())
+    }
+}
+
+/// Replaces the selection with `text` through the accessibility API, without
+/// touching the clipboard.
+///
+/// Fails with [`std::io::ErrorKind::PermissionDenied`] when the focused element's
+/// selection is read-only, and with [`std::io::ErrorKind::Unsupported`] on
+/// platforms other than macOS.
+pub fn set_selected_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        return mock::set_selected_text(text);
+    }
+    _set_selected_text(text)
+}
+
+/// Replaces the selection with `text`.
+///
+/// Uses the accessibility API when the app allows it (see [`set_selected_text`]),
+/// otherwise puts `text` on the clipboard, simulates pressing cmd+v (ctrl+v on
+/// Windows and Linux) and restores the previous clipboard contents.
+pub fn replace_selected_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        return mock::set_selected_text(text);
+    }
+    _replace_selected_text(text)
 }
 
+/// Types `text` over the selection as synthetic key events, one character
+/// every `interval`.
+///
+/// Meant for apps where pasting is blocked or mangled (some terminals and
+/// remote-desktop sessions). Cancelling `cancel` from another thread stops
+/// typing and returns an [`std::io::ErrorKind::Interrupted`] error. On
+/// Linux it needs an X11 session (or XWayland's focus), elsewhere it fails
+/// with [`std::io::ErrorKind::Unsupported`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use get_selected_text::{type_text, CancellationToken};
+///
+/// let cancel = CancellationToken::new();
+/// type_text("hello", Duration::from_millis(10), &cancel).unwrap();
+/// ```
+pub fn type_text(
+    text: &str,
+    interval: std::time::Duration,
+    cancel: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if cancel.is_cancelled() {
+        return Err(cancel::cancelled_error());
+    }
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        return mock::set_selected_text(text);
+    }
+    _type_text(text, interval, cancel)
+}
+
+/// Bounding box of the current selection in global coordinates (see
+/// [`Rect`]), for placing popups next to it.
+///
+/// Read through the accessibility API on macOS and the UI Automation text
+/// pattern on Windows, so it only works in apps exposing their text there.
+/// Not supported on other platforms.
+pub fn get_selection_bounds() -> Result<Rect, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    {
+        macos::get_selection_bounds()
+    }
+    #[cfg(target_os = "windows")]
+    {
+        windows::get_selection_bounds()
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    {
+        Err(unsupported_error())
+    }
+}
+
+/// Reads the selection together with its style runs (font, bold, italic,
+/// color, link targets) through the accessibility API, for consumers that
+/// need to reconstruct it faithfully. Only supported on macOS, and only by
+/// apps exposing attributed text (Cocoa text views, Safari, Pages...).
+pub fn get_selected_styled_text() -> Result<StyledText, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    {
+        let styled = macos::get_selected_styled_text()?;
+        // runs hold offsets into the text
+        #[cfg(feature = "redact")]
+        redact::check_unchanged(&styled.text, redact::App::active)?;
+        Ok(styled)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Err(unsupported_error())
+    }
+}
+
+/// The whole sentence (or sentences) the selection is part of, with where
+/// the selection sits in it, so grammar and rewriting tools can work on
+/// complete sentences when the user selected a fragment. A caret without a
+/// selection gives the sentence it's in.
+///
+/// The text around the selection is read through the accessibility API and
+/// segmented for the language it's written in. Only supported on macOS, and
+/// only by apps exposing their text with character ranges (Cocoa text views,
+/// most browsers' text fields).
+pub fn get_selected_sentence() -> Result<ExpandedSelection, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    {
+        expand_selection(expand::TextUnit::Sentence)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Err(unsupported_error())
+    }
+}
+
+/// Like [`get_selected_sentence`], the paragraph (or paragraphs) around the
+/// selection.
+pub fn get_selected_paragraph() -> Result<ExpandedSelection, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    {
+        expand_selection(expand::TextUnit::Paragraph)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Err(unsupported_error())
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn expand_selection(
+    unit: expand::TextUnit,
+) -> Result<ExpandedSelection, Box<dyn std::error::Error>> {
+    let expanded = macos::expand_selection(unit)?;
+    #[cfg(feature = "redact")]
+    redact::check_unchanged(&expanded.text, redact::App::active)?;
+    Ok(expanded)
+}
+
+/// The focused accessibility element's attributes and those of its
+/// children, `depth` levels down, for bug reports about apps the selection
+/// can't be read from: serialize it, e.g. to JSON, and attach it
+/// (`get-selected-text --ax-tree <depth>`). Only supported on macOS.
+pub fn dump_focused_ax_tree(depth: usize) -> Result<AxNode, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    {
+        macos::dump_focused_ax_tree(depth)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = depth;
+        Err(unsupported_error())
+    }
+}
+
+/// Returns the links contained in the selection, with their visible text.
+///
+/// On macOS they're read from the selection's attributed string through the
+/// accessibility API when the app exposes one. Otherwise the selection is
+/// copied and the links are taken from its HTML flavor, so apps that only
+/// copy plain text give none.
+pub fn get_selected_links() -> Result<Vec<Link>, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    match get_selected_styled_text() {
+        Ok(styled) => return Ok(links::links_from_styled_text(&styled)),
+        Err(e) if matches!(e.downcast_ref(), Some(GetTextError::Redacted)) => return Err(e),
+        Err(_) => {}
+    }
+    Ok(get_selected_text_and_html()?
+        .html
+        .map(|html| extract_links_from_html(&html))
+        .unwrap_or_default())
+}
+
+/// Returns the selection as rows and columns when it comes from a table: the
+/// selected rows of an accessible table on macOS, the first `<table>` of its
+/// HTML flavor on Windows and Linux, otherwise the captured text when it's
+/// tab-separated, as spreadsheets and web tables copy. `None` when the
+/// selection isn't tabular.
+pub fn get_selected_table() -> Result<Option<Table>, Box<dyn std::error::Error>> {
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        let text = mock::get_selected_text_passively()?;
+        #[cfg(feature = "redact")]
+        let text = redact::redact_text(text, redact::App::active)?;
+        return Ok(parse_table(&text));
+    }
+    #[cfg(target_os = "macos")]
+    {
+        if let Some(table) = macos::get_selected_table_by_ax() {
+            #[cfg(feature = "redact")]
+            let table = redact::redact_table(table, redact::App::active)?;
+            return Ok(Some(table));
+        }
+        Ok(parse_table(&get_selected_text()?.text.join("\n")))
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let selection = get_selected_text_and_html()?;
+        let html_table = selection
+            .html
+            .and_then(|html| extract_tables_from_html(&html).into_iter().next());
+        Ok(html_table.or_else(|| parse_table(&selection.text)))
+    }
+}
+
+/// Recognizes the text on screen inside `rect` (global coordinates, see
+/// [`Rect`]), for "capture text from anywhere" features. Pass
+/// [`get_selection_bounds`] or a user-drawn rectangle.
+///
+/// Needs the Screen Recording permission. Only supported on macOS.
+pub fn ocr_region(rect: Rect, options: &OcrOptions) -> Result<String, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    {
+        ocr::ocr_region(rect, options)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = (rect, options);
+        Err(unsupported_error())
+    }
+}
+
+/// Reads the selection of a specific window through the accessibility API,
+/// without bringing it to the front or simulating key presses.
+///
+/// `window_id` is the `CGWindowID` (`kCGWindowNumber`) of the window. Only
+/// supported on macOS.
+pub fn get_selected_text_for_window(window_id: u32) -> Result<String, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    {
+        let text = macos::get_selected_text_for_window(window_id)?;
+        #[cfg(feature = "redact")]
+        let text = redact::redact_text(text, || macos::window_owner_app(window_id))?;
+        Ok(text)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = window_id;
+        Err(unsupported_error())
+    }
+}
+
+/// Lists the non-empty selections in every window of the frontmost app, for
+/// split-pane editors and multi-window apps where the focused element isn't
+/// necessarily the one the user means. Only supported on macOS.
+pub fn get_window_selections() -> Result<Vec<WindowSelection>, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    {
+        let selections = macos::get_window_selections()?;
+        #[cfg(feature = "redact")]
+        let selections = redact::redact_window_selections(selections, redact::App::active)?;
+        Ok(selections)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Err(unsupported_error())
+    }
+}
+
+/// Reads the clipboard text without simulating any key presses, empty when
+/// it holds no text.
+pub fn get_clipboard_text() -> Result<String, Box<dyn std::error::Error>> {
+    let text = _get_clipboard_text()?;
+    #[cfg(feature = "redact")]
+    let text = redact::redact_text(text, redact::App::active)?;
+    Ok(text)
+}
+
+/// Reads the file paths on the clipboard without simulating any key presses.
+pub fn get_clipboard_files() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let paths = _get_clipboard_files()?;
+    #[cfg(feature = "redact")]
+    let paths = redact::redact_texts(paths, redact::App::active)?;
+    Ok(paths)
+}
+
+/// Whether the process has the permissions captures need: Accessibility
+/// access on macOS, nothing to grant elsewhere.
+pub fn has_permissions() -> bool {
+    #[cfg(target_os = "macos")]
+    return macos_accessibility_client::accessibility::application_is_trusted();
+    #[cfg(not(target_os = "macos"))]
+    return true;
+}
+
+/// Like [`has_permissions`], but on macOS also shows the system prompt
+/// pointing the user to the Accessibility settings when not granted yet.
+pub fn request_permissions() -> bool {
+    #[cfg(target_os = "macos")]
+    return macos_accessibility_client::accessibility::application_is_trusted_with_prompt();
+    #[cfg(not(target_os = "macos"))]
+    return true;
+}
+
+pub(crate) fn unsupported_error() -> Box<dyn std::error::Error> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Not supported on this platform",
+    ))
+}
+
+/// Whether [`get_selected_text_passively`] can ever succeed on this platform.
+pub(crate) fn passive_read_supported() -> bool {
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        return true;
+    }
+    cfg!(any(
+        target_os = "macos",
+        target_os = "windows",
+        target_os = "linux"
+    ))
+}
+
+/// Reads the selection without simulating any key presses.
+pub(crate) fn get_selected_text_passively() -> Result<String, Box<dyn std::error::Error>> {
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        return mock::get_selected_text_passively();
+    }
+    _get_selected_text_passively()
+}
+
+pub(crate) fn active_app_name() -> String {
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        return mock::app_name();
+    }
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    {
+        window::active_window()
+            .map(|window| window.app_name)
+            .unwrap_or_default()
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        String::new()
+    }
+}
+
+/// What the per-app method statistics are keyed by: the executable path, which
+/// unlike the display name isn't localized and tells same-named apps apart.
+/// The name when the path isn't known.
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+pub(crate) fn active_app_id() -> String {
+    #[cfg(feature = "mock")]
+    if mock::is_enabled() {
+        return mock::app_name();
+    }
+    window::active_window()
+        .map(|window| window.process_path.unwrap_or(window.app_name))
+        .unwrap_or_default()
+}
+
+/// What the clipboard currently holds, as reported by [`get_clipboard_kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum ClipboardKind {
+    Empty,
+    Text,
+    Files,
+    Image,
+    Other,
+}
+
+/// The selection with its formatting, see [`get_selected_styled_text`].
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct StyledText {
+    pub text: String,
+    pub runs: Vec<StyleRun>,
+}
+
+/// The sentence or paragraph around the selection, see
+/// [`get_selected_sentence`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ExpandedSelection {
+    pub text: String,
+    /// Byte range of the selection into [`ExpandedSelection::text`].
+    pub selection: std::ops::Range<usize>,
+}
+
+/// A stretch of [`StyledText::text`] sharing the same attributes.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct StyleRun {
+    /// Byte range into [`StyledText::text`].
+    pub range: std::ops::Range<usize>,
+    pub font_name: Option<String>,
+    pub font_family: Option<String>,
+    pub font_size: Option<f64>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    /// `#rrggbb`
+    pub color: Option<String>,
+    /// Target of a link covering the run.
+    pub link: Option<String>,
+}
+
+/// An accessibility element, see [`dump_focused_ax_tree`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct AxNode {
+    /// Attribute names and values, e.g. `AXRole: AXTextArea`. Long values
+    /// are cut short, elements and lists are left out.
+    pub attributes: std::collections::BTreeMap<String, String>,
+    pub children: Vec<AxNode>,
+    /// Children past the first 100, not listed.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub omitted_children: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// Plain text and HTML of one capture, see [`get_selected_text_and_html`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct HtmlSelection {
+    pub text: String,
+    /// `None` when the app only copied plain text.
+    pub html: Option<String>,
+}
+
+/// Selections found in one window by [`get_window_selections`].
 #[derive(Debug, Clone, serde::Serialize)]
+pub struct WindowSelection {
+    pub window_id: u32,
+    pub title: String,
+    pub text: Vec<String>,
+}
+
+/// What had the focus when the capture started, see [`SelectedText::focus`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Focus {
+    /// A window of the frontmost app.
+    #[default]
+    App,
+    /// Finder without one of its windows focused, i.e. the desktop.
+    Desktop,
+    MissionControl,
+    ScreenSaver,
+    LockScreen,
+    /// No app is frontmost, e.g. during a space switch.
+    NoFrontmostApp,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct SelectedText {
-    is_file_paths: bool,
-    app_name: String,
-    text: Vec<String>,
+    pub is_file_paths: bool,
+    /// Localized display name of the frontmost app, prefer `bundle_id` for matching.
+    pub app_name: String,
+    /// e.g. `com.microsoft.VSCodeInsiders`
+    pub bundle_id: Option<String>,
+    pub process_id: Option<i32>,
+    /// `CFBundleShortVersionString` of the app
+    pub app_version: Option<String>,
+    /// `CGWindowID` of the frontmost window
+    pub window_id: Option<u32>,
+    /// Where the mouse was when the capture started.
+    pub mouse_position: Option<Point>,
+    /// Tells the desktop, Mission Control and the like apart from an app.
+    pub focus: Focus,
+    /// PNG bytes of an image selection, see [`CaptureOptions::image_text`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<Vec<u8>>,
+    pub text: Vec<String>,
+    /// The capture methods tried, in order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+    /// Parts of the capture that failed while another part gave the result,
+    /// e.g. reading the selected files in Finder while a name is edited.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
 #[cfg(test)]
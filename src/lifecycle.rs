@@ -0,0 +1,112 @@
+//! Setting up and tearing down the crate's process-wide state, for apps that
+//! want it to happen at a time of their choosing rather than on the first
+//! capture.
+
+use std::sync::{Arc, Weak};
+
+use parking_lot::Mutex;
+
+type Stop = Box<dyn FnOnce() + Send>;
+
+/// What's running until [`shutdown`].
+static RUNNING: Mutex<Vec<Weak<Mutex<Option<Stop>>>>> = Mutex::new(Vec::new());
+
+/// A hotkey registration or a server, stopped once by whichever comes
+/// first: [`Running::stop`], dropping it, or [`shutdown`].
+pub(crate) struct Running(Arc<Mutex<Option<Stop>>>);
+
+#[cfg_attr(
+    not(any(
+        target_os = "windows",
+        feature = "websocket",
+        feature = "grpc",
+        all(target_os = "linux", feature = "dbus"),
+        all(target_os = "macos", feature = "xpc")
+    )),
+    allow(dead_code)
+)]
+impl Running {
+    pub(crate) fn new(stop: impl FnOnce() + Send + 'static) -> Self {
+        let stop: Arc<Mutex<Option<Stop>>> = Arc::new(Mutex::new(Some(Box::new(stop))));
+        let mut running = RUNNING.lock();
+        running.retain(|stop| stop.strong_count() > 0);
+        running.push(Arc::downgrade(&stop));
+        Self(stop)
+    }
+
+    pub(crate) fn stop(&self) {
+        stop(&self.0);
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl std::fmt::Debug for Running {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Running")
+            .field("stopped", &self.0.lock().is_none())
+            .finish()
+    }
+}
+
+fn stop(stop: &Mutex<Option<Stop>>) {
+    // taken first, stopping can drop another `Running`
+    let stop = stop.lock().take();
+    if let Some(stop) = stop {
+        stop();
+    }
+}
+
+/// Sets up what captures otherwise create on first use: the per-app method
+/// statistics and, on Linux, the Wayland protocol probe. On macOS it also checks
+/// the Accessibility permission and fails with `PermissionDenied` without it,
+/// after everything else is set up.
+///
+/// Calling it is optional and calling it again does nothing.
+///
+/// ```no_run
+/// get_selected_text::init().expect("grant Accessibility access");
+/// // ...
+/// get_selected_text::shutdown();
+/// ```
+pub fn init() -> Result<(), Box<dyn std::error::Error>> {
+    crate::stats::init();
+    #[cfg(target_os = "linux")]
+    crate::wayland::capabilities();
+    #[cfg(target_os = "macos")]
+    return crate::macos::init();
+    #[cfg(not(target_os = "macos"))]
+    Ok(())
+}
+
+/// Stops every selection watcher, hotkey registration and server
+/// (WebSocket, gRPC, D-Bus, XPC), drops the method statistics (and on macOS
+/// the observer forgetting relaunched apps) and the last selection, and
+/// releases what earlier captures kept open: the portal session and the
+/// uinput keyboard on Linux, where it also turns the session's accessibility
+/// flag back off if captures from Chromium apps turned it on. Captures still
+/// work afterwards and set things up again, a declined portal session is
+/// asked for again. Handles of what was stopped stay valid, stopping them
+/// again does nothing.
+pub fn shutdown() {
+    crate::watcher::stop_all();
+    let running = std::mem::take(&mut *RUNNING.lock());
+    for running in running.iter().filter_map(Weak::upgrade) {
+        stop(&running);
+    }
+    crate::stats::clear();
+    crate::last::forget();
+    #[cfg(target_os = "linux")]
+    {
+        #[cfg(feature = "session-bus")]
+        {
+            crate::portal::close_session();
+            crate::atspi::restore_accessibility();
+        }
+        crate::uinput::destroy();
+    }
+}
@@ -0,0 +1,113 @@
+//! Hyperlinks inside a selection, which plain-text capture loses.
+
+/// A link found in the selection.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Link {
+    /// The visible text of the link, e.g. "click here".
+    pub text: String,
+    pub url: String,
+}
+
+/// Merges consecutive style runs pointing to the same target into links.
+#[cfg(target_os = "macos")]
+pub(crate) fn links_from_styled_text(styled: &crate::StyledText) -> Vec<Link> {
+    let mut links: Vec<Link> = Vec::new();
+    let mut last_end = None;
+    for run in &styled.runs {
+        let Some(url) = &run.link else {
+            last_end = None;
+            continue;
+        };
+        let text = styled.text.get(run.range.clone()).unwrap_or_default();
+        match links.last_mut() {
+            Some(last) if last.url == *url && last_end == Some(run.range.start) => {
+                last.text.push_str(text)
+            }
+            _ => links.push(Link {
+                text: text.to_string(),
+                url: url.clone(),
+            }),
+        }
+        last_end = Some(run.range.end);
+    }
+    links
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Visible text of an HTML fragment, whitespace collapsed.
+pub(crate) fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for ch in html.chars() {
+        match ch {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(ch),
+            _ => {}
+        }
+    }
+    decode_entities(
+        text.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .as_str(),
+    )
+}
+
+fn href(tag: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let start = lower.find("href")? + "href".len();
+    let rest = tag[start..].trim_start().strip_prefix('=')?.trim_start();
+    let value = match rest.chars().next()? {
+        quote @ ('"' | '\'') => rest[1..].split(quote).next()?,
+        _ => rest.split(|c: char| c.is_whitespace() || c == '>').next()?,
+    };
+    Some(decode_entities(value))
+}
+
+/// Extracts the `<a href>` links from an HTML fragment, such as the HTML
+/// flavor browsers put on the clipboard.
+///
+/// ```
+/// let links = get_selected_text::extract_links_from_html(
+///     r#"Read <a href="https://example.com/docs?a=1&amp;b=2">the <b>docs</b></a>."#,
+/// );
+/// assert_eq!(links[0].text, "the docs");
+/// assert_eq!(links[0].url, "https://example.com/docs?a=1&b=2");
+/// ```
+pub fn extract_links_from_html(html: &str) -> Vec<Link> {
+    let lower = html.to_ascii_lowercase();
+    let mut links = Vec::new();
+    let mut position = 0;
+    while let Some(start) = lower[position..].find("<a").map(|i| i + position) {
+        let after = lower[start + 2..].chars().next();
+        let Some(tag_end) = lower[start..].find('>').map(|i| i + start) else {
+            break;
+        };
+        position = tag_end + 1;
+        // skip <abbr>, <area>, ...
+        if !matches!(after, Some(c) if c.is_whitespace()) {
+            continue;
+        }
+        let Some(url) = href(&html[start..tag_end]) else {
+            continue;
+        };
+        let close = lower[position..]
+            .find("</a")
+            .map_or(html.len(), |i| i + position);
+        links.push(Link {
+            text: strip_tags(&html[position..close]),
+            url,
+        });
+        position = close;
+    }
+    links
+}
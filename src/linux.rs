@@ -1,6 +1,321 @@
 use enigo::*;
 
-pub fn get_selected_text() -> Result<String, Box<dyn std::error::Error>> {
-    let mut enigo = Enigo::new(&Settings::default()).unwrap();
-    crate::utils::get_selected_text_by_clipboard(&mut enigo, false)
+use crate::attempt::Attempts;
+use crate::wayland::KeystrokeMethod;
+use crate::Strategy;
+
+/// Runs `f` with the least invasive way of pressing keys the session has.
+fn with_keyboard<T>(
+    f: impl FnOnce(&mut dyn Keyboard) -> Result<T, Box<dyn std::error::Error>>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    match crate::wayland::keystroke_method() {
+        #[cfg(feature = "wayland")]
+        KeystrokeMethod::VirtualKeyboard => f(&mut crate::wayland::VirtualKeyboard::new()?),
+        KeystrokeMethod::Uinput => f(&mut *crate::uinput::keyboard()?),
+        KeystrokeMethod::X11 => f(&mut Enigo::new(&Settings::default())?),
+    }
+}
+
+/// Whether `desktop` (`GNOME`, `KDE`) is one of `XDG_CURRENT_DESKTOP`'s.
+pub(crate) fn is_desktop(desktop: &str) -> bool {
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .is_ok_and(|desktops| desktops.split(':').any(|name| name == desktop))
+}
+
+/// Where the PRIMARY selection is read from without key presses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PrimarySource {
+    DataControl,
+    /// The companion GNOME Shell extension.
+    #[cfg(feature = "session-bus")]
+    GnomeShell,
+    X11,
+}
+
+fn primary_source() -> PrimarySource {
+    match crate::wayland::capabilities() {
+        Some(capabilities) if capabilities.data_control => PrimarySource::DataControl,
+        #[cfg(feature = "session-bus")]
+        Some(_) if crate::gnome::extension_enabled() => PrimarySource::GnomeShell,
+        _ => PrimarySource::X11,
+    }
+}
+
+impl PrimarySource {
+    fn method(self) -> &'static str {
+        match self {
+            Self::DataControl => "data-control",
+            #[cfg(feature = "session-bus")]
+            Self::GnomeShell => "gnome-shell-extension",
+            Self::X11 => "primary-selection",
+        }
+    }
+}
+
+fn read_primary_selection(source: PrimarySource) -> Result<String, Box<dyn std::error::Error>> {
+    use arboard::{Clipboard, GetExtLinux, LinuxClipboardKind};
+
+    match source {
+        PrimarySource::DataControl => Ok(Clipboard::new()?
+            .get()
+            .clipboard(LinuxClipboardKind::Primary)
+            .text()?),
+        #[cfg(feature = "session-bus")]
+        PrimarySource::GnomeShell => crate::gnome::get_selected_text(),
+        PrimarySource::X11 => crate::x11::read_primary_selection(PASSIVE_READ_TIMEOUT),
+    }
+}
+
+pub fn get_selected_text(
+    options: &crate::CaptureOptions,
+    attempts: &mut Attempts,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(strategies) = options.chain() {
+        return get_selected_text_by_strategies(&strategies, options, attempts);
+    }
+    #[cfg(feature = "session-bus")]
+    if crate::portal::is_sandboxed() {
+        return copy_selected_text(options, attempts);
+    }
+    // on Wayland the compositor or the Shell hands the selection over, no key
+    // presses needed
+    let source = primary_source();
+    if source != PrimarySource::X11 {
+        return attempts.record(Strategy::Accessibility, source.method(), || {
+            read_primary_selection(source)
+        });
+    }
+    if let Some(text) = chromium_selected_text(attempts) {
+        return Ok(text);
+    }
+    // selecting in a terminal sets PRIMARY, ctrl+c would interrupt it
+    if crate::terminal::focused() {
+        if let Ok(text) = attempts.record(Strategy::Accessibility, source.method(), || {
+            read_primary_selection(source)
+        }) {
+            return Ok(text);
+        }
+    }
+    copy_selected_text(options, attempts)
+}
+
+/// Copies the selection with ctrl+c, through the portal in a sandbox.
+fn copy_selected_text(
+    options: &crate::CaptureOptions,
+    attempts: &mut Attempts,
+) -> Result<String, Box<dyn std::error::Error>> {
+    #[cfg(feature = "session-bus")]
+    if crate::portal::is_sandboxed() {
+        return attempts.record(Strategy::Clipboard, "portal", || {
+            crate::terminal::ensure_not_focused()?;
+            crate::portal::get_selected_text(&options.cancel)
+        });
+    }
+    attempts.record(Strategy::Clipboard, "clipboard", || {
+        crate::terminal::ensure_not_focused()?;
+        with_keyboard(|keyboard| {
+            crate::utils::get_selected_text_by_clipboard(keyboard, false, &options.cancel)
+        })
+    })
+}
+
+fn get_selected_text_by_strategies(
+    strategies: &[Strategy],
+    options: &crate::CaptureOptions,
+    attempts: &mut Attempts,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let found =
+        crate::options::try_strategies(strategies, &crate::active_app_id(), options, |strategy| {
+            let text = match strategy {
+                Strategy::AppSpecific => return Ok(None),
+                Strategy::Accessibility => match chromium_selected_text(attempts) {
+                    Some(text) => text,
+                    None => {
+                        let source = primary_source();
+                        attempts.record(Strategy::Accessibility, source.method(), || {
+                            read_primary_selection(source)
+                        })?
+                    }
+                },
+                Strategy::Clipboard => copy_selected_text(options, attempts)?,
+                Strategy::Ocr => return Err(crate::unsupported_error()),
+            };
+            Ok((!text.is_empty()).then_some(text))
+        })?;
+    Ok(found.unwrap_or_default())
+}
+
+/// The selection of a focused Chromium or Electron app, read over AT-SPI.
+fn chromium_selected_text(attempts: &mut Attempts) -> Option<String> {
+    #[cfg(feature = "session-bus")]
+    return attempts.record_found(
+        Strategy::Accessibility,
+        "at-spi",
+        crate::atspi::get_chromium_selected_text,
+    );
+    #[cfg(not(feature = "session-bus"))]
+    {
+        let _ = attempts;
+        None
+    }
+}
+
+pub(crate) fn get_selected_text_and_html(
+) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
+    crate::terminal::ensure_not_focused()?;
+    with_keyboard(crate::utils::get_selected_text_and_html_by_clipboard)
+}
+
+/// Reads the PRIMARY selection, which X11 and most Wayland compositors keep in
+/// sync with the current selection without any key presses.
+pub(crate) fn get_selected_text_passively() -> Result<String, Box<dyn std::error::Error>> {
+    #[cfg(feature = "session-bus")]
+    if let Some(text) = crate::atspi::get_chromium_selected_text() {
+        return Ok(text);
+    }
+    read_primary_selection(primary_source())
+}
+
+const PASSIVE_READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+pub(crate) fn diagnose() -> crate::Diagnostics {
+    use crate::CaptureMethod;
+
+    #[cfg(feature = "session-bus")]
+    let sandboxed = crate::portal::is_sandboxed();
+    // without a portal sandboxed captures copy like everywhere else
+    #[cfg(not(feature = "session-bus"))]
+    let sandboxed = false;
+    let capture_method = match primary_source() {
+        _ if sandboxed => CaptureMethod::Portal,
+        PrimarySource::DataControl => CaptureMethod::DataControl,
+        #[cfg(feature = "session-bus")]
+        PrimarySource::GnomeShell => CaptureMethod::GnomeShellExtension,
+        PrimarySource::X11 => match crate::wayland::keystroke_method() {
+            #[cfg(feature = "wayland")]
+            KeystrokeMethod::VirtualKeyboard => CaptureMethod::VirtualKeyboard,
+            KeystrokeMethod::Uinput => CaptureMethod::Uinput,
+            KeystrokeMethod::X11 => CaptureMethod::XTest,
+        },
+    };
+    let mut diagnostics = crate::Diagnostics::new(capture_method);
+    diagnostics.sandboxed = sandboxed;
+    diagnostics.desktop = std::env::var("XDG_CURRENT_DESKTOP").ok();
+
+    let wayland = crate::wayland::capabilities();
+    diagnostics.session_type = if wayland.is_some() {
+        Some("wayland".to_string())
+    } else if std::env::var_os("DISPLAY").is_some() {
+        Some("x11".to_string())
+    } else {
+        None
+    };
+    diagnostics.capability("x11", x11rb::connect(None).is_ok());
+    if let Some(wayland) = wayland {
+        diagnostics.capability("data-control", wayland.data_control);
+        diagnostics.capability("virtual-keyboard", wayland.virtual_keyboard);
+        diagnostics.capability("uinput", wayland.uinput);
+    }
+    #[cfg(feature = "session-bus")]
+    let (portal, at_spi) = {
+        if is_desktop("GNOME") {
+            diagnostics.capability("gnome-shell-extension", crate::gnome::extension_enabled());
+        }
+        if is_desktop("KDE") {
+            diagnostics.capability("klipper", crate::klipper::is_running());
+        }
+        (
+            sandboxed && diagnostics.capability("portal", crate::portal::is_available()),
+            diagnostics.capability("at-spi", crate::atspi::is_available()),
+        )
+    };
+    #[cfg(not(feature = "session-bus"))]
+    let (portal, at_spi) = (false, false);
+
+    let mut hints = Vec::new();
+    if diagnostics.session_type.is_none() {
+        hints.push(
+            "Neither WAYLAND_DISPLAY nor DISPLAY is set, there is no session to capture from.",
+        );
+    }
+    if sandboxed && !portal {
+        hints.push(
+            "No desktop portal is running: install xdg-desktop-portal and your desktop's backend.",
+        );
+    }
+    if wayland.is_some() && capture_method == CaptureMethod::XTest {
+        hints.push(if cfg!(not(feature = "wayland")) {
+            "Built without the wayland feature, only XWayland apps can be captured: give write access to /dev/uinput (e.g. join the input group)."
+        } else if is_desktop("GNOME") {
+            "GNOME offers neither data-control nor a virtual keyboard, only XWayland apps can be captured: enable the companion Shell extension (gnome-extension/ in the repository)."
+        } else {
+            "The compositor offers neither data-control nor a virtual keyboard, only XWayland apps can be captured: give write access to /dev/uinput (e.g. join the input group)."
+        });
+    }
+    if cfg!(feature = "session-bus") && !at_spi {
+        hints.push("The accessibility bus isn't running: Chromium and Electron apps go through the clipboard, and the watcher polls.");
+    }
+    diagnostics.hints = hints.into_iter().map(String::from).collect();
+    diagnostics
+}
+
+pub fn set_selected_text(_text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err(Box::new(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Setting the selected text is not supported on this platform",
+    )))
+}
+
+pub fn replace_selected_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "session-bus")]
+    if crate::portal::is_sandboxed() {
+        return crate::portal::paste_text(text);
+    }
+    with_keyboard(|keyboard| crate::utils::paste_text_by_clipboard(keyboard, text))
+}
+
+pub fn type_text(
+    text: &str,
+    interval: std::time::Duration,
+    cancel: &crate::CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // the virtual keyboard and uinput keymaps hold only the shortcut keys,
+    // arbitrary characters need XTest
+    if crate::wayland::keystroke_method() != KeystrokeMethod::X11 {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Typing text needs an X11 session",
+        )));
+    }
+    let mut enigo = Enigo::new(&Settings::default())?;
+    crate::utils::type_text(&mut enigo, text, interval, cancel)
+}
+
+pub use crate::utils::{
+    get_clipboard_files, get_clipboard_kind, get_clipboard_text, mouse_position,
+};
+
+pub fn displays() -> Result<Vec<crate::Display>, Box<dyn std::error::Error>> {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::randr::ConnectionExt;
+
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let monitors = conn.randr_get_monitors(root, true)?.reply()?;
+    Ok(monitors
+        .monitors
+        .iter()
+        .map(|monitor| crate::Display {
+            id: monitor.outputs.first().copied().unwrap_or_default(),
+            frame: crate::Rect {
+                x: monitor.x as f64,
+                y: monitor.y as f64,
+                width: monitor.width as f64,
+                height: monitor.height as f64,
+            },
+            // X11 has no per-monitor scaling, toolkits scale everything uniformly
+            scale_factor: 1.0,
+            is_primary: monitor.primary,
+        })
+        .collect())
 }
@@ -1,139 +1,735 @@
-use std::num::NonZeroUsize;
-
-use accessibility_ng::{AXAttribute, AXUIElement};
-use accessibility_sys_ng::{kAXFocusedUIElementAttribute, kAXSelectedTextAttribute};
-use active_win_pos_rs::get_active_window;
-use core_foundation::string::CFString;
-use debug_print::debug_println;
-use lru::LruCache;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use accessibility_ng::{AXAttribute, AXUIElement, AXValue};
+use accessibility_sys_ng::{
+    kAXAttributedStringForRangeParameterizedAttribute, kAXButtonRole, kAXCheckBoxRole,
+    kAXChildrenAttribute, kAXColorWellRole, kAXDialogSubrole, kAXDisclosureTriangleRole,
+    kAXEnabledAttribute, kAXErrorSuccess, kAXFocusedApplicationAttribute,
+    kAXFocusedUIElementAttribute, kAXFocusedWindowAttribute, kAXHiddenAttribute,
+    kAXIncrementorRole, kAXMenuBarAttribute, kAXMenuButtonRole, kAXMenuItemCmdCharAttribute,
+    kAXMenuItemCmdModifiersAttribute, kAXMenuItemRole, kAXModalAttribute, kAXOutlineRole,
+    kAXParentAttribute, kAXPopUpButtonRole, kAXProgressIndicatorRole, kAXRadioButtonRole,
+    kAXRoleAttribute, kAXScrollBarRole, kAXSelectedRowsAttribute, kAXSelectedTextAttribute,
+    kAXSheetRole, kAXSliderRole, kAXSplitterRole, kAXStringForRangeParameterizedAttribute,
+    kAXSubroleAttribute, kAXSystemDialogSubrole, kAXTableRole, kAXTextFieldRole, kAXTitleAttribute,
+    kAXToolbarRole, kAXURLAttribute, kAXValueAttribute, kAXValueIndicatorRole, kAXWindowAttribute,
+    kAXWindowsAttribute, AXError, AXUIElementRef,
+};
+#[cfg(feature = "ax-record")]
+use accessibility_sys_ng::{
+    kAXErrorNoValue, kAXValueTypeCFRange, kAXValueTypeCGPoint, kAXValueTypeCGRect,
+    kAXValueTypeCGSize,
+};
+use cocoa::appkit::NSPasteboardTypeString;
+use cocoa::base::{id, nil};
+use cocoa::foundation::{NSInteger, NSRange, NSString};
+use core_foundation::array::CFArray;
+use core_foundation::base::{
+    kCFAllocatorDefault, CFAllocatorRef, CFIndex, CFOptionFlags, CFRange, CFRelease, CFType,
+    CFTypeRef, TCFType,
+};
+use core_foundation::boolean::CFBoolean;
+use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+use core_foundation::number::CFNumber;
+use core_foundation::string::{CFString, CFStringRef};
+use core_foundation::url::CFURL;
+use core_foundation::ConcreteCFType;
+use core_graphics::display::CGDisplay;
+use core_graphics::event::{CGEvent, CGEventTapLocation};
+use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+use core_graphics::geometry::CGRect;
+#[cfg(feature = "ax-record")]
+use core_graphics::geometry::{CGPoint, CGSize};
+use core_graphics::window::{
+    copy_window_info, kCGWindowBounds, kCGWindowListOptionIncludingWindow, kCGWindowOwnerPID,
+    CGWindowID,
+};
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel};
+use objc::{class, msg_send, sel, sel_impl};
 use parking_lot::Mutex;
 
-use crate::SelectedText;
+use crate::attempt::Attempts;
+use crate::cancel::{cancelled_error, CancellationToken};
+use crate::diagnose::{SelfTestResult, SELF_TEST_TEXT};
+use crate::expand::{utf16_to_byte_offset, TextUnit};
+use crate::file_paths::parse_file_paths;
+use crate::GetTextError;
+use crate::{
+    AxNode, CaptureOptions, ClipboardKind, Display, ExpandedSelection, Focus, Point, Rect,
+    SelectedText, Strategy, StyleRun, StyledText, Table, WindowSelection,
+};
 
-static GET_SELECTED_TEXT_METHOD: Mutex<Option<LruCache<String, u8>>> = Mutex::new(None);
+// captures from several threads would fight over the clipboard
+static CAPTURE: Mutex<()> = Mutex::new(());
 
-// TDO: optimize / refactor / test later
-fn split_file_paths(input: &str) -> Vec<String> {
-    let mut paths = Vec::new();
-    let mut current_path = String::new();
-    let mut in_quotes = false;
-
-    for ch in input.chars() {
-        match ch {
-            '\'' => {
-                current_path.push(ch);
-                in_quotes = !in_quotes;
-                if !in_quotes {
-                    paths.push(current_path.clone());
-                    current_path.clear();
-                }
-            }
-            ' ' if !in_quotes => {
-                if !current_path.is_empty() {
-                    paths.push(current_path.clone());
-                    current_path.clear();
-                }
-            }
-            _ => current_path.push(ch),
+/// The bundle id, which unlike the display name isn't localized and tells
+/// same-named apps apart. The name for the rare apps without one.
+fn app_id(selected_text: &SelectedText) -> String {
+    selected_text
+        .bundle_id
+        .clone()
+        .unwrap_or_else(|| selected_text.app_name.clone())
+}
+
+/// Finder's selected files are the selection, also with no app frontmost as
+/// the desktop is Finder's.
+fn in_finder(selected_text: &SelectedText) -> bool {
+    selected_text.bundle_id.as_deref() == Some("com.apple.finder")
+        || selected_text.focus == Focus::NoFrontmostApp
+}
+
+/// Looks at bundle ids rather than names, which are localized and can be
+/// anything.
+fn focus(bundle_id: Option<&str>, window_id: &str) -> Focus {
+    // Mission Control leaves the frontmost app alone and focuses the Dock
+    let focused_app_id =
+        ax_attribute::<AXUIElement>(&AXUIElement::system_wide(), kAXFocusedApplicationAttribute)
+            .and_then(|app| app.pid().ok())
+            .and_then(|pid| app_bundle_info(pid).0);
+    if focused_app_id.as_deref() == Some("com.apple.dock") {
+        return Focus::MissionControl;
+    }
+    match bundle_id {
+        Some("com.apple.ScreenSaver.Engine" | "com.apple.ScreenSaver.legacyScreenSaver") => {
+            Focus::ScreenSaver
         }
+        Some("com.apple.loginwindow") => Focus::LockScreen,
+        Some("com.apple.finder") if window_id.is_empty() => Focus::Desktop,
+        Some(_) => Focus::App,
+        None if focused_app_id.is_some() => Focus::App,
+        None => Focus::NoFrontmostApp,
     }
+}
 
-    if !current_path.is_empty() {
-        paths.push(current_path);
+extern "C" fn app_launched_or_terminated(_this: &Object, _cmd: Sel, notification: id) {
+    unsafe {
+        let user_info: id = msg_send![notification, userInfo];
+        if user_info == nil {
+            return;
+        }
+        let app = dictionary_value(user_info, "NSWorkspaceApplicationKey");
+        if app == nil {
+            return;
+        }
+        // keyed like app_id
+        let app_id = nsstring_to_string(msg_send![app, bundleIdentifier])
+            .or_else(|| nsstring_to_string(msg_send![app, localizedName]));
+        if let Some(app_id) = app_id {
+            crate::stats::forget(&app_id);
+        }
     }
+}
 
-    paths
+/// The observer's class. Named after this copy's callback: two copies of
+/// the crate loaded into one process each declare their own.
+#[cfg_attr(not(feature = "method-cache"), allow(dead_code))]
+fn app_observer_class() -> Option<&'static Class> {
+    static CLASS: OnceLock<Option<&'static Class>> = OnceLock::new();
+    *CLASS.get_or_init(|| {
+        let callback = app_launched_or_terminated as extern "C" fn(&Object, Sel, id);
+        let name = format!("GSTAppObserver{:x}", callback as usize);
+        Class::get(&name).or_else(|| {
+            let mut decl = ClassDecl::new(&name, class!(NSObject))?;
+            unsafe { decl.add_method(sel!(appLaunchedOrTerminated:), callback) };
+            Some(decl.register())
+        })
+    })
+}
+
+const APP_NOTIFICATIONS: [&str; 2] = [
+    "NSWorkspaceDidLaunchApplicationNotification",
+    "NSWorkspaceDidTerminateApplicationNotification",
+];
+
+/// Forgets an app's method statistics when it quits or launches, as a
+/// relaunched or updated app can behave differently, until dropped.
+/// Delivered on the main run loop, which any app with a UI runs.
+#[cfg_attr(not(feature = "method-cache"), allow(dead_code))]
+pub(crate) struct AppObserver(id);
+
+// the notification center can be used from any thread
+unsafe impl Send for AppObserver {}
+
+#[cfg_attr(not(feature = "method-cache"), allow(dead_code))]
+impl AppObserver {
+    pub(crate) fn start() -> Option<Self> {
+        let class = app_observer_class()?;
+        unsafe {
+            let observer: id = msg_send![class, new];
+            let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+            let center: id = msg_send![workspace, notificationCenter];
+            for name in APP_NOTIFICATIONS {
+                let name = NSString::alloc(nil).init_str(name);
+                let _: () = msg_send![center, addObserver: observer
+                                               selector: sel!(appLaunchedOrTerminated:)
+                                                   name: name
+                                                 object: nil];
+                let _: () = msg_send![name, release];
+            }
+            Some(Self(observer))
+        }
+    }
+}
+
+impl Drop for AppObserver {
+    fn drop(&mut self) {
+        unsafe {
+            let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+            let center: id = msg_send![workspace, notificationCenter];
+            let _: () = msg_send![center, removeObserver: self.0];
+            let _: () = msg_send![self.0, release];
+        }
+    }
 }
 
-pub fn get_selected_text() -> Result<SelectedText, Box<dyn std::error::Error>> {
-    if GET_SELECTED_TEXT_METHOD.lock().is_none() {
-        let cache = LruCache::new(NonZeroUsize::new(100).unwrap());
-        *GET_SELECTED_TEXT_METHOD.lock() = Some(cache);
+pub(crate) fn init() -> Result<(), Box<dyn std::error::Error>> {
+    if !crate::has_permissions() {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "The app has no Accessibility access",
+        )));
     }
-    let mut cache = GET_SELECTED_TEXT_METHOD.lock();
-    let cache = cache.as_mut().unwrap();
-    let app_name = match get_active_window() {
-        Ok(window) => window.app_name,
+    Ok(())
+}
+
+pub fn get_selected_text(
+    options: &CaptureOptions,
+    attempts: &mut Attempts,
+) -> Result<SelectedText, Box<dyn std::error::Error>> {
+    // read first, popups anchor to where the user was, not where the mouse is
+    // once the slower capture paths are done
+    let mouse_position = mouse_position().ok();
+    let selected_text = match crate::window::active_window() {
+        Ok(window) => {
+            let process_id = window.process_id as i32;
+            let (bundle_id, app_version) = app_bundle_info(process_id);
+            SelectedText {
+                focus: focus(bundle_id.as_deref(), &window.window_id),
+                app_name: window.app_name,
+                bundle_id,
+                process_id: Some(process_id),
+                app_version,
+                window_id: window.window_id.parse().ok(),
+                mouse_position,
+                ..Default::default()
+            }
+        }
         Err(_) => {
-            // user might be in the desktop / home view
-            String::new()
+            // no app is frontmost, e.g. during a space switch
+            SelectedText {
+                focus: focus(None, ""),
+                mouse_position,
+                ..Default::default()
+            }
         }
     };
+    if let Some(strategies) = options.chain() {
+        return get_selected_text_by_strategies(selected_text, &strategies, options, attempts);
+    }
+    let window_id = selected_text.window_id;
+
+    let result = get_selected_text_by_methods(selected_text.clone(), options, attempts);
+    let found = matches!(&result, Ok(selected) if selected.text.iter().any(|t| !t.is_empty()));
+    if !options.ocr || found || options.cancel.is_cancelled() {
+        return result;
+    }
+    match attempts.record(Strategy::Ocr, "vision", || {
+        crate::ocr::recognize_selection(window_id, &options.ocr_options)
+    }) {
+        Ok(text) if !text.is_empty() => Ok(SelectedText {
+            text: vec![text],
+            ..selected_text
+        }),
+        _ => result,
+    }
+}
 
-    if app_name == "Finder" || app_name.is_empty() {
-        if let Ok(text) = get_selected_file_paths_by_clipboard_using_applescript() {
-            return Ok(SelectedText {
-                is_file_paths: true,
-                app_name: app_name,
-                text: split_file_paths(&text),
-            });
+/// Captures from `selected_text`'s app as [`get_selected_text`] does, while
+/// a [`crate::recording::Replay`] answers the accessibility queries.
+#[cfg(feature = "ax-record")]
+pub(crate) fn replay_capture(
+    selected_text: SelectedText,
+    options: &CaptureOptions,
+) -> Result<SelectedText, Box<dyn std::error::Error>> {
+    let mut attempts = Attempts::default();
+    match options.chain() {
+        Some(strategies) => {
+            get_selected_text_by_strategies(selected_text, &strategies, options, &mut attempts)
         }
+        None => get_selected_text_by_methods(selected_text, options, &mut attempts),
     }
+}
 
-    let mut selected_text = SelectedText {
-        is_file_paths: false,
-        app_name: app_name.clone(),
-        text: vec![],
-    };
+fn get_selected_text_by_strategies(
+    mut selected_text: SelectedText,
+    strategies: &[Strategy],
+    options: &CaptureOptions,
+    attempts: &mut Attempts,
+) -> Result<SelectedText, Box<dyn std::error::Error>> {
+    let window_id = selected_text.window_id;
+    // while a file name is edited, its text is the selection
+    let in_finder = in_finder(&selected_text) && !text_field_focused();
+    let found =
+        crate::options::try_strategies(strategies, &app_id(&selected_text), options, |strategy| {
+            let (text, is_file_paths) = match strategy {
+                Strategy::AppSpecific if in_finder => {
+                    let paths = attempts.record(
+                        Strategy::AppSpecific,
+                        "finder-applescript",
+                        get_selected_file_paths_by_clipboard_using_applescript,
+                    )?;
+                    (parse_file_paths(&paths), true)
+                }
+                Strategy::AppSpecific => return Ok(None),
+                Strategy::Accessibility => (
+                    vec![attempts.record(
+                        Strategy::Accessibility,
+                        "ax",
+                        get_selected_text_by_ax,
+                    )?],
+                    false,
+                ),
+                Strategy::Clipboard => (
+                    vec![attempts.record(Strategy::Clipboard, "applescript", || {
+                        get_selected_text_by_clipboard(&mut selected_text, options)
+                    })?],
+                    false,
+                ),
+                Strategy::Ocr => (
+                    vec![attempts.record(Strategy::Ocr, "vision", || {
+                        #[cfg(feature = "ax-record")]
+                        crate::recording::not_replayed("OCR")?;
+                        crate::ocr::recognize_selection(window_id, &options.ocr_options)
+                    })?],
+                    false,
+                ),
+            };
+            Ok(text
+                .iter()
+                .any(|text| !text.is_empty())
+                .then_some((text, is_file_paths)))
+        })?;
+    if let Some((text, is_file_paths)) = found {
+        selected_text.text = text;
+        selected_text.is_file_paths = is_file_paths;
+    }
+    Ok(selected_text)
+}
 
-    if let Some(text) = cache.get(&app_name) {
-        if *text == 0 {
-            let ax_text = get_selected_text_by_ax()?;
-            if !ax_text.is_empty() {
-                cache.put(app_name.clone(), 0);
-                selected_text.text = vec![ax_text];
-                return Ok(selected_text);
+fn get_selected_text_by_methods(
+    mut selected_text: SelectedText,
+    options: &CaptureOptions,
+    attempts: &mut Attempts,
+) -> Result<SelectedText, Box<dyn std::error::Error>> {
+    let _capture = CAPTURE.lock();
+
+    if in_finder(&selected_text) && !options.cancel.is_cancelled() {
+        // a file name being edited, its text is the selection rather than the
+        // file (and copying the path would copy the text instead)
+        if text_field_focused() {
+            match attempts.record(Strategy::Accessibility, "ax", get_selected_text_by_ax) {
+                Ok(text) if !text.is_empty() => {
+                    selected_text.text = vec![text];
+                    return Ok(selected_text);
+                }
+                Ok(_) => {}
+                Err(e) => selected_text
+                    .warnings
+                    .push(format!("Reading the selected text failed: {e}")),
             }
         }
-        let txt = get_selected_text_by_clipboard_using_applescript()?;
-        selected_text.text = vec![txt];
-        return Ok(selected_text);
-    }
-    match get_selected_text_by_ax() {
-        Ok(txt) => {
-            if !txt.is_empty() {
-                cache.put(app_name.clone(), 0);
+        match attempts.record(
+            Strategy::AppSpecific,
+            "finder-applescript",
+            get_selected_file_paths_by_clipboard_using_applescript,
+        ) {
+            Ok(text) => {
+                if options.cancel.is_cancelled() {
+                    return Err(cancelled_error());
+                }
+                selected_text.is_file_paths = true;
+                selected_text.text = parse_file_paths(&text);
+                return Ok(selected_text);
             }
-            selected_text.text = vec![txt];
-            Ok(selected_text)
+            Err(e) => selected_text
+                .warnings
+                .push(format!("Reading the selected files failed: {e}")),
         }
-        Err(_) => match get_selected_text_by_clipboard_using_applescript() {
-            Ok(txt) => {
-                if !txt.is_empty() {
-                    cache.put(app_name, 1);
+    }
+
+    // AX reading an empty selection means nothing is selected, copying only
+    // follows when it failed, unless the caller's strategies ask for it. Empty
+    // reads count as misses, so an app whose AX always reports "" gets AX
+    // demoted and the clipboard tried first.
+    let app = app_id(&selected_text);
+    let mut ax_empty = None;
+    let mut copied_empty = false;
+    let found = crate::options::try_strategies(
+        &[Strategy::Accessibility, Strategy::Clipboard],
+        &app,
+        options,
+        |strategy| {
+            let text = match strategy {
+                Strategy::Accessibility => {
+                    let start = std::time::Instant::now();
+                    let text =
+                        attempts.record(Strategy::Accessibility, "ax", get_selected_text_by_ax)?;
+                    if text.is_empty() {
+                        ax_empty = Some(start.elapsed());
+                    }
+                    text
                 }
-                selected_text.text = vec![txt];
-                Ok(selected_text)
-            }
-            Err(e) => Err(e),
+                _ if ax_empty.is_some() => return Ok(None),
+                _ => {
+                    let text = attempts.record(Strategy::Clipboard, "applescript", || {
+                        get_selected_text_by_clipboard(&mut selected_text, options)
+                    })?;
+                    copied_empty = text.is_empty();
+                    text
+                }
+            };
+            Ok((!text.is_empty()).then_some(text))
         },
+    )?;
+    if let (None, Some(latency)) = (&found, ax_empty) {
+        crate::stats::record_empty(&app, Strategy::Accessibility, latency, copied_empty);
     }
+    selected_text.text = vec![found.unwrap_or_default()];
+    Ok(selected_text)
 }
 
-fn get_selected_text_by_ax() -> Result<String, Box<dyn std::error::Error>> {
-    // debug_println!("get_selected_text_by_ax");
+/// Which strategy will likely read the selection in the running app with
+/// `bundle_id`: AX when its focused element has selected text to report,
+/// the clipboard otherwise. `None` when it isn't running or has no focus to
+/// look at.
+pub(crate) fn probe(bundle_id: &str) -> Option<Strategy> {
+    let pid = unsafe {
+        let bundle_id = NSString::alloc(nil).init_str(bundle_id);
+        let apps: id = msg_send![
+            class!(NSRunningApplication),
+            runningApplicationsWithBundleIdentifier: bundle_id
+        ];
+        let _: () = msg_send![bundle_id, release];
+        let app: id = msg_send![apps, firstObject];
+        if app == nil {
+            return None;
+        }
+        let pid: i32 = msg_send![app, processIdentifier];
+        pid
+    };
+    // apps keep their focused element while in the background
+    let focused =
+        ax_attribute::<AXUIElement>(&AXUIElement::application(pid), kAXFocusedUIElementAttribute)?;
+    Some(
+        if ax_attribute::<CFString>(&focused, kAXSelectedTextAttribute).is_some() {
+            Strategy::Accessibility
+        } else {
+            Strategy::Clipboard
+        },
+    )
+}
+
+/// The frontmost app and its focused window, straight from `NSWorkspace` and
+/// AX. Apps without windows (Finder on the desktop) still report their name
+/// and pid, with an empty title and window id.
+pub(crate) fn active_window() -> Result<crate::window::ActiveWindow, Box<dyn std::error::Error>> {
+    let (process_id, app_name, process_path) = unsafe {
+        let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let app: id = msg_send![workspace, frontmostApplication];
+        if app == nil {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "No frontmost application",
+            )));
+        }
+        let process_id: i32 = msg_send![app, processIdentifier];
+        let executable_url: id = msg_send![app, executableURL];
+        let process_path = if executable_url == nil {
+            None
+        } else {
+            nsstring_to_string(msg_send![executable_url, path])
+        };
+        (
+            process_id,
+            nsstring_to_string(msg_send![app, localizedName]).unwrap_or_default(),
+            process_path,
+        )
+    };
+    let window = ax_attribute::<AXUIElement>(
+        &AXUIElement::application(process_id),
+        kAXFocusedWindowAttribute,
+    );
+    let window_id = window.as_ref().and_then(ax_window_id);
+    Ok(crate::window::ActiveWindow {
+        app_name,
+        title: window
+            .as_ref()
+            .and_then(|window| ax_attribute::<CFString>(window, kAXTitleAttribute))
+            .map(|title| title.to_string())
+            .unwrap_or_default(),
+        process_id: process_id as u64,
+        process_path,
+        window_id: window_id.map(|id| id.to_string()).unwrap_or_default(),
+        frame: window_id.and_then(window_bounds).unwrap_or_default(),
+    })
+}
+
+/// Bundle identifier and short version string of the app running as `pid`.
+pub(crate) fn app_bundle_info(pid: i32) -> (Option<String>, Option<String>) {
+    unsafe {
+        let app: id =
+            msg_send![class!(NSRunningApplication), runningApplicationWithProcessIdentifier: pid];
+        if app == nil {
+            return (None, None);
+        }
+        let bundle_id = nsstring_to_string(msg_send![app, bundleIdentifier]);
+        let bundle_url: id = msg_send![app, bundleURL];
+        let bundle: id = if bundle_url == nil {
+            nil
+        } else {
+            msg_send![class!(NSBundle), bundleWithURL: bundle_url]
+        };
+        let app_version = if bundle == nil {
+            None
+        } else {
+            let key = NSString::alloc(nil).init_str("CFBundleShortVersionString");
+            let version: id = msg_send![bundle, objectForInfoDictionaryKey: key];
+            let _: () = msg_send![key, release];
+            nsstring_to_string(version)
+        };
+        (bundle_id, app_version)
+    }
+}
+
+pub(crate) unsafe fn nsstring_to_string(s: id) -> Option<String> {
+    if s == nil {
+        return None;
+    }
+    let ptr: *const c_char = msg_send![s, UTF8String];
+    if ptr.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+/// Paths of the `public.file-url` items on `pasteboard`.
+pub(crate) unsafe fn pasteboard_file_paths(pasteboard: id) -> Vec<String> {
+    let file_url_type = NSString::alloc(nil).init_str("public.file-url");
+    let items: id = msg_send![pasteboard, pasteboardItems];
+    let count: usize = if items == nil {
+        0
+    } else {
+        msg_send![items, count]
+    };
+
+    let mut paths = Vec::new();
+    for i in 0..count {
+        let item: id = msg_send![items, objectAtIndex: i];
+        let url_string: id = msg_send![item, stringForType: file_url_type];
+        if url_string == nil {
+            continue;
+        }
+        let url: id = msg_send![class!(NSURL), URLWithString: url_string];
+        let path: id = if url == nil {
+            nil
+        } else {
+            msg_send![url, path]
+        };
+        if let Some(path) = nsstring_to_string(path) {
+            paths.push(path);
+        }
+    }
+    let _: () = msg_send![file_url_type, release];
+    paths
+}
+
+pub(crate) unsafe fn pasteboard_text(pasteboard: id) -> Option<String> {
+    let text: id = msg_send![pasteboard, stringForType: NSPasteboardTypeString];
+    nsstring_to_string(text)
+}
+
+unsafe fn general_pasteboard() -> id {
+    msg_send![class!(NSPasteboard), generalPasteboard]
+}
+
+/// Reads the clipboard text without simulating any key presses, empty when
+/// it holds no text.
+pub fn get_clipboard_text() -> Result<String, Box<dyn std::error::Error>> {
+    Ok(unsafe { pasteboard_text(general_pasteboard()) }.unwrap_or_default())
+}
+
+/// Reads the file paths on the clipboard without simulating any key presses.
+pub fn get_clipboard_files() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Ok(unsafe { pasteboard_file_paths(general_pasteboard()) })
+}
+
+/// Inspects what kind of content the clipboard holds.
+pub fn get_clipboard_kind() -> Result<ClipboardKind, Box<dyn std::error::Error>> {
+    let types: Vec<String> = unsafe {
+        let types: id = msg_send![general_pasteboard(), types];
+        let count: usize = if types == nil {
+            0
+        } else {
+            msg_send![types, count]
+        };
+        (0..count)
+            .filter_map(|i| nsstring_to_string(msg_send![types, objectAtIndex: i]))
+            .collect()
+    };
+    let has = |uti: &str| types.iter().any(|t| t == uti);
+    Ok(if types.is_empty() {
+        ClipboardKind::Empty
+    } else if has("public.file-url") {
+        ClipboardKind::Files
+    } else if has("public.utf8-plain-text") {
+        ClipboardKind::Text
+    } else if has("public.png") || has("public.tiff") {
+        ClipboardKind::Image
+    } else {
+        ClipboardKind::Other
+    })
+}
+
+pub(crate) fn get_selected_text_passively() -> Result<String, Box<dyn std::error::Error>> {
+    get_selected_text_by_ax()
+}
+
+pub(crate) fn diagnose() -> crate::Diagnostics {
+    let mut diagnostics = crate::Diagnostics::new(crate::CaptureMethod::Accessibility);
+    let trusted = macos_accessibility_client::accessibility::application_is_trusted();
+    if !diagnostics.capability("accessibility-permission", trusted) {
+        diagnostics.hints.push(
+            "Grant the app Accessibility access in System Settings > Privacy & Security, nothing can be captured without it."
+                .to_string(),
+        );
+    }
+    for name in running_clipboard_managers() {
+        diagnostics.hints.push(format!(
+            "{name} is running: copies made for captures are marked transient, make sure it's set to skip those or the selections end up in its history."
+        ));
+    }
+    diagnostics
+}
+
+// bundle id and name of the clipboard managers known to watch the pasteboard
+const CLIPBOARD_MANAGERS: &[(&str, &str)] = &[
+    ("com.wiheads.paste", "Paste"),
+    ("com.wiheads.paste-setapp", "Paste"),
+    ("org.p0deje.Maccy", "Maccy"),
+    ("com.runningwithcrayons.Alfred", "Alfred"),
+    ("com.raycast.macos", "Raycast"),
+    ("com.tapbots.Pastebot2Mac", "Pastebot"),
+    ("com.fiplab.copyclip2", "CopyClip"),
+];
+
+fn running_clipboard_managers() -> Vec<&'static str> {
+    let mut names: Vec<_> = CLIPBOARD_MANAGERS
+        .iter()
+        .filter(|(bundle_id, _)| unsafe {
+            let bundle_id = NSString::alloc(nil).init_str(bundle_id);
+            let apps: id = msg_send![
+                class!(NSRunningApplication),
+                runningApplicationsWithBundleIdentifier: bundle_id
+            ];
+            let _: () = msg_send![bundle_id, release];
+            let count: usize = msg_send![apps, count];
+            count > 0
+        })
+        .map(|&(_, name)| name)
+        .collect();
+    names.dedup();
+    names
+}
+
+fn focused_element() -> Result<AXUIElement, Box<dyn std::error::Error>> {
     let system_element = AXUIElement::system_wide();
-    let Some(selected_element) = system_element
-        .attribute(&AXAttribute::new(&CFString::from_static_string(
-            kAXFocusedUIElementAttribute,
-        )))
-        .map(|element| element.downcast_into::<AXUIElement>())
-        .ok()
-        .flatten()
-    else {
+    let Some(selected_element) = ax_read(
+        &system_element,
+        &AXAttribute::new(&CFString::from_static_string(kAXFocusedUIElementAttribute)),
+    )
+    .map_err(|source| GetTextError::Accessibility {
+        attribute: kAXFocusedUIElementAttribute,
+        source: source.into(),
+    })?
+    .downcast_into::<AXUIElement>() else {
         return Err(Box::new(std::io::Error::new(
             std::io::ErrorKind::NotFound,
             "No selected element",
         )));
     };
-    let Some(selected_text) = selected_element
-        .attribute(&AXAttribute::new(&CFString::from_static_string(
-            kAXSelectedTextAttribute,
-        )))
-        .map(|text| text.downcast_into::<CFString>())
+    Ok(selected_element)
+}
+
+fn text_field_focused() -> bool {
+    focused_element()
         .ok()
-        .flatten()
+        .and_then(|element| ax_attribute::<CFString>(&element, kAXRoleAttribute))
+        .is_some_and(|role| role == CFString::from_static_string(kAXTextFieldRole))
+}
+
+// controls that hold no selection, where cmd+c can do something else
+const CONTROL_ROLES: &[&str] = &[
+    kAXButtonRole,
+    kAXCheckBoxRole,
+    kAXColorWellRole,
+    kAXDisclosureTriangleRole,
+    kAXIncrementorRole,
+    kAXMenuButtonRole,
+    kAXMenuItemRole,
+    kAXPopUpButtonRole,
+    kAXProgressIndicatorRole,
+    kAXRadioButtonRole,
+    kAXScrollBarRole,
+    kAXSliderRole,
+    kAXSplitterRole,
+    kAXToolbarRole,
+    kAXValueIndicatorRole,
+];
+
+/// The role of the focused element when it's one of [`CONTROL_ROLES`].
+fn focused_control_role() -> Option<String> {
+    let role = ax_attribute::<CFString>(&focused_element().ok()?, kAXRoleAttribute)?.to_string();
+    CONTROL_ROLES.contains(&role.as_str()).then_some(role)
+}
+
+/// Whether the frontmost app shows a modal alert, a dialog or a sheet.
+fn dialog_in_front() -> bool {
+    let Some(window) =
+        ax_attribute::<AXUIElement>(&AXUIElement::system_wide(), kAXFocusedApplicationAttribute)
+            .and_then(|app| ax_attribute::<AXUIElement>(&app, kAXFocusedWindowAttribute))
     else {
+        return false;
+    };
+    let subrole = ax_attribute::<CFString>(&window, kAXSubroleAttribute);
+    subrole.is_some_and(|subrole| {
+        subrole == CFString::from_static_string(kAXDialogSubrole)
+            || subrole == CFString::from_static_string(kAXSystemDialogSubrole)
+    }) || ax_attribute::<CFBoolean>(&window, kAXModalAttribute).is_some_and(bool::from)
+        || ax_children(&window, kAXChildrenAttribute)
+            .iter()
+            .any(|child| {
+                ax_attribute::<CFString>(child, kAXRoleAttribute)
+                    .is_some_and(|role| role == CFString::from_static_string(kAXSheetRole))
+            })
+}
+
+fn get_selected_text_by_ax() -> Result<String, Box<dyn std::error::Error>> {
+    // debug_println!("get_selected_text_by_ax");
+    let selected_element = focused_element()?;
+    let Some(selected_text) = ax_read(
+        &selected_element,
+        &AXAttribute::new(&CFString::from_static_string(kAXSelectedTextAttribute)),
+    )
+    .map_err(|source| GetTextError::Accessibility {
+        attribute: kAXSelectedTextAttribute,
+        source: source.into(),
+    })?
+    .downcast_into::<CFString>() else {
         return Err(Box::new(std::io::Error::new(
             std::io::ErrorKind::NotFound,
             "No selected text",
@@ -142,120 +738,1319 @@ fn get_selected_text_by_ax() -> Result<String, Box<dyn std::error::Error>> {
     Ok(selected_text.to_string())
 }
 
-const REGULAR_TEXT_COPY_APPLE_SCRIPT: &str = r#"
-use AppleScript version "2.4"
-use scripting additions
-use framework "Foundation"
-use framework "AppKit"
+/// Hashes the focused element and its selected range rather than the text,
+/// the text only for elements without a range.
+pub(crate) fn selection_fingerprint() -> Result<u64, Box<dyn std::error::Error>> {
+    use std::hash::{Hash, Hasher};
+
+    let element = focused_element()?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    element.hash(&mut hasher);
+    let range = ax_read(&element, &AXAttribute::selected_text_range())
+        .ok()
+        .and_then(|range| range.get_value::<CFRange>().ok());
+    match range {
+        Some(range) => (range.location, range.length).hash(&mut hasher),
+        None => ax_selected_text(&element).hash(&mut hasher),
+    }
+    Ok(hasher.finish())
+}
+
+// multi-line selections give the box around all lines
+pub(crate) fn get_selection_bounds() -> Result<Rect, Box<dyn std::error::Error>> {
+    let element = focused_element()?;
+    let range = ax_read(&element, &AXAttribute::selected_text_range())?;
+    let bounds = ax_read_parameterized(&element, &AXAttribute::bounds_for_range(), &range)?;
+    let rect: CGRect = bounds.get_value()?;
+    Ok(Rect {
+        x: rect.origin.x,
+        y: rect.origin.y,
+        width: rect.size.width,
+        height: rect.size.height,
+    })
+}
+
+pub(crate) fn get_selected_styled_text() -> Result<StyledText, Box<dyn std::error::Error>> {
+    let element = focused_element()?;
+    let range = ax_read(&element, &AXAttribute::selected_text_range())?;
+    let attributed = ax_read_parameterized(
+        &element,
+        &AXAttribute::<CFType>::new(&CFString::from_static_string(
+            kAXAttributedStringForRangeParameterizedAttribute,
+        )),
+        &range,
+    )?;
+    Ok(unsafe { styled_text_from_attributed_string(attributed.as_CFTypeRef() as id) })
+}
+
+/// The sentence or paragraph around the focused element's selected range,
+/// read a window of text at a time.
+pub(crate) fn expand_selection(
+    unit: TextUnit,
+) -> Result<ExpandedSelection, Box<dyn std::error::Error>> {
+    let element = focused_element()?;
+    let range: CFRange = ax_read(&element, &AXAttribute::selected_text_range())?.get_value()?;
+    let len = ax_read(&element, &AXAttribute::number_of_characters())?
+        .to_i64()
+        .unwrap_or_default();
+    let string_for_range = AXAttribute::<CFType>::new(&CFString::from_static_string(
+        kAXStringForRangeParameterizedAttribute,
+    ));
+    let start = range.location.max(0) as usize;
+    crate::expand::expand(
+        start..start + range.length.max(0) as usize,
+        len.max(0) as usize,
+        |read| {
+            let range =
+                AXValue::from_CFRange(CFRange::init(read.start as CFIndex, read.len() as CFIndex))?;
+            let text = ax_read_parameterized(&element, &string_for_range, &range)?;
+            Ok(text
+                .downcast_into::<CFString>()
+                .map(|text| text.to_string())
+                .unwrap_or_default())
+        },
+        |text, offset| segment(text, offset, unit),
+    )
+}
+
+extern "C" {
+    fn CFStringTokenizerCopyBestStringLanguage(string: CFStringRef, range: CFRange) -> CFStringRef;
+    fn CFLocaleCreate(allocator: CFAllocatorRef, identifier: CFStringRef) -> CFTypeRef;
+    fn CFLocaleCopyCurrent() -> CFTypeRef;
+    fn CFStringTokenizerCreate(
+        allocator: CFAllocatorRef,
+        string: CFStringRef,
+        range: CFRange,
+        options: CFOptionFlags,
+        locale: CFTypeRef,
+    ) -> CFTypeRef;
+    fn CFStringTokenizerGoToTokenAtIndex(tokenizer: CFTypeRef, index: CFIndex) -> CFOptionFlags;
+    fn CFStringTokenizerGetCurrentTokenRange(tokenizer: CFTypeRef) -> CFRange;
+}
+
+const TOKENIZER_UNIT_SENTENCE: CFOptionFlags = 1;
+const TOKENIZER_UNIT_PARAGRAPH: CFOptionFlags = 2;
+
+/// The UTF-16 range of the sentence or paragraph at `offset` into `text`,
+/// split by the rules of the language the text is in, or the user's.
+fn segment(text: &str, offset: usize, unit: TextUnit) -> Option<std::ops::Range<usize>> {
+    let string = CFString::new(text);
+    let range = CFRange::init(0, string.char_len());
+    let options = match unit {
+        TextUnit::Sentence => TOKENIZER_UNIT_SENTENCE,
+        TextUnit::Paragraph => TOKENIZER_UNIT_PARAGRAPH,
+    };
+    unsafe {
+        let language = CFStringTokenizerCopyBestStringLanguage(string.as_concrete_TypeRef(), range);
+        let locale = if language.is_null() {
+            CFLocaleCopyCurrent()
+        } else {
+            let locale = CFLocaleCreate(kCFAllocatorDefault, language);
+            CFRelease(language.cast());
+            locale
+        };
+        let tokenizer = CFStringTokenizerCreate(
+            kCFAllocatorDefault,
+            string.as_concrete_TypeRef(),
+            range,
+            options,
+            locale,
+        );
+        CFRelease(locale);
+        if tokenizer.is_null() {
+            return None;
+        }
+        // 0 is kCFStringTokenizerTokenNone, e.g. between paragraphs
+        let token = (CFStringTokenizerGoToTokenAtIndex(tokenizer, offset as CFIndex) != 0)
+            .then(|| CFStringTokenizerGetCurrentTokenRange(tokenizer));
+        CFRelease(tokenizer);
+        token.map(|token| token.location as usize..(token.location + token.length) as usize)
+    }
+}
+
+const AX_TREE_VALUE_CHARS: usize = 200;
+const AX_TREE_CHILDREN: usize = 100;
+
+pub(crate) fn dump_focused_ax_tree(depth: usize) -> Result<AxNode, Box<dyn std::error::Error>> {
+    Ok(ax_node(&focused_element()?, depth))
+}
+
+fn ax_node(element: &AXUIElement, depth: usize) -> AxNode {
+    let mut node = AxNode::default();
+    for name in element
+        .attribute_names()
+        .iter()
+        .flat_map(|names| names.iter())
+    {
+        let Ok(value) = ax_read(element, &AXAttribute::new(&name)) else {
+            continue;
+        };
+        if let Some(value) = describe_ax_value(&value) {
+            node.attributes.insert(name.to_string(), value);
+        }
+    }
+    if depth > 0 {
+        let children = ax_children(element, kAXChildrenAttribute);
+        node.omitted_children = children.len().saturating_sub(AX_TREE_CHILDREN);
+        node.children = children
+            .iter()
+            .take(AX_TREE_CHILDREN)
+            .map(|child| ax_node(child, depth - 1))
+            .collect();
+    }
+    node
+}
+
+/// Text for an attribute value, `None` for elements and lists of them.
+fn describe_ax_value(value: &CFType) -> Option<String> {
+    if value.instance_of::<AXUIElement>() || value.instance_of::<CFArray>() {
+        return None;
+    }
+    let text = if let Some(text) = value.downcast::<CFString>() {
+        text.to_string()
+    } else if let Some(flag) = value.downcast::<CFBoolean>() {
+        bool::from(flag).to_string()
+    } else if let Some(number) = value.downcast::<CFNumber>() {
+        match number.to_i64() {
+            Some(number) => number.to_string(),
+            None => number.to_f64().unwrap_or_default().to_string(),
+        }
+    } else {
+        format!("{value:?}")
+    };
+    Some(match text.char_indices().nth(AX_TREE_VALUE_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    })
+}
+
+extern "C" {
+    fn CGColorGetNumberOfComponents(color: *const c_void) -> usize;
+    fn CGColorGetComponents(color: *const c_void) -> *const f64;
+}
+
+unsafe fn dictionary_value(dictionary: id, key: &str) -> id {
+    let key = NSString::alloc(nil).init_str(key);
+    let value: id = msg_send![dictionary, objectForKey: key];
+    let _: () = msg_send![key, release];
+    value
+}
+
+unsafe fn cg_color_to_hex(color: id) -> Option<String> {
+    if color == nil {
+        return None;
+    }
+    let color = color as *const c_void;
+    let components = std::slice::from_raw_parts(
+        CGColorGetComponents(color),
+        CGColorGetNumberOfComponents(color),
+    );
+    let (r, g, b) = match components {
+        // gray + alpha
+        [white, _] => (*white, *white, *white),
+        [r, g, b, ..] => (*r, *g, *b),
+        _ => return None,
+    };
+    let channel = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(r),
+        channel(g),
+        channel(b)
+    ))
+}
+
+/// Reads an attributed string as handed out by AX, which uses its own
+/// `AXFont` / `AXForegroundColor` / `AXLink` keys rather than AppKit's.
+unsafe fn styled_text_from_attributed_string(attributed: id) -> StyledText {
+    let text = nsstring_to_string(msg_send![attributed, string]).unwrap_or_default();
+    let length: usize = msg_send![attributed, length];
+    let mut runs = Vec::new();
+    let mut index = 0;
+    while index < length {
+        let mut effective = NSRange::new(0, 0);
+        let attributes: id = msg_send![attributed, attributesAtIndex: index effectiveRange: &mut effective as *mut NSRange as *mut c_void];
+        let end = (effective.location + effective.length) as usize;
+        if end <= index {
+            break;
+        }
+
+        let font = dictionary_value(attributes, "AXFont");
+        let (font_name, font_family, font_size) = if font == nil {
+            (None, None, None)
+        } else {
+            let size = dictionary_value(font, "AXFontSize");
+            (
+                nsstring_to_string(dictionary_value(font, "AXFontName")),
+                nsstring_to_string(dictionary_value(font, "AXFontFamily")),
+                (size != nil).then(|| msg_send![size, doubleValue]),
+            )
+        };
+        // AX has no bold / italic traits, font names carry them ("Helvetica-BoldOblique")
+        let traits = font_name.as_deref().unwrap_or_default().to_lowercase();
+        let underline = dictionary_value(attributes, "AXUnderline");
+        let underline = underline != nil && {
+            let style: NSInteger = msg_send![underline, integerValue];
+            style != 0
+        };
+        let link = dictionary_value(attributes, "AXLink");
+        let link = if link == nil {
+            None
+        } else {
+            let element = AXUIElement::wrap_under_get_rule(link as AXUIElementRef);
+            ax_attribute::<CFURL>(&element, kAXURLAttribute).map(|url| url.get_string().to_string())
+        };
+
+        runs.push(StyleRun {
+            range: utf16_to_byte_offset(&text, index)..utf16_to_byte_offset(&text, end),
+            font_name,
+            font_family,
+            font_size,
+            bold: traits.contains("bold") || traits.contains("heavy") || traits.contains("black"),
+            italic: traits.contains("italic") || traits.contains("oblique"),
+            underline,
+            color: cg_color_to_hex(dictionary_value(attributes, "AXForegroundColor")),
+            link,
+        });
+        index = end;
+    }
+    StyledText { text, runs }
+}
+
+/// `element.attribute(attribute)`, recorded and replayed with the
+/// `ax-record` feature.
+fn ax_read<T: TCFType>(
+    element: &AXUIElement,
+    attribute: &AXAttribute<T>,
+) -> Result<T, accessibility_ng::Error> {
+    #[cfg(feature = "ax-record")]
+    if let Some(value) = replay_ax_query(element, attribute.as_CFString(), None) {
+        return value;
+    }
+    let value = element.attribute(attribute);
+    #[cfg(feature = "ax-record")]
+    record_ax_query(element, attribute.as_CFString(), None, &value);
+    value
+}
+
+fn ax_read_parameterized<T: TCFType, U: TCFType>(
+    element: &AXUIElement,
+    attribute: &AXAttribute<T>,
+    parameter: &U,
+) -> Result<T, accessibility_ng::Error> {
+    #[cfg(feature = "ax-record")]
+    if let Some(value) = replay_ax_query(
+        element,
+        attribute.as_CFString(),
+        Some(parameter.as_CFType()),
+    ) {
+        return value;
+    }
+    let value = element.parameterized_attribute(attribute, parameter);
+    #[cfg(feature = "ax-record")]
+    record_ax_query(
+        element,
+        attribute.as_CFString(),
+        Some(parameter.as_CFType()),
+        &value,
+    );
+    value
+}
+
+#[cfg(feature = "ax-record")]
+fn record_ax_query<T: TCFType>(
+    element: &AXUIElement,
+    attribute: &CFString,
+    parameter: Option<CFType>,
+    value: &Result<T, accessibility_ng::Error>,
+) {
+    if !crate::recording::is_recording() {
+        return;
+    }
+    crate::recording::push(crate::recording::AxQuery {
+        element: ax_element_label(element),
+        attribute: attribute.to_string(),
+        parameter: parameter.as_ref().map(to_ax_value),
+        value: value
+            .as_ref()
+            .ok()
+            .map(|value| to_ax_value(&value.as_CFType())),
+        error: value.as_ref().err().map(|e| e.to_string()),
+        error_code: match value {
+            Err(accessibility_ng::Error::Ax(code)) => Some(*code),
+            _ => None,
+        },
+    });
+}
+
+/// The recorded answer to the query while a replay runs.
+#[cfg(feature = "ax-record")]
+fn replay_ax_query<T: TCFType>(
+    element: &AXUIElement,
+    attribute: &CFString,
+    parameter: Option<CFType>,
+) -> Option<Result<T, accessibility_ng::Error>> {
+    use core_foundation::base::TCFTypeRef;
+
+    if !crate::recording::is_replaying() {
+        return None;
+    }
+    let parameter = parameter.as_ref().map(to_ax_value);
+    let answer = crate::recording::replayed(
+        &ax_element_label(element),
+        &attribute.to_string(),
+        parameter.as_ref(),
+    )?;
+    Some(
+        answer
+            .map_err(accessibility_ng::Error::Ax)
+            .and_then(|value| {
+                let value =
+                    from_ax_value(&value).ok_or(accessibility_ng::Error::Ax(kAXErrorNoValue))?;
+                // as `AXUIElement::attribute` checks it
+                if T::type_id() != CFType::type_id() && !value.instance_of::<T>() {
+                    return Err(accessibility_ng::Error::UnexpectedType {
+                        expected: T::type_id(),
+                        received: value.type_of(),
+                    });
+                }
+                Ok(unsafe { T::wrap_under_get_rule(T::Ref::from_void_ptr(value.as_CFTypeRef())) })
+            }),
+    )
+}
+
+#[cfg(feature = "ax-record")]
+fn to_ax_value(value: &CFType) -> crate::recording::AxValue {
+    use crate::recording::AxValue;
+
+    if let Some(element) = value.downcast::<AXUIElement>() {
+        return AxValue::Element(ax_element_label(&element));
+    }
+    if let Some(text) = value.downcast::<CFString>() {
+        return AxValue::String(text.to_string());
+    }
+    if let Some(boolean) = value.downcast::<CFBoolean>() {
+        return AxValue::Bool(boolean.into());
+    }
+    if let Some(number) = value.downcast::<CFNumber>() {
+        return AxValue::Number(number.to_f64().unwrap_or_default());
+    }
+    if let Some(array) = value.downcast::<CFArray>() {
+        return AxValue::Array(
+            array
+                .iter()
+                .map(|item| to_ax_value(&unsafe { CFType::wrap_under_get_rule(*item) }))
+                .collect(),
+        );
+    }
+    if let Some(value) = value.downcast::<AXValue>() {
+        let typed = match value.get_type() {
+            kind if kind == kAXValueTypeCFRange => {
+                value
+                    .get_value::<CFRange>()
+                    .ok()
+                    .map(|range| AxValue::Range {
+                        location: range.location as i64,
+                        length: range.length as i64,
+                    })
+            }
+            kind if kind == kAXValueTypeCGPoint => {
+                value
+                    .get_value::<CGPoint>()
+                    .ok()
+                    .map(|point| AxValue::Point {
+                        x: point.x,
+                        y: point.y,
+                    })
+            }
+            kind if kind == kAXValueTypeCGSize => {
+                value.get_value::<CGSize>().ok().map(|size| AxValue::Size {
+                    width: size.width,
+                    height: size.height,
+                })
+            }
+            kind if kind == kAXValueTypeCGRect => {
+                value.get_value::<CGRect>().ok().map(|rect| AxValue::Rect {
+                    x: rect.origin.x,
+                    y: rect.origin.y,
+                    width: rect.size.width,
+                    height: rect.size.height,
+                })
+            }
+            _ => None,
+        };
+        if let Some(typed) = typed {
+            return typed;
+        }
+    }
+    AxValue::Other(format!("{value:?}"))
+}
+
+#[cfg(feature = "ax-record")]
+fn from_ax_value(value: &crate::recording::AxValue) -> Option<CFType> {
+    use crate::recording::AxValue;
+
+    let point = |x, y| CGPoint::new(x, y);
+    Some(match value {
+        AxValue::String(text) => CFString::new(text).into_CFType(),
+        // AX reports counts and indexes as integers, which don't convert to
+        // floats losslessly the other way round
+        AxValue::Number(number) if number.fract() == 0.0 => {
+            CFNumber::from(*number as i64).into_CFType()
+        }
+        AxValue::Number(number) => CFNumber::from(*number).into_CFType(),
+        AxValue::Bool(boolean) => CFBoolean::from(*boolean).into_CFType(),
+        AxValue::Range { location, length } => {
+            AXValue::from_CFRange(CFRange::init(*location as CFIndex, *length as CFIndex))
+                .ok()?
+                .into_CFType()
+        }
+        AxValue::Point { x, y } => AXValue::from_CGPoint(point(*x, *y)).ok()?.into_CFType(),
+        AxValue::Size { width, height } => AXValue::from_CGSize(CGSize::new(*width, *height))
+            .ok()?
+            .into_CFType(),
+        AxValue::Rect {
+            x,
+            y,
+            width,
+            height,
+        } => AXValue::from_CGRect(CGRect::new(&point(*x, *y), &CGSize::new(*width, *height)))
+            .ok()?
+            .into_CFType(),
+        AxValue::Element(label) => replayed_element(label)?.into_CFType(),
+        AxValue::Array(values) => {
+            let values: Vec<CFType> = values.iter().filter_map(from_ax_value).collect();
+            CFArray::from_CFTypes(&values).into_CFType()
+        }
+        AxValue::Other(_) => return None,
+    })
+}
+
+/// Replayed elements are stand-ins for apps with made-up pids, from this one
+/// up, which no process has.
+#[cfg(feature = "ax-record")]
+const REPLAYED_PID: i32 = 1 << 24;
+
+/// The stand-in for the recorded element labelled `label`.
+#[cfg(feature = "ax-record")]
+fn replayed_element(label: &str) -> Option<AXUIElement> {
+    if label == "system-wide" {
+        return Some(AXUIElement::system_wide());
+    }
+    let index: i32 = label.strip_prefix("element-")?.parse().ok()?;
+    Some(AXUIElement::application(REPLAYED_PID + index))
+}
+
+#[cfg(feature = "ax-record")]
+fn ax_element_label(element: &AXUIElement) -> String {
+    use std::hash::{Hash, Hasher};
+
+    if *element == AXUIElement::system_wide() {
+        return "system-wide".to_string();
+    }
+    if crate::recording::is_replaying() {
+        return match element.pid() {
+            Ok(pid) if pid >= REPLAYED_PID => format!("element-{}", pid - REPLAYED_PID),
+            _ => "unknown".to_string(),
+        };
+    }
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    element.hash(&mut hasher);
+    crate::recording::element_label(hasher.finish())
+}
+
+fn ax_attribute<T: ConcreteCFType>(element: &AXUIElement, name: &'static str) -> Option<T> {
+    ax_read(
+        element,
+        &AXAttribute::<CFType>::new(&CFString::from_static_string(name)),
+    )
+    .ok()
+    .and_then(|value| value.downcast_into::<T>())
+}
+
+fn ax_children(element: &AXUIElement, name: &'static str) -> Vec<AXUIElement> {
+    ax_attribute::<CFArray>(element, name)
+        .map(|children| {
+            children
+                .iter()
+                .filter_map(|child| {
+                    unsafe { CFType::wrap_under_get_rule(*child) }.downcast_into::<AXUIElement>()
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn frontmost_menu_bar() -> Option<AXUIElement> {
+    let app =
+        ax_attribute::<AXUIElement>(&AXUIElement::system_wide(), kAXFocusedApplicationAttribute)?;
+    ax_attribute::<AXUIElement>(&app, kAXMenuBarAttribute)
+}
+
+/// Whether ⌘`key` alone is `item`'s shortcut.
+fn has_shortcut(item: &AXUIElement, key: &str) -> bool {
+    ax_attribute::<CFString>(item, kAXMenuItemCmdCharAttribute)
+        .is_some_and(|c| c.to_string().eq_ignore_ascii_case(key))
+        // 0 is the command key alone
+        && ax_attribute::<CFNumber>(item, kAXMenuItemCmdModifiersAttribute)
+            .and_then(|modifiers| modifiers.to_i64())
+            .unwrap_or(0)
+            == 0
+}
+
+/// The frontmost app's menu item for ⌘`key` alone, e.g. Edit ▸ Copy for
+/// `c`.
+fn menu_item(key: &str) -> Option<AXUIElement> {
+    ax_children(&frontmost_menu_bar()?, kAXChildrenAttribute)
+        .iter()
+        .flat_map(|menu| ax_children(menu, kAXChildrenAttribute))
+        .flat_map(|menu| ax_children(&menu, kAXChildrenAttribute))
+        .find(|item| has_shortcut(item, key))
+}
+
+/// The position of the frontmost app's menu item for ⌘C, Edit ▸ Copy in
+/// any language, as System Events counts: menu bar item, then item, from 1.
+fn copy_menu_position() -> Option<(usize, usize)> {
+    ax_children(&frontmost_menu_bar()?, kAXChildrenAttribute)
+        .iter()
+        .enumerate()
+        .find_map(|(menu, bar_item)| {
+            let items = ax_children(bar_item, kAXChildrenAttribute)
+                .first()
+                .map(|menu| ax_children(menu, kAXChildrenAttribute))
+                .unwrap_or_default();
+            let item = items.iter().position(|item| has_shortcut(item, "c"))?;
+            Some((menu + 1, item + 1))
+        })
+}
 
-set savedAlertVolume to alert volume of (get volume settings)
+/// The copy script running `script`, with the `copySelection` handler it
+/// calls clicking the menu item for ⌘C, which works where apps with Emacs
+/// keybindings or remote desktop clients take the keystroke before the menu
+/// sees it. Pressing ⌘C where no menu item has it. A Copy moved to another
+/// shortcut isn't found.
+fn with_copy_handler(script: &CopyScript) -> String {
+    let copy_selection = match copy_menu_position() {
+        Some((menu, item)) => format!(
+            "tell application \"System Events\" to click menu item {item} of menu 1 of menu bar item {menu} of menu bar 1 of (first application process whose frontmost is true)"
+        ),
+        None => "tell application \"System Events\" to keystroke \"c\" using {command down}"
+            .to_string(),
+    };
+    let indent = |step: &str| {
+        step.lines()
+            .map(|line| match line {
+                "" => "\n".to_string(),
+                line => format!("        {line}\n"),
+            })
+            .collect::<String>()
+    };
+    COPY_APPLE_SCRIPT
+        .replace("{copy}\n", &indent(script.copy))
+        .replace("{read}\n", &indent(script.read))
+        .replace("{copySelection}", &copy_selection)
+}
 
--- Back up clipboard contents:
-set savedClipboard to the clipboard
+/// Whether ⌘`key` would do anything. Pressing a disabled Copy only beeps,
+/// which the scripts used to hide by muting the alert volume for everyone.
+/// `true` when there's no such menu item to tell.
+fn shortcut_enabled(key: &str) -> bool {
+    menu_item(key)
+        .and_then(|item| ax_attribute::<CFBoolean>(&item, kAXEnabledAttribute))
+        .is_none_or(bool::from)
+}
 
-set thePasteboard to current application's NSPasteboard's generalPasteboard()
-set theCount to thePasteboard's changeCount()
+/// Checks before pressing ⌘ with each of `keys`: not on a control, where
+/// apps give the shortcuts their own meaning, nor with a dialog in front,
+/// whose buttons a keystroke could press instead. `Ok(false)` when one of
+/// the shortcuts is disabled.
+fn may_press_shortcuts(keys: &[&str]) -> Result<bool, Box<dyn std::error::Error>> {
+    if let Some(role) = focused_control_role() {
+        return Err(Box::new(GetTextError::NoSelection { role }));
+    }
+    if dialog_in_front() {
+        return Err(Box::new(GetTextError::DialogInFront));
+    }
+    Ok(keys.iter().all(|key| shortcut_enabled(key)))
+}
 
-tell application "System Events"
-    set volume alert volume 0
-end tell
+fn ax_selected_text(element: &AXUIElement) -> Option<String> {
+    ax_attribute::<CFString>(element, kAXSelectedTextAttribute)
+        .map(|text| text.to_string())
+        .filter(|text| !text.is_empty())
+}
+
+extern "C" {
+    // private but stable since 10.x, the only way to map AX windows to CGWindowIDs
+    fn _AXUIElementGetWindow(element: AXUIElementRef, window_id: *mut CGWindowID) -> AXError;
+}
+
+fn ax_window_id(window: &AXUIElement) -> Option<CGWindowID> {
+    let mut window_id: CGWindowID = 0;
+    let err = unsafe { _AXUIElementGetWindow(window.as_concrete_TypeRef(), &mut window_id) };
+    (err == kAXErrorSuccess).then_some(window_id)
+}
+
+fn window_info(window_id: CGWindowID) -> Option<CFDictionary<CFString, CFType>> {
+    let info = copy_window_info(kCGWindowListOptionIncludingWindow, window_id)?;
+    let dict = *info.get(0)?;
+    Some(unsafe { CFDictionary::wrap_under_get_rule(dict as CFDictionaryRef) })
+}
+
+fn window_owner_pid(window_id: CGWindowID) -> Option<i32> {
+    let info = window_info(window_id)?;
+    let key = unsafe { CFString::wrap_under_get_rule(kCGWindowOwnerPID) };
+    info.find(&key)?.downcast::<CFNumber>()?.to_i32()
+}
+
+/// The app `window_id` belongs to.
+#[cfg(feature = "redact")]
+pub(crate) fn window_owner_app(window_id: CGWindowID) -> crate::redact::App {
+    let key = unsafe { CFString::wrap_under_get_rule(core_graphics::window::kCGWindowOwnerName) };
+    let name = window_info(window_id)
+        .and_then(|info| Some(info.find(&key)?.downcast::<CFString>()?.to_string()));
+    crate::redact::App {
+        name: name.unwrap_or_default(),
+        bundle_id: window_owner_pid(window_id).and_then(|pid| app_bundle_info(pid).0),
+    }
+}
+
+fn window_bounds(window_id: CGWindowID) -> Option<Rect> {
+    let info = window_info(window_id)?;
+    let key = unsafe { CFString::wrap_under_get_rule(kCGWindowBounds) };
+    let bounds = info.find(&key)?.downcast::<CFDictionary>()?;
+    let bounds: CFDictionary<CFString, CFType> =
+        unsafe { CFDictionary::wrap_under_get_rule(bounds.as_concrete_TypeRef()) };
+    let value = |name: &'static str| {
+        bounds
+            .find(CFString::from_static_string(name))?
+            .downcast::<CFNumber>()?
+            .to_f64()
+    };
+    Some(Rect {
+        x: value("X")?,
+        y: value("Y")?,
+        width: value("Width")?,
+        height: value("Height")?,
+    })
+}
+
+/// Depth-first search for non-empty selections below `element`, skipping
+/// hidden subtrees and giving up after [`MAX_VISITED`] elements.
+fn find_selected_texts(element: &AXUIElement, depth: usize, texts: &mut Vec<String>) {
+    let mut stack = vec![(element.clone(), depth)];
+    let mut visited = 0;
+    while let Some((element, depth)) = stack.pop() {
+        visited += 1;
+        if visited > MAX_VISITED {
+            return;
+        }
+        if ax_attribute::<CFBoolean>(&element, kAXHiddenAttribute).is_some_and(bool::from) {
+            continue;
+        }
+        if let Some(text) = ax_selected_text(&element) {
+            texts.push(text);
+            continue;
+        }
+        if depth == 0 {
+            continue;
+        }
+        let children = ax_children(&element, kAXChildrenAttribute);
+        stack.extend(children.into_iter().rev().map(|child| (child, depth - 1)));
+    }
+}
+
+const MAX_SEARCH_DEPTH: usize = 32;
+// web views can have tens of thousands of elements, each read a round trip
+// to the app
+const MAX_VISITED: usize = 2000;
+
+/// Text shown in a table cell: its own value, or the first static text below it.
+fn ax_cell_text(cell: &AXUIElement, depth: usize) -> Option<String> {
+    if let Some(value) = ax_attribute::<CFString>(cell, kAXValueAttribute) {
+        return Some(value.to_string());
+    }
+    if depth == 0 {
+        return None;
+    }
+    ax_children(cell, kAXChildrenAttribute)
+        .iter()
+        .find_map(|child| ax_cell_text(child, depth - 1))
+}
+
+/// Reads the selected rows of the table (or outline) the focus is in.
+pub(crate) fn get_selected_table_by_ax() -> Option<Table> {
+    let mut element = focused_element().ok()?;
+    // the focus is usually on the table itself, sometimes on a cell or row in it
+    for _ in 0..4 {
+        let role = ax_attribute::<CFString>(&element, kAXRoleAttribute)?.to_string();
+        if role == kAXTableRole || role == kAXOutlineRole {
+            break;
+        }
+        element = ax_attribute::<AXUIElement>(&element, kAXParentAttribute)?;
+    }
+    let rows = ax_children(&element, kAXSelectedRowsAttribute)
+        .iter()
+        .map(|row| {
+            ax_children(row, kAXChildrenAttribute)
+                .iter()
+                .map(|cell| ax_cell_text(cell, 3).unwrap_or_default())
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<_>>();
+    (!rows.is_empty()).then_some(Table { rows })
+}
+
+pub fn get_selected_text_for_window(
+    window_id: CGWindowID,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let Some(pid) = window_owner_pid(window_id) else {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "No such window",
+        )));
+    };
+    let app = AXUIElement::application(pid);
+    let Some(window) = ax_children(&app, kAXWindowsAttribute)
+        .into_iter()
+        .find(|window| ax_window_id(window) == Some(window_id))
+    else {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Window is not accessible",
+        )));
+    };
+
+    // the app keeps track of its focused element even while in the background
+    if let Some(focused) = ax_attribute::<AXUIElement>(&app, kAXFocusedUIElementAttribute) {
+        let in_window = ax_attribute::<AXUIElement>(&focused, kAXWindowAttribute)
+            .is_some_and(|focused_window| focused_window == window);
+        if in_window {
+            if let Some(text) = ax_selected_text(&focused) {
+                return Ok(text);
+            }
+        }
+    }
+
+    let mut texts = Vec::new();
+    find_selected_texts(&window, MAX_SEARCH_DEPTH, &mut texts);
+    Ok(texts.into_iter().next().unwrap_or_default())
+}
 
+pub fn get_window_selections() -> Result<Vec<WindowSelection>, Box<dyn std::error::Error>> {
+    let Some(app) =
+        ax_attribute::<AXUIElement>(&AXUIElement::system_wide(), kAXFocusedApplicationAttribute)
+    else {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "No focused application",
+        )));
+    };
+
+    let selections = ax_children(&app, kAXWindowsAttribute)
+        .into_iter()
+        .filter_map(|window| {
+            let mut text = Vec::new();
+            find_selected_texts(&window, MAX_SEARCH_DEPTH, &mut text);
+            (!text.is_empty()).then(|| WindowSelection {
+                window_id: ax_window_id(&window).unwrap_or_default(),
+                title: ax_attribute::<CFString>(&window, kAXTitleAttribute)
+                    .map(|title| title.to_string())
+                    .unwrap_or_default(),
+                text,
+            })
+        })
+        .collect();
+    Ok(selections)
+}
+
+pub fn set_selected_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let selected_element = focused_element()?;
+    let attribute = AXAttribute::new(&CFString::from_static_string(kAXSelectedTextAttribute));
+    if !selected_element.is_settable(&attribute).unwrap_or(false) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "Selected text is read-only",
+        )));
+    }
+    selected_element.set_attribute(&attribute, CFString::new(text).as_CFType())?;
+    Ok(())
+}
+
+pub fn type_text(
+    text: &str,
+    interval: Duration,
+    cancel: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+        .map_err(|_| "Failed to create event source")?;
+    for ch in text.chars() {
+        if cancel.is_cancelled() {
+            return Err(cancelled_error());
+        }
+        let mut buf = [0u16; 2];
+        for key_down in [true, false] {
+            let event = CGEvent::new_keyboard_event(source.clone(), 0, key_down)
+                .map_err(|_| "Failed to create keyboard event")?;
+            event.set_string_from_utf16_unchecked(ch.encode_utf16(&mut buf));
+            event.post(CGEventTapLocation::HID);
+        }
+        thread::sleep(interval);
+    }
+    Ok(())
+}
+
+pub fn replace_selected_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if set_selected_text(text).is_ok() {
+        return Ok(());
+    }
+    paste_text_using_applescript(text)
+}
+
+/// What a copy script does with the clipboard set aside: `copy` puts the
+/// selection on it, `read` sets `theResult` from it. [`with_copy_handler`]
+/// wraps them in the backup, marker and restore steps they all share.
+struct CopyScript {
+    copy: &'static str,
+    read: &'static str,
+}
+
+const REGULAR_TEXT_COPY_APPLE_SCRIPT: CopyScript = CopyScript {
+    copy: r#"
 -- Copy selected text to clipboard:
-tell application "System Events" to keystroke "c" using {command down}
-delay 0.1 -- Without this, the clipboard may have stale data.
+copySelection()
+"#,
+    read: r#"
+set theResult to the clipboard
+"#,
+};
 
-tell application "System Events"
-    set volume alert volume savedAlertVolume
-end tell
+// same as REGULAR_TEXT_COPY_APPLE_SCRIPT, selecting everything first
+const SELECT_ALL_COPY_APPLE_SCRIPT: CopyScript = CopyScript {
+    copy: r#"
+-- Select the whole document and copy it to clipboard:
+tell application "System Events" to keystroke "a" using {command down}
+delay 0.05
+copySelection()
+"#,
+    read: REGULAR_TEXT_COPY_APPLE_SCRIPT.read,
+};
 
-if thePasteboard's changeCount() is theCount then
-    return ""
+// same as REGULAR_TEXT_COPY_APPLE_SCRIPT, but an image-only clipboard is
+// written as PNG to the path in argv instead of being coerced to text
+const IMAGE_AWARE_COPY_APPLE_SCRIPT: CopyScript = CopyScript {
+    copy: REGULAR_TEXT_COPY_APPLE_SCRIPT.copy,
+    read: r#"
+set imagePath to item 1 of argv
+set theResult to ""
+if (thePasteboard's stringForType:(current application's NSPasteboardTypeString)) is missing value then
+    set theImageData to thePasteboard's dataForType:(current application's NSPasteboardTypePNG)
+    if theImageData is missing value then
+        set theTIFF to thePasteboard's dataForType:(current application's NSPasteboardTypeTIFF)
+        if theTIFF is not missing value then
+            set theRep to current application's NSBitmapImageRep's imageRepWithData:theTIFF
+            set theImageData to theRep's representationUsingType:(current application's NSBitmapImageFileTypePNG) |properties|:(missing value)
+        end if
+    end if
+    if theImageData is not missing value then
+        theImageData's writeToFile:imagePath atomically:true
+    end if
+else
+    set theResult to the clipboard
 end if
+"#,
+};
 
-set theSelectedText to the clipboard
+// same as REGULAR_TEXT_COPY_APPLE_SCRIPT, also writing the HTML flavor (if
+// any) to the path in argv before the clipboard is restored
+const HTML_COPY_APPLE_SCRIPT: CopyScript = CopyScript {
+    copy: REGULAR_TEXT_COPY_APPLE_SCRIPT.copy,
+    read: r#"
+set htmlPath to item 1 of argv
+set theHTML to thePasteboard's stringForType:(current application's NSPasteboardTypeHTML)
+if theHTML is not missing value then
+    theHTML's writeToFile:htmlPath atomically:true encoding:(current application's NSUTF8StringEncoding) |error|:(missing value)
+end if
+set theResult to the clipboard
+"#,
+};
+
+const FILE_PATH_COPY_APPLE_SCRIPT: CopyScript = CopyScript {
+    copy: r#"
+-- Copy the selected files to clipboard:
+tell application "System Events" to keystroke "c" using {command down}
+"#,
+    read: r#"
+-- One percent-encoded path per line, file names can hold linefeeds:
+set theURLs to thePasteboard's readObjectsForClasses:{current application's NSURL} options:(missing value)
+set theAllowed to current application's NSCharacterSet's URLPathAllowedCharacterSet()
+set thePaths to current application's NSMutableArray's array()
+repeat with theURL in theURLs
+    (thePaths's addObject:((theURL's |path|())'s stringByAddingPercentEncodingWithAllowedCharacters:theAllowed))
+end repeat
+set theResult to (thePaths's componentsJoinedByString:linefeed) as text
+"#,
+};
+
+// The transient type is added once the copy replaced the marker, the app
+// copying clears the pasteboard first. Clipboard managers looking at the
+// pasteboard in between still record the copy, and the restore after it.
+const COPY_APPLE_SCRIPT: &str = r#"
+use AppleScript version "2.4"
+use scripting additions
+use framework "Foundation"
+use framework "AppKit"
+
+on run argv
+    -- Back up clipboard contents:
+    set savedClipboard to the clipboard
+
+    -- Put the clipboard back also when a step fails:
+    try
+        -- Leave a marker the copy has to replace, clipboard managers bump changeCount too:
+        set thePasteboard to current application's NSPasteboard's generalPasteboard()
+        set theMarker to "io.github.yetone.get-selected-text.marker"
+        thePasteboard's clearContents()
+        thePasteboard's setString:"" forType:theMarker
+        thePasteboard's setString:"" forType:"org.nspasteboard.TransientType"
+{copy}
+        delay 0.1 -- Without this, the clipboard may have stale data.
+
+        if (thePasteboard's types()'s containsObject:theMarker) as boolean then
+            set the clipboard to savedClipboard
+            return ""
+        end if
+
+        -- Clipboard managers that honor it (Maccy, Paste, Alfred...) skip the copy, unless they saw it already:
+        thePasteboard's addTypes:{"org.nspasteboard.TransientType"} owner:(missing value)
+        thePasteboard's setString:"" forType:"org.nspasteboard.TransientType"
+{read}
 
-set the clipboard to savedClipboard
+        set the clipboard to savedClipboard
 
-theSelectedText
+        return theResult
+    on error errorMessage number errorNumber
+        set the clipboard to savedClipboard
+        error errorMessage number errorNumber
+    end try
+end run
+
+on copySelection()
+    {copySelection}
+end copySelection
 "#;
 
-const FILE_PATH_COPY_APPLE_SCRIPT: &str = r#"
+const PASTE_APPLE_SCRIPT: &str = r#"
 use AppleScript version "2.4"
 use scripting additions
 use framework "Foundation"
 use framework "AppKit"
 
-set savedAlertVolume to alert volume of (get volume settings)
+on run argv
+    -- Back up clipboard contents:
+    set savedClipboard to the clipboard
 
--- Back up clipboard contents:
-set savedClipboard to the clipboard
+    -- Put the clipboard back also when a step fails:
+    try
+        set the clipboard to item 1 of argv
 
-set thePasteboard to current application's NSPasteboard's generalPasteboard()
-set theCount to thePasteboard's changeCount()
+        -- Paste the replacement over the selection:
+        tell application "System Events" to keystroke "v" using {command down}
+        delay 0.2 -- Give the app time to read the pasteboard before it's restored.
 
-tell application "System Events"
-    set volume alert volume 0
-end tell
+        set the clipboard to savedClipboard
+    on error errorMessage number errorNumber
+        set the clipboard to savedClipboard
+        error errorMessage number errorNumber
+    end try
+end run
+"#;
 
--- Copy selected text to clipboard:
-tell application "System Events" to keystroke "c" using {command down, option down}
-delay 0.1 -- Without this, the clipboard may have stale data.
+const SELF_TEST_OPEN_APPLE_SCRIPT: &str = r#"
+on run argv
+    tell application "System Events" to set thePrevious to bundle identifier of first application process whose frontmost is true
 
-tell application "System Events"
-    set volume alert volume savedAlertVolume
-end tell
+    tell application "TextEdit"
+        activate
+        make new document with properties {text:item 1 of argv}
+    end tell
+    delay 0.5
 
-if thePasteboard's changeCount() is theCount then
-    return ""
-end if
+    -- Select the whole test text:
+    tell application "System Events" to keystroke "a" using {command down}
+    delay 0.2
 
-set theSelectedText to the clipboard
+    return thePrevious
+end run
+"#;
 
-set the clipboard to savedClipboard
+const SELF_TEST_CLOSE_APPLE_SCRIPT: &str = r#"
+on run argv
+    tell application "TextEdit"
+        close (every document whose text is item 1 of argv) saving no
+        if (count of documents) is 0 then quit
+    end tell
 
-theSelectedText
+    if item 2 of argv is not "" then
+        tell application id (item 2 of argv) to activate
+    end if
+end run
 "#;
 
+/// Reads [`SELF_TEST_TEXT`] back from a TextEdit document with each
+/// method, calling them directly so the attempts leave TextEdit's method
+/// statistics alone.
+pub(crate) fn self_test() -> Result<Vec<SelfTestResult>, Box<dyn std::error::Error>> {
+    init()?;
+    let previous = run_apple_script(SELF_TEST_OPEN_APPLE_SCRIPT, &[SELF_TEST_TEXT])?;
+    let window_id = crate::window::active_window()
+        .ok()
+        .and_then(|window| window.window_id.parse().ok());
+    let options = CaptureOptions::new();
+    let results = [Strategy::Accessibility, Strategy::Clipboard, Strategy::Ocr]
+        .into_iter()
+        .map(|strategy| {
+            let read = match strategy {
+                Strategy::Accessibility => get_selected_text_by_ax(),
+                Strategy::Clipboard => {
+                    get_selected_text_by_clipboard(&mut SelectedText::default(), &options)
+                }
+                _ => crate::ocr::recognize_selection(window_id, &options.ocr_options),
+            };
+            SelfTestResult::new(strategy, read)
+        })
+        .collect();
+    run_apple_script(SELF_TEST_CLOSE_APPLE_SCRIPT, &[SELF_TEST_TEXT, &previous])?;
+    Ok(results)
+}
+
+/// Copying, pasting and the Finder paths go through `osascript`. Fails with
+/// `Unsupported` when built without the `applescript` feature.
+#[cfg(feature = "applescript")]
+fn run_apple_script(script: &str, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    #[cfg(feature = "ax-record")]
+    crate::recording::not_replayed("osascript")?;
+    // each script backs up and restores the clipboard
+    let _round_trip = crate::round_trip::begin()?;
+    let output = std::process::Command::new("osascript")
+        .arg("-e")
+        .arg(script)
+        .args(args)
+        .output()
+        .map_err(|source| GetTextError::Spawn {
+            program: "osascript",
+            source,
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let (kind, code) = crate::AppleScriptErrorKind::from_stderr(&stderr);
+        return Err(Box::new(GetTextError::AppleScript {
+            status: output.status,
+            stderr,
+            kind,
+            code,
+        }));
+    }
+    let content =
+        String::from_utf8(output.stdout).map_err(|source| GetTextError::InvalidOutput {
+            program: "osascript",
+            source,
+        })?;
+    Ok(content.trim().to_string())
+}
+
+#[cfg(not(feature = "applescript"))]
+fn run_apple_script(_script: &str, _args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Built without the applescript feature",
+    )
+    .into())
+}
+
 fn get_selected_text_by_clipboard_using_applescript() -> Result<String, Box<dyn std::error::Error>>
 {
     // debug_println!("get_selected_text_by_clipboard_using_applescript");
-    let output = std::process::Command::new("osascript")
-        .arg("-e")
-        .arg(REGULAR_TEXT_COPY_APPLE_SCRIPT)
-        .output()?;
-    if output.status.success() {
-        let content = String::from_utf8(output.stdout)?;
-        let content = content.trim();
-        Ok(content.to_string())
+    run_apple_script(&with_copy_handler(&REGULAR_TEXT_COPY_APPLE_SCRIPT), &[])
+}
+
+/// Copies the selection through the clipboard. With `options.image_text`,
+/// an image selection ends up in `selected_text.image` and its recognized
+/// text is returned, or the copied text when nothing was recognized.
+fn get_selected_text_by_clipboard(
+    selected_text: &mut SelectedText,
+    options: &CaptureOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // osascript isn't interrupted, the script has to restore the clipboard
+    if options.cancel.is_cancelled() {
+        return Err(cancelled_error());
+    }
+    if !may_press_shortcuts(&["c"])? {
+        return Ok(String::new());
+    }
+    let text = get_selected_text_by_clipboard_copying(selected_text, options)?;
+    if options.cancel.is_cancelled() {
+        return Err(cancelled_error());
+    }
+    Ok(text)
+}
+
+fn get_selected_text_by_clipboard_copying(
+    selected_text: &mut SelectedText,
+    options: &CaptureOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if !options.image_text {
+        return get_selected_text_by_clipboard_using_applescript();
+    }
+    let image_path = std::env::temp_dir().join(format!(
+        "get-selected-text-{}-{}.png",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    ));
+    let text = run_apple_script(
+        &with_copy_handler(&IMAGE_AWARE_COPY_APPLE_SCRIPT),
+        &[&image_path.to_string_lossy()],
+    )?;
+    let image = std::fs::read(&image_path);
+    let _ = std::fs::remove_file(&image_path);
+    let Ok(image) = image else {
+        return Ok(text);
+    };
+    let recognized = match crate::ocr::recognize_image_data(&image, &options.ocr_options) {
+        Ok(recognized) => recognized,
+        Err(e) => {
+            selected_text
+                .warnings
+                .push(format!("Recognizing the copied image's text failed: {e}"));
+            String::new()
+        }
+    };
+    selected_text.image = Some(image);
+    // an image copied along with text, e.g. from a document, keeps the text
+    Ok(if recognized.trim().is_empty() {
+        text
     } else {
-        let err = output
-            .stderr
-            .into_iter()
-            .map(|c| c as char)
-            .collect::<String>()
-            .into();
-        Err(err)
+        recognized
+    })
+}
+
+/// Copies the selection once, reading both its plain text and HTML flavors.
+pub(crate) fn get_selected_text_and_html(
+) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
+    if !may_press_shortcuts(&["c"])? {
+        return Ok((String::new(), None));
     }
+    let html_path = std::env::temp_dir().join(format!(
+        "get-selected-text-{}-{}.html",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    ));
+    let text = run_apple_script(
+        &with_copy_handler(&HTML_COPY_APPLE_SCRIPT),
+        &[&html_path.to_string_lossy()],
+    )?;
+    let html = std::fs::read_to_string(&html_path).ok();
+    let _ = std::fs::remove_file(&html_path);
+    Ok((text, html))
+}
+
+/// Where the selection can be put back through: a character range in text
+/// views, a text marker range in web views.
+const SELECTION_RANGE_ATTRIBUTES: &[&str] = &["AXSelectedTextRange", "AXSelectedTextMarkerRange"];
+
+/// The focused element's value, otherwise everything ⌘A selects, copied
+/// with the selection put back afterwards.
+pub(crate) fn get_full_document_text() -> Result<String, Box<dyn std::error::Error>> {
+    let element = focused_element()?;
+    if let Some(value) = ax_attribute::<CFString>(&element, kAXValueAttribute) {
+        let value = value.to_string();
+        if !value.is_empty() {
+            return Ok(value);
+        }
+    }
+    if !may_press_shortcuts(&["a", "c"])? {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Nothing can be selected here",
+        )));
+    }
+    let selection = SELECTION_RANGE_ATTRIBUTES.iter().find_map(|name| {
+        let attribute = AXAttribute::<CFType>::new(&CFString::from_static_string(name));
+        let range = ax_read(&element, &attribute).ok()?;
+        element
+            .is_settable(&attribute)
+            .unwrap_or(false)
+            .then_some((attribute, range))
+    });
+    let text = run_apple_script(&with_copy_handler(&SELECT_ALL_COPY_APPLE_SCRIPT), &[]);
+    if let Some((attribute, range)) = selection {
+        let _ = element.set_attribute(&attribute, range);
+    }
+    text
 }
 
 fn get_selected_file_paths_by_clipboard_using_applescript(
 ) -> Result<String, Box<dyn std::error::Error>> {
     // debug_println!("get_selected_text_by_clipboard_using_applescript");
-    let output = std::process::Command::new("osascript")
-        .arg("-e")
-        .arg(FILE_PATH_COPY_APPLE_SCRIPT)
-        .output()?;
-    if output.status.success() {
-        let content = String::from_utf8(output.stdout)?;
-        let content = content.trim();
-        Ok(content.to_string())
-    } else {
-        let err = output
-            .stderr
-            .into_iter()
-            .map(|c| c as char)
-            .collect::<String>()
-            .into();
-        Err(err)
+    if !may_press_shortcuts(&["c"])? {
+        return Ok(String::new());
+    }
+    run_apple_script(&with_copy_handler(&FILE_PATH_COPY_APPLE_SCRIPT), &[])
+}
+
+fn paste_text_using_applescript(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !may_press_shortcuts(&["v"])? {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Nothing can be pasted here",
+        )));
     }
+    run_apple_script(PASTE_APPLE_SCRIPT, &[text])?;
+    Ok(())
+}
+
+pub fn displays() -> Result<Vec<Display>, Box<dyn std::error::Error>> {
+    let ids = CGDisplay::active_displays()
+        .map_err(|e| format!("CGGetActiveDisplayList failed: {}", e))?;
+    Ok(ids
+        .into_iter()
+        .map(|id| {
+            let display = CGDisplay::new(id);
+            let bounds = display.bounds();
+            let scale_factor = display
+                .display_mode()
+                .filter(|mode| mode.width() > 0)
+                .map_or(1.0, |mode| mode.pixel_width() as f64 / mode.width() as f64);
+            Display {
+                id,
+                frame: Rect {
+                    x: bounds.origin.x,
+                    y: bounds.origin.y,
+                    width: bounds.size.width,
+                    height: bounds.size.height,
+                },
+                scale_factor,
+                is_primary: display.is_main(),
+            }
+        })
+        .collect())
+}
+
+/// Current mouse position in global screen coordinates.
+pub fn mouse_position() -> Result<Point, Box<dyn std::error::Error>> {
+    let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
+        .map_err(|_| "Failed to create event source")?;
+    let event = CGEvent::new(source).map_err(|_| "Failed to create event")?;
+    let location = event.location();
+    Ok(Point {
+        x: location.x,
+        y: location.y,
+    })
 }
@@ -0,0 +1,330 @@
+//! Scriptable fake backend for tests, enabled with the `mock` feature.
+//!
+//! Once any of the setters is called, [`crate::get_selected_text`] and the
+//! selection watcher return the scripted values instead of touching the
+//! system, until [`reset`] is called.
+//!
+//! ```
+//! use get_selected_text::mock;
+//!
+//! mock::set_selection("hello");
+//! mock::fail_next("permission denied");
+//!
+//! assert!(get_selected_text::get_selected_text().is_err());
+//! # #[cfg(not(target_os = "macos"))]
+//! assert_eq!(get_selected_text::get_selected_text().unwrap(), "hello");
+//! mock::reset();
+//! ```
+//!
+//! With the `simulate` feature, a process started with
+//! `GET_SELECTED_TEXT_SIMULATE` set is mocked from the start, no code
+//! changes needed, for demos, screenshots and CI runs of whole apps:
+//!
+//! - `text:hello` selects `hello`
+//! - `file:/tmp/selection.txt` reads the file on every capture, so editing
+//!   it changes the selection
+//! - `stdin` reads one line from stdin per capture, and fails once it's
+//!   exhausted
+//!
+//! `GET_SELECTED_TEXT_SIMULATE_APP` sets the app name reported with it. Any
+//! other value fails every capture with what's wrong with it.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+enum Response {
+    Selection(String),
+    Error(String),
+}
+
+/// Where the selection comes from when nothing is queued.
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Fixed,
+    File(PathBuf),
+    Stdin,
+    /// A malformed `GET_SELECTED_TEXT_SIMULATE`, failing every capture.
+    #[cfg_attr(not(any(feature = "simulate", test)), allow(dead_code))]
+    Invalid(String),
+}
+
+struct State {
+    enabled: bool,
+    app_name: String,
+    selection: String,
+    source: Source,
+    queue: VecDeque<Response>,
+    latency: Duration,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    enabled: false,
+    app_name: String::new(),
+    selection: String::new(),
+    source: Source::Fixed,
+    queue: VecDeque::new(),
+    latency: Duration::ZERO,
+});
+
+/// Sets the selection returned by every capture until changed.
+pub fn set_selection(text: impl Into<String>) {
+    let mut state = STATE.lock();
+    state.enabled = true;
+    state.selection = text.into();
+    state.source = Source::Fixed;
+}
+
+/// Reads the selection from `path` on every capture, so the file can be
+/// edited while the app runs. Fails the captures while it can't be read.
+pub fn set_selection_file(path: impl Into<PathBuf>) {
+    let mut state = STATE.lock();
+    state.enabled = true;
+    state.source = Source::File(path.into());
+}
+
+/// Reads the selection from stdin, one line per capture.
+pub fn set_selection_from_stdin() {
+    let mut state = STATE.lock();
+    state.enabled = true;
+    state.source = Source::Stdin;
+}
+
+/// Queues a selection returned by the next capture only.
+pub fn set_next_selection(text: impl Into<String>) {
+    let mut state = STATE.lock();
+    state.enabled = true;
+    state.queue.push_back(Response::Selection(text.into()));
+}
+
+/// Makes the next capture fail with `message`.
+pub fn fail_next(message: impl Into<String>) {
+    let mut state = STATE.lock();
+    state.enabled = true;
+    state.queue.push_back(Response::Error(message.into()));
+}
+
+/// Sets the app name reported with the selection.
+pub fn set_app_name(app_name: impl Into<String>) {
+    let mut state = STATE.lock();
+    state.enabled = true;
+    state.app_name = app_name.into();
+}
+
+/// Delays every capture by `latency`.
+pub fn set_latency(latency: Duration) {
+    let mut state = STATE.lock();
+    state.enabled = true;
+    state.latency = latency;
+}
+
+/// Clears the script and goes back to the real backend.
+pub fn reset() {
+    let mut state = STATE.lock();
+    state.enabled = false;
+    state.app_name.clear();
+    state.selection.clear();
+    state.source = Source::Fixed;
+    state.queue.clear();
+    state.latency = Duration::ZERO;
+}
+
+/// Held by tests scripting the mock, which is shared by the whole process.
+#[cfg(test)]
+pub(crate) fn lock() -> parking_lot::MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock()
+}
+
+pub(crate) fn is_enabled() -> bool {
+    #[cfg(feature = "simulate")]
+    {
+        static FROM_ENV: std::sync::Once = std::sync::Once::new();
+        FROM_ENV.call_once(simulate_from_env);
+    }
+    STATE.lock().enabled
+}
+
+/// Applies `GET_SELECTED_TEXT_SIMULATE` and `GET_SELECTED_TEXT_SIMULATE_APP`.
+#[cfg(feature = "simulate")]
+fn simulate_from_env() {
+    let var = |name| std::env::var_os(name).map(|value| value.to_string_lossy().into_owned());
+    simulate(
+        var("GET_SELECTED_TEXT_SIMULATE").as_deref(),
+        var("GET_SELECTED_TEXT_SIMULATE_APP").as_deref(),
+    );
+}
+
+/// Mocks the selection `spec` describes. A malformed one fails the captures
+/// rather than leaving the real backend to be used unnoticed.
+#[cfg(any(feature = "simulate", test))]
+fn simulate(spec: Option<&str>, app_name: Option<&str>) {
+    if let Some(app_name) = app_name {
+        set_app_name(app_name);
+    }
+    let Some(spec) = spec else {
+        return;
+    };
+    if let Some(text) = spec.strip_prefix("text:") {
+        set_selection(text);
+    } else if let Some(path) = spec.strip_prefix("file:") {
+        set_selection_file(path);
+    } else if spec == "stdin" {
+        set_selection_from_stdin();
+    } else {
+        let mut state = STATE.lock();
+        state.enabled = true;
+        state.source = Source::Invalid(format!(
+            "GET_SELECTED_TEXT_SIMULATE takes text:..., file:... or stdin, not {spec:?}"
+        ));
+    }
+}
+
+pub(crate) fn app_name() -> String {
+    STATE.lock().app_name.clone()
+}
+
+pub(crate) fn get_selected_text_passively() -> Result<String, Box<dyn std::error::Error>> {
+    let (response, source, latency) = {
+        let mut state = STATE.lock();
+        let response = state.queue.pop_front();
+        let response = match (&response, &state.source) {
+            (None, Source::Fixed) => Some(Response::Selection(state.selection.clone())),
+            _ => response,
+        };
+        (response, state.source.clone(), state.latency)
+    };
+    // sleep outside the lock so tests can re-script while a capture is in flight
+    thread::sleep(latency);
+    match (response, source) {
+        (Some(Response::Selection(text)), _) => Ok(text),
+        (Some(Response::Error(message)), _) => Err(message.into()),
+        (None, Source::File(path)) => {
+            let text = std::fs::read_to_string(path)?;
+            Ok(text.strip_suffix('\n').unwrap_or(&text).to_string())
+        }
+        (None, Source::Stdin) => {
+            let mut line = String::new();
+            if std::io::stdin().read_line(&mut line)? == 0 {
+                return Err("No more simulated selections on stdin".into());
+            }
+            Ok(line.trim_end_matches(['\r', '\n']).to_string())
+        }
+        (None, Source::Invalid(message)) => Err(message.into()),
+        (None, Source::Fixed) => unreachable!("a fixed selection is always answered"),
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn get_selected_text() -> Result<crate::SelectedText, Box<dyn std::error::Error>> {
+    let text = get_selected_text_passively()?;
+    Ok(crate::SelectedText {
+        is_file_paths: false,
+        app_name: app_name(),
+        text: vec![text],
+        ..Default::default()
+    })
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn get_selected_text() -> Result<String, Box<dyn std::error::Error>> {
+    get_selected_text_passively()
+}
+
+/// Replaces the mocked selection, like a cooperative app would, rewriting
+/// the [`set_selection_file`] file. A queued [`fail_next`] error is returned
+/// instead.
+pub(crate) fn set_selected_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = STATE.lock();
+    if let Some(Response::Error(message)) = state.queue.front() {
+        let message = message.clone();
+        state.queue.pop_front();
+        return Err(message.into());
+    }
+    match &state.source {
+        Source::File(path) => std::fs::write(path, text)?,
+        _ => state.selection = text.to_string(),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture() -> Result<String, String> {
+        get_selected_text_passively().map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_script() {
+        let _mock = lock();
+        assert!(!is_enabled());
+        set_selection("hello");
+        set_app_name("Notes");
+        assert!(is_enabled());
+        assert_eq!(app_name(), "Notes");
+        assert_eq!(capture(), Ok("hello".to_string()));
+
+        // queued responses come first, in order, then the selection again
+        set_next_selection("next");
+        fail_next("no access");
+        assert_eq!(capture(), Ok("next".to_string()));
+        assert_eq!(capture(), Err("no access".to_string()));
+        assert_eq!(capture(), Ok("hello".to_string()));
+
+        // a queued failure is what replacing returns too
+        fail_next("read-only");
+        assert!(set_selected_text("bye").is_err());
+        set_selected_text("bye").unwrap();
+        assert_eq!(capture(), Ok("bye".to_string()));
+
+        set_latency(Duration::from_millis(50));
+        let started = std::time::Instant::now();
+        capture().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        reset();
+        assert!(!is_enabled());
+        assert_eq!(app_name(), "");
+        let state = STATE.lock();
+        assert!(state.queue.is_empty() && state.latency.is_zero());
+    }
+
+    #[test]
+    fn test_simulate() {
+        let _mock = lock();
+        simulate(Some("text:hello"), Some("Notes"));
+        assert_eq!(capture(), Ok("hello".to_string()));
+        assert_eq!(app_name(), "Notes");
+        reset();
+
+        // read on every capture, without the line break editors add
+        let path = std::env::temp_dir().join(format!("gst-simulate-{}.txt", std::process::id()));
+        std::fs::write(&path, "from a file\n").unwrap();
+        simulate(Some(&format!("file:{}", path.display())), None);
+        assert_eq!(capture(), Ok("from a file".to_string()));
+        std::fs::write(&path, "edited").unwrap();
+        assert_eq!(capture(), Ok("edited".to_string()));
+        std::fs::remove_file(&path).unwrap();
+        assert!(capture().is_err());
+        reset();
+
+        simulate(Some("stdin"), None);
+        assert_eq!(STATE.lock().source, Source::Stdin);
+        reset();
+
+        simulate(Some("txt:hello"), None);
+        assert!(is_enabled());
+        assert!(capture()
+            .unwrap_err()
+            .contains("GET_SELECTED_TEXT_SIMULATE"));
+        reset();
+
+        simulate(None, None);
+        assert!(!is_enabled());
+    }
+}
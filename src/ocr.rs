@@ -0,0 +1,202 @@
+//! Text recognition through Apple's Vision framework, the last resort for
+//! apps that expose neither AX nor a usable clipboard (Citrix, VMs, games,
+//! screen-shared windows).
+
+use std::os::raw::c_void;
+
+use cocoa::base::{id, nil, BOOL, NO, YES};
+use cocoa::foundation::{NSArray, NSAutoreleasePool, NSInteger, NSString, NSUInteger};
+use core_foundation::base::{CFRelease, CFTypeRef};
+use core_graphics::geometry::{CGPoint, CGRect, CGSize};
+use core_graphics::sys;
+use core_graphics::window::{
+    kCGNullWindowID, kCGWindowImageBestResolution, kCGWindowImageBoundsIgnoreFraming,
+    kCGWindowListOptionIncludingWindow, kCGWindowListOptionOnScreenOnly, CGWindowID,
+    CGWindowListCreateImage,
+};
+use objc::{class, msg_send, sel, sel_impl};
+
+use crate::{OcrOptions, RecognitionLevel, Rect};
+
+#[link(name = "Vision", kind = "framework")]
+extern "C" {}
+
+extern "C" {
+    static CGRectNull: CGRect;
+}
+
+/// Owned `CGImageRef`.
+struct Image(*mut sys::CGImage);
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.0 as CFTypeRef) }
+    }
+}
+
+fn screenshot_error() -> Box<dyn std::error::Error> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        "Failed to capture the screen, is Screen Recording allowed?",
+    ))
+}
+
+fn capture_window(window_id: CGWindowID) -> Result<Image, Box<dyn std::error::Error>> {
+    let image = unsafe {
+        CGWindowListCreateImage(
+            CGRectNull,
+            kCGWindowListOptionIncludingWindow,
+            window_id,
+            kCGWindowImageBoundsIgnoreFraming | kCGWindowImageBestResolution,
+        )
+    };
+    if image.is_null() {
+        return Err(screenshot_error());
+    }
+    Ok(Image(image))
+}
+
+fn capture_rect(rect: Rect) -> Result<Image, Box<dyn std::error::Error>> {
+    let bounds = CGRect::new(
+        &CGPoint::new(rect.x, rect.y),
+        &CGSize::new(rect.width, rect.height),
+    );
+    let image = unsafe {
+        CGWindowListCreateImage(
+            bounds,
+            kCGWindowListOptionOnScreenOnly,
+            kCGNullWindowID,
+            kCGWindowImageBestResolution,
+        )
+    };
+    if image.is_null() {
+        return Err(screenshot_error());
+    }
+    Ok(Image(image))
+}
+
+unsafe fn text_request(options: &OcrOptions) -> id {
+    let request: id = msg_send![class!(VNRecognizeTextRequest), alloc];
+    let request: id = msg_send![request, init];
+
+    // VNRequestTextRecognitionLevel
+    let level: NSInteger = match options.level {
+        RecognitionLevel::Accurate => 0,
+        RecognitionLevel::Fast => 1,
+    };
+    let _: () = msg_send![request, setRecognitionLevel: level];
+
+    if options.languages.is_empty() {
+        let detects: BOOL =
+            msg_send![request, respondsToSelector: sel!(setAutomaticallyDetectsLanguage:)];
+        if detects != NO {
+            let _: () = msg_send![request, setAutomaticallyDetectsLanguage: YES];
+        }
+    } else {
+        let languages = options
+            .languages
+            .iter()
+            .map(|language| NSString::alloc(nil).init_str(language).autorelease())
+            .collect::<Vec<_>>();
+        let languages = NSArray::arrayWithObjects(nil, &languages);
+        let _: () = msg_send![request, setRecognitionLanguages: languages];
+    }
+    request
+}
+
+/// Runs a `VNRecognizeTextRequest` on `image` and joins the recognized lines.
+fn recognize_text(
+    image: &Image,
+    options: &OcrOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    unsafe {
+        let pool = NSAutoreleasePool::new(nil);
+        let handler_options: id = msg_send![class!(NSDictionary), dictionary];
+        let handler: id = msg_send![class!(VNImageRequestHandler), alloc];
+        let handler: id =
+            msg_send![handler, initWithCGImage: image.0 as *mut c_void options: handler_options];
+        let result = perform_text_request(handler, options);
+        pool.drain();
+        result
+    }
+}
+
+/// Like [`recognize_text`] for encoded image bytes (PNG, TIFF, JPEG...).
+pub(crate) fn recognize_image_data(
+    data: &[u8],
+    options: &OcrOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    unsafe {
+        let pool = NSAutoreleasePool::new(nil);
+        let data: id = msg_send![class!(NSData), dataWithBytes: data.as_ptr() as *const c_void length: data.len() as NSUInteger];
+        let handler_options: id = msg_send![class!(NSDictionary), dictionary];
+        let handler: id = msg_send![class!(VNImageRequestHandler), alloc];
+        let handler: id = msg_send![handler, initWithData: data options: handler_options];
+        let result = perform_text_request(handler, options);
+        pool.drain();
+        result
+    }
+}
+
+/// Performs the text request on `handler` and releases it.
+unsafe fn perform_text_request(
+    handler: id,
+    options: &OcrOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let request = text_request(options);
+
+    let mut error: id = nil;
+    let ok: BOOL = msg_send![handler, performRequests: NSArray::arrayWithObject(nil, request) error: &mut error as *mut id as *mut c_void];
+    let text = if ok == NO {
+        let description: id = msg_send![error, localizedDescription];
+        Err(crate::macos::nsstring_to_string(description)
+            .unwrap_or_else(|| "Text recognition failed".to_string())
+            .into())
+    } else {
+        let observations: id = msg_send![request, results];
+        let lines = (0..observations.count())
+            .filter_map(|i| {
+                let observation = observations.objectAtIndex(i);
+                let candidates: id = msg_send![observation, topCandidates: 1 as NSUInteger];
+                if candidates.count() == 0 {
+                    return None;
+                }
+                let candidate = candidates.objectAtIndex(0);
+                crate::macos::nsstring_to_string(msg_send![candidate, string])
+            })
+            .collect::<Vec<_>>();
+        Ok(lines.join("\n"))
+    };
+
+    let _: () = msg_send![request, release];
+    let _: () = msg_send![handler, release];
+    text
+}
+
+pub(crate) fn ocr_region(
+    rect: Rect,
+    options: &OcrOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    recognize_text(&capture_rect(rect)?, options)
+}
+
+/// Recognizes the text inside the selection's bounds, or inside the whole
+/// window when the app doesn't report any.
+pub(crate) fn recognize_selection(
+    window_id: Option<CGWindowID>,
+    options: &OcrOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let image = match (crate::macos::get_selection_bounds(), window_id) {
+        (Ok(bounds), _) if bounds.width > 0.0 && bounds.height > 0.0 => {
+            return ocr_region(bounds, options)
+        }
+        (_, Some(window_id)) => capture_window(window_id)?,
+        _ => {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Nothing to recognize text in",
+            )))
+        }
+    };
+    recognize_text(&image, options)
+}
@@ -0,0 +1,157 @@
+//! Selections of Word, Excel and Outlook read through their COM automation
+//! models (`Application.Selection`), which are far more dependable than the
+//! UI Automation trees those apps expose.
+
+use windows::core::{Interface, BSTR, GUID, HSTRING, PCWSTR};
+use windows::Win32::System::Com::{
+    CLSIDFromProgID, IDispatch, DISPATCH_METHOD, DISPATCH_PROPERTYGET, DISPPARAMS,
+};
+use windows::Win32::System::Ole::GetActiveObject;
+use windows::Win32::System::Variant::VARIANT;
+
+const LOCALE_USER_DEFAULT: u32 = 0x400;
+
+// cell by cell is one cross-process call each, past this copying is faster
+const MAX_EXCEL_CELLS: i32 = 10_000;
+
+/// The running instance registered for `prog_id`, e.g. `Word.Application`.
+fn active_object(prog_id: &str) -> Result<IDispatch, Box<dyn std::error::Error>> {
+    unsafe {
+        let clsid = CLSIDFromProgID(&HSTRING::from(prog_id))?;
+        let mut unknown = None;
+        GetActiveObject(&clsid, None, &mut unknown)?;
+        let unknown = unknown.ok_or_else(|| format!("{prog_id} is not running"))?;
+        Ok(unknown.cast()?)
+    }
+}
+
+/// Reads a property or calls a method by name.
+fn invoke(
+    object: &IDispatch,
+    name: &str,
+    args: &[VARIANT],
+) -> Result<VARIANT, Box<dyn std::error::Error>> {
+    let name = HSTRING::from(name);
+    let mut id = 0;
+    // IDispatch wants the arguments last to first
+    let mut args = args.iter().rev().cloned().collect::<Vec<_>>();
+    let params = DISPPARAMS {
+        rgvarg: args.as_mut_ptr(),
+        cArgs: args.len() as u32,
+        ..Default::default()
+    };
+    let mut result = VARIANT::default();
+    unsafe {
+        object.GetIDsOfNames(
+            &GUID::zeroed(),
+            &PCWSTR(name.as_ptr()),
+            1,
+            LOCALE_USER_DEFAULT,
+            &mut id,
+        )?;
+        object.Invoke(
+            id,
+            &GUID::zeroed(),
+            LOCALE_USER_DEFAULT,
+            DISPATCH_METHOD | DISPATCH_PROPERTYGET,
+            &params,
+            Some(&mut result),
+            None,
+            None,
+        )?;
+    }
+    Ok(result)
+}
+
+fn object(
+    object: &IDispatch,
+    name: &str,
+    args: &[VARIANT],
+) -> Result<IDispatch, Box<dyn std::error::Error>> {
+    Ok(IDispatch::try_from(&invoke(object, name, args)?)?)
+}
+
+fn text(object: &IDispatch, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(BSTR::try_from(&invoke(object, name, &[])?)?.to_string())
+}
+
+/// Word marks paragraphs with `\r`, manual line breaks with `\v` and table
+/// cells with `\r\x07`.
+fn word_selection_text(application: &IDispatch) -> Result<String, Box<dyn std::error::Error>> {
+    let text = text(&object(application, "Selection", &[])?, "Text")?;
+    Ok(text
+        .replace("\r\x07", "\t")
+        .replace(['\r', '\x0b'], "\n")
+        .trim_end_matches(['\n', '\t'])
+        .to_string())
+}
+
+/// Cells of the selected range as tab-separated rows, with the formatting
+/// the sheet displays (dates, currencies, rounding).
+fn excel_selection_text(application: &IDispatch) -> Result<String, Box<dyn std::error::Error>> {
+    let selection = object(application, "Selection", &[])?;
+    let count = |name| -> Result<i32, Box<dyn std::error::Error>> {
+        Ok(i32::try_from(&invoke(
+            &object(&selection, name, &[])?,
+            "Count",
+            &[],
+        )?)?)
+    };
+    // fails for charts and shapes, which aren't ranges
+    let (rows, columns) = (count("Rows")?, count("Columns")?);
+    if rows.saturating_mul(columns) > MAX_EXCEL_CELLS {
+        return Err("Too many cells selected".into());
+    }
+    let mut lines = Vec::new();
+    for row in 1..=rows {
+        let mut cells = Vec::new();
+        for column in 1..=columns {
+            let cell = object(&selection, "Cells", &[row.into(), column.into()])?;
+            cells.push(text(&cell, "Text")?);
+        }
+        lines.push(cells.join("\t"));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Outlook edits mail in an embedded Word, reachable from the open message
+/// window or the reply being written in the reading pane.
+fn outlook_selection_text(
+    application: &IDispatch,
+    window_title: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let editor = match object(application, "ActiveInspector", &[]) {
+        // ActiveInspector is the topmost message window even when the main
+        // window is in front of it
+        Ok(inspector) if text(&inspector, "Caption").is_ok_and(|c| c == window_title) => {
+            object(&inspector, "WordEditor", &[])?
+        }
+        _ => object(
+            &object(application, "ActiveExplorer", &[])?,
+            "ActiveInlineResponseWordEditor",
+            &[],
+        )?,
+    };
+    word_selection_text(&object(&editor, "Application", &[])?)
+}
+
+/// Reads the selection when the foreground window belongs to an Office app,
+/// `None` for any other app. COM must be initialized on the calling thread.
+pub(crate) fn get_selected_text() -> Option<Result<String, Box<dyn std::error::Error>>> {
+    let window = crate::window::active_window().ok()?;
+    let process_name = std::path::Path::new(&window.process_path?)
+        .file_name()?
+        .to_string_lossy()
+        .to_lowercase();
+    Some(match process_name.as_str() {
+        "winword.exe" => {
+            active_object("Word.Application").and_then(|app| word_selection_text(&app))
+        }
+        "excel.exe" => {
+            active_object("Excel.Application").and_then(|app| excel_selection_text(&app))
+        }
+        "outlook.exe" => active_object("Outlook.Application")
+            .and_then(|app| outlook_selection_text(&app, &window.title)),
+        _ => return None,
+    })
+}
@@ -0,0 +1,388 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::CancellationToken;
+
+static DEFAULT_OPTIONS: Mutex<Option<CaptureOptions>> = Mutex::new(None);
+
+/// Tweaks for [`crate::get_selected_text_with_options`]. [`Self::new`]
+/// starts from the options [`crate::get_selected_text`] uses, see
+/// [`set_default_capture_options`].
+///
+/// ```
+/// use get_selected_text::CaptureOptions;
+///
+/// let options = CaptureOptions::new().ocr(true);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CaptureOptions {
+    pub(crate) ocr: bool,
+    pub(crate) ocr_options: OcrOptions,
+    pub(crate) image_text: bool,
+    pub(crate) cancel: CancellationToken,
+    pub(crate) strategies: Option<Vec<Strategy>>,
+    pub(crate) forbidden: Vec<Strategy>,
+    pub(crate) unsupported_app_cooldown: Option<Duration>,
+}
+
+/// How long an app where every method failed is skipped by default.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+const UNSUPPORTED_APP_COOLDOWN: Duration = Duration::from_secs(60);
+
+impl CaptureOptions {
+    /// The defaults set with [`set_default_capture_options`], otherwise the
+    /// same as [`Default::default`].
+    pub fn new() -> Self {
+        DEFAULT_OPTIONS.lock().clone().unwrap_or_default()
+    }
+
+    /// Falls back to screenshotting the selection (or the focused window) and
+    /// running text recognition on it when every other method came back
+    /// empty. Off by default; needs the Screen Recording permission and only
+    /// works on macOS.
+    pub fn ocr(mut self, enabled: bool) -> Self {
+        self.ocr = enabled;
+        self
+    }
+
+    /// When the selection is an image (a web image, a preview), returns it as
+    /// PNG in `SelectedText::image` and its recognized text as the text, for
+    /// "translate this screenshot" flows. Off by default, only on macOS.
+    pub fn image_text(mut self, enabled: bool) -> Self {
+        self.image_text = enabled;
+        self
+    }
+
+    /// Languages and accuracy used by the OCR fallback and [`Self::image_text`].
+    pub fn ocr_options(mut self, options: OcrOptions) -> Self {
+        self.ocr_options = options;
+        self
+    }
+
+    /// Aborts the capture once `token` is cancelled, e.g. when the hotkey is
+    /// pressed again or the UI is closed. An in-flight copy stops at its next
+    /// step, after the clipboard is put back, and the capture fails with
+    /// [`std::io::ErrorKind::Interrupted`].
+    pub fn cancel(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Tries exactly `strategies`, in order, until one finds a selection,
+    /// instead of the built-in chain. They're reordered by how they did in the
+    /// focused app. Strategies the platform doesn't have are skipped.
+    ///
+    /// ```
+    /// use get_selected_text::{CaptureOptions, Strategy};
+    ///
+    /// // never touch the clipboard
+    /// let options = CaptureOptions::new().strategies([Strategy::AppSpecific, Strategy::Accessibility]);
+    /// ```
+    pub fn strategies(mut self, strategies: impl IntoIterator<Item = Strategy>) -> Self {
+        self.strategies = Some(strategies.into_iter().collect());
+        self
+    }
+
+    /// Never uses `strategy`, even when [`Self::strategies`] lists it.
+    pub fn forbid(mut self, strategy: Strategy) -> Self {
+        self.forbidden.push(strategy);
+        self
+    }
+
+    /// After every method failed in an app (games, virtual machine windows),
+    /// fails right away with [`std::io::ErrorKind::Unsupported`] there for
+    /// `cooldown` instead of trying them all again on each hotkey press. A
+    /// minute by default, `Duration::ZERO` turns it off. Needs the
+    /// `method-cache` feature.
+    pub fn unsupported_app_cooldown(mut self, cooldown: Duration) -> Self {
+        self.unsupported_app_cooldown = Some(cooldown);
+        self
+    }
+
+    /// The strategies to try, `None` for the built-in chain.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    pub(crate) fn chain(&self) -> Option<Vec<Strategy>> {
+        if self.strategies.is_none() && self.forbidden.is_empty() {
+            return None;
+        }
+        let chain = self.strategies.clone().unwrap_or_else(|| {
+            let mut chain = vec![
+                Strategy::AppSpecific,
+                Strategy::Accessibility,
+                Strategy::Clipboard,
+            ];
+            if self.ocr {
+                chain.push(Strategy::Ocr);
+            }
+            chain
+        });
+        Some(
+            chain
+                .into_iter()
+                .filter(|strategy| !self.forbidden.contains(strategy))
+                .collect(),
+        )
+    }
+}
+
+/// Makes `options` the ones [`crate::get_selected_text`] uses and
+/// [`CaptureOptions::new`] starts from, e.g. to never OCR anywhere in the
+/// app, or to leave the clipboard to an explicit retry:
+///
+/// ```
+/// use get_selected_text::{set_default_capture_options, CaptureOptions, Strategy};
+///
+/// set_default_capture_options(CaptureOptions::new().forbid(Strategy::Ocr).strategies([
+///     Strategy::AppSpecific,
+///     Strategy::Accessibility,
+/// ]));
+/// // on "try harder"
+/// let retry = CaptureOptions::new().strategies([Strategy::Clipboard]);
+/// ```
+pub fn set_default_capture_options(options: CaptureOptions) {
+    *DEFAULT_OPTIONS.lock() = Some(options);
+}
+
+/// A way of reading the selection, see [`CaptureOptions::strategies`]. From
+/// least to most invasive, roughly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Integrations with particular apps: Finder's selected files on macOS,
+    /// consoles and the Office object models on Windows.
+    AppSpecific,
+    /// The selection as exposed without key presses: AX on macOS, UI
+    /// Automation on Windows, AT-SPI and the PRIMARY selection on Linux.
+    Accessibility,
+    /// Simulating a copy and reading the clipboard, which is restored
+    /// afterwards.
+    Clipboard,
+    /// Text recognition on a screenshot of the selection, macOS only.
+    Ocr,
+}
+
+/// Runs `capture` for each of `strategies` until one returns a selection,
+/// in the order that worked best in `app` so far (see [`crate::stats`]).
+/// Fails with the last error only when no strategy could look at all, and
+/// then right away in `app` for the options' cooldown.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+pub(crate) fn try_strategies<T>(
+    strategies: &[Strategy],
+    app: &str,
+    options: &CaptureOptions,
+    mut capture: impl FnMut(Strategy) -> Result<Option<T>, Box<dyn std::error::Error>>,
+) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    let cooldown = options
+        .unsupported_app_cooldown
+        .unwrap_or(UNSUPPORTED_APP_COOLDOWN);
+    if crate::stats::recently_unsupported(app, cooldown) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("Nothing could read the selection in {app} recently"),
+        )));
+    }
+
+    let mut tried = Vec::new();
+    let mut error = None;
+    let mut looked = false;
+    for strategy in crate::stats::rank(app, strategies) {
+        if options.cancel.is_cancelled() {
+            return Err(crate::cancel::cancelled_error());
+        }
+        let start = std::time::Instant::now();
+        let result = capture(strategy);
+        tried.push((strategy, start.elapsed()));
+        match result {
+            Ok(Some(selection)) => {
+                crate::stats::record(app, strategies, &tried);
+                return Ok(Some(selection));
+            }
+            Ok(None) => looked = true,
+            Err(e) => error = Some(e),
+        }
+    }
+    match error {
+        Some(e) if !looked => {
+            if !options.cancel.is_cancelled() && is_about_the_app(&*e) {
+                crate::stats::mark_unsupported(app);
+            }
+            Err(e)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Whether `error` says the app can't be read, rather than the capture
+/// being cancelled, a permission missing or the moment being wrong.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+fn is_about_the_app(error: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(e) = error.downcast_ref::<std::io::Error>() {
+        return !matches!(
+            e.kind(),
+            std::io::ErrorKind::Interrupted | std::io::ErrorKind::PermissionDenied
+        );
+    }
+    error
+        .downcast_ref::<crate::GetTextError>()
+        .is_none_or(|e| !e.is_momentary() && !e.is_permission())
+}
+
+/// Speed / accuracy trade-off of text recognition.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecognitionLevel {
+    /// Character-by-character recognition, a few times faster but worse with
+    /// small, stylized or rotated text.
+    Fast,
+    /// Neural-network based, handles mixed languages and CJK far better.
+    #[default]
+    Accurate,
+}
+
+/// Settings for text recognition, see [`crate::ocr_region`] and
+/// [`CaptureOptions::ocr_options`].
+///
+/// ```
+/// use get_selected_text::{OcrOptions, RecognitionLevel};
+///
+/// let options = OcrOptions::new()
+///     .languages(["ja-JP", "en-US"])
+///     .level(RecognitionLevel::Accurate);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OcrOptions {
+    pub(crate) languages: Vec<String>,
+    pub(crate) level: RecognitionLevel,
+}
+
+impl OcrOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// BCP 47 language codes to recognize, in priority order. Empty lets the
+    /// system detect the language where it can (macOS 13+), otherwise it
+    /// assumes English.
+    pub fn languages<I, S>(mut self, languages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.languages = languages.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn level(mut self, level: RecognitionLevel) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+#[cfg(test)]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_strategies() {
+        let options = CaptureOptions::default();
+        let unsupported = || -> Box<dyn std::error::Error> { "unsupported".into() };
+        let strategies = [Strategy::Ocr, Strategy::Accessibility, Strategy::Clipboard];
+
+        let mut tried = Vec::new();
+        let found = try_strategies(&strategies, "test", &options, |strategy| {
+            tried.push(strategy);
+            match strategy {
+                Strategy::Ocr => Err(unsupported()),
+                Strategy::Accessibility => Ok(Some("text")),
+                _ => Ok(None),
+            }
+        });
+        assert_eq!(found.unwrap(), Some("text"));
+        assert_eq!(tried, [Strategy::Ocr, Strategy::Accessibility]);
+
+        // what worked in the app goes first
+        #[cfg(feature = "method-cache")]
+        {
+            tried.clear();
+            let found = try_strategies(&strategies, "test", &options, |strategy| {
+                tried.push(strategy);
+                Ok(Some(strategy))
+            });
+            assert_eq!(found.unwrap(), Some(Strategy::Accessibility));
+            assert_eq!(tried, [Strategy::Accessibility]);
+        }
+
+        // empty beats failing
+        let found = try_strategies(&strategies, "other", &options, |strategy| match strategy {
+            Strategy::Clipboard => Ok(None::<&str>),
+            _ => Err(unsupported()),
+        });
+        assert_eq!(found.unwrap(), None);
+
+        let found = try_strategies(&strategies, "other", &options, |_| {
+            Err::<Option<&str>, _>(unsupported())
+        });
+        assert!(found.is_err());
+
+        // and then not tried at all for a while
+        #[cfg(feature = "method-cache")]
+        {
+            let found = try_strategies(
+                &strategies,
+                "other",
+                &options,
+                |_| -> Result<Option<&str>, _> { panic!("tried again") },
+            );
+            assert_eq!(
+                found
+                    .unwrap_err()
+                    .downcast_ref::<std::io::Error>()
+                    .unwrap()
+                    .kind(),
+                std::io::ErrorKind::Unsupported
+            );
+            let options = CaptureOptions::default().unsupported_app_cooldown(Duration::ZERO);
+            let found = try_strategies(&strategies, "other", &options, |_| Ok(Some("text")));
+            assert_eq!(found.unwrap(), Some("text"));
+        }
+    }
+
+    #[test]
+    fn test_try_strategies_without_cooldown() {
+        let strategies = [Strategy::Accessibility];
+        let cooldown = UNSUPPORTED_APP_COOLDOWN;
+
+        let token = CancellationToken::new();
+        let options = CaptureOptions::default().cancel(token.clone());
+        let found = try_strategies(&strategies, "cancelled", &options, |_| {
+            token.cancel();
+            Err::<Option<&str>, _>(crate::cancel::cancelled_error())
+        });
+        assert!(found.is_err());
+        assert!(!crate::stats::recently_unsupported("cancelled", cooldown));
+
+        let options = CaptureOptions::default();
+        let found = try_strategies(&strategies, "denied", &options, |_| {
+            Err::<Option<&str>, _>(Box::new(std::io::Error::from(
+                std::io::ErrorKind::PermissionDenied,
+            )))
+        });
+        assert!(found.is_err());
+        assert!(!crate::stats::recently_unsupported("denied", cooldown));
+
+        let found = try_strategies(&strategies, "not-authorized", &options, |_| {
+            Err::<Option<&str>, _>(Box::new(crate::GetTextError::AppleScript {
+                status: std::process::ExitStatus::default(),
+                stderr: String::new(),
+                kind: crate::AppleScriptErrorKind::NotAuthorized,
+                code: Some(-1743),
+            }))
+        });
+        assert!(found.is_err());
+        assert!(!crate::stats::recently_unsupported(
+            "not-authorized",
+            cooldown
+        ));
+    }
+}
@@ -0,0 +1,489 @@
+//! The RemoteDesktop and Clipboard desktop portals, the only way to press keys
+//! and reach the clipboard from inside a Flatpak or Snap sandbox. Starting the
+//! session asks the user once per process, a refusal stands until
+//! [`close_session`].
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::message::Type;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::MatchRule;
+
+use crate::uinput::{evdev_keycode, KEY_LEFTCTRL};
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+static NEXT_TOKEN: AtomicUsize = AtomicUsize::new(0);
+/// Whether the user declined the session, so captures don't ask again.
+static DENIED: AtomicBool = AtomicBool::new(false);
+
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+const REMOTE_DESKTOP: &str = "org.freedesktop.portal.RemoteDesktop";
+const CLIPBOARD: &str = "org.freedesktop.portal.Clipboard";
+
+const MIME_TYPE: &str = "text/plain;charset=utf-8";
+// what GTK and Qt put on the clipboard next to `text/uri-list`: a key to
+// the copied files, exported by the FileTransfer portal
+const FILE_TRANSFER_MIME_TYPE: &str = "application/vnd.portal.filetransfer";
+
+const DOCUMENTS: &str = "org.freedesktop.portal.Documents";
+const DOCUMENTS_PATH: &str = "/org/freedesktop/portal/documents";
+const FILE_TRANSFER: &str = "org.freedesktop.portal.FileTransfer";
+
+const DEVICE_KEYBOARD: u32 = 1;
+// keep the permission until it's revoked, instead of the process's lifetime
+const PERSIST_MODE_PERSISTENT: u32 = 2;
+
+// how long the app gets to answer ctrl+c
+const COPY_TIMEOUT: Duration = Duration::from_millis(300);
+// long enough to answer the portal's dialog, the session lock is held
+// meanwhile
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Whether we run inside a Flatpak or Snap sandbox.
+pub(crate) fn is_sandboxed() -> bool {
+    std::path::Path::new("/.flatpak-info").exists() || std::env::var_os("SNAP").is_some()
+}
+
+/// Whether a portal implementation is on the session bus.
+pub(crate) fn is_available() -> bool {
+    let has_owner = || -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = Connection::session()?;
+        Ok(zbus::blocking::fdo::DBusProxy::new(&conn)?.name_has_owner(DESTINATION.try_into()?)?)
+    };
+    has_owner().unwrap_or(false)
+}
+
+type Options<'a> = HashMap<&'a str, Value<'a>>;
+
+/// Calls a portal method answering through a Request object and returns the
+/// results of its Response, failing if the user cancelled or nothing came
+/// within [`RESPONSE_TIMEOUT`].
+fn request(
+    conn: &Connection,
+    interface: &str,
+    method: &str,
+    body: &(impl serde::Serialize + zbus::zvariant::DynamicType),
+    token: &str,
+) -> Result<HashMap<String, OwnedValue>, Box<dyn std::error::Error>> {
+    let sender = conn
+        .unique_name()
+        .ok_or("Not connected to the session bus")?
+        .trim_start_matches(':')
+        .replace('.', "_");
+    let path = format!("{OBJECT_PATH}/request/{sender}/{token}");
+    // subscribe first, the response can come before the call returns
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface("org.freedesktop.portal.Request")?
+        .member("Response")?
+        .path(path)?
+        .build();
+    let mut responses = MessageIterator::for_match_rule(rule, conn, None)?;
+    conn.call_method(
+        Some(DESTINATION),
+        OBJECT_PATH,
+        Some(interface),
+        method,
+        body,
+    )?;
+    // waited for on a thread, which ends when the connection is closed if
+    // the answer never comes
+    let (sender, response) = mpsc::channel();
+    thread::spawn(move || sender.send(responses.next()));
+    let response = match response.recv_timeout(RESPONSE_TIMEOUT) {
+        Ok(response) => response.ok_or("The portal didn't answer")??,
+        Err(_) => {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "The portal didn't answer in time",
+            )))
+        }
+    };
+    let (code, results): (u32, HashMap<String, OwnedValue>) = response.body().deserialize()?;
+    if code != 0 {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "Keyboard and clipboard access was not granted",
+        )));
+    }
+    Ok(results)
+}
+
+fn new_token() -> String {
+    format!(
+        "get_selected_text_{}",
+        NEXT_TOKEN.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+struct Session {
+    conn: Connection,
+    handle: OwnedObjectPath,
+    /// The text served while we own the clipboard.
+    offer: Arc<Mutex<String>>,
+    owner_changes: mpsc::Receiver<OwnerChange>,
+}
+
+/// A new clipboard owner.
+struct OwnerChange {
+    is_owner: bool,
+    mime_types: Vec<String>,
+}
+
+/// Creates a RemoteDesktop session, returning its handle.
+fn create_session(conn: &Connection) -> Result<OwnedObjectPath, Box<dyn std::error::Error>> {
+    let token = new_token();
+    let session_token = new_token();
+    let options: Options = HashMap::from([
+        ("handle_token", Value::from(token.as_str())),
+        ("session_handle_token", Value::from(session_token.as_str())),
+    ]);
+    let results = request(conn, REMOTE_DESKTOP, "CreateSession", &(options,), &token)?;
+    let handle = results
+        .get("session_handle")
+        .ok_or("The portal didn't create a session")?;
+    Ok(OwnedObjectPath::try_from(String::try_from(
+        handle.try_clone()?,
+    )?)?)
+}
+
+impl Session {
+    /// Creates and starts a session, closing what was opened when that fails.
+    fn start() -> Result<Self, Box<dyn std::error::Error>> {
+        let conn = Connection::session()?;
+        let handle = match create_session(&conn) {
+            Ok(handle) => handle,
+            Err(e) => {
+                let _ = conn.close();
+                return Err(e);
+            }
+        };
+        let (sender, owner_changes) = mpsc::channel();
+        let session = Self {
+            conn,
+            handle,
+            offer: Arc::new(Mutex::new(String::new())),
+            owner_changes,
+        };
+        match session.begin(sender) {
+            Ok(()) => Ok(session),
+            Err(e) => {
+                session.close();
+                Err(e)
+            }
+        }
+    }
+
+    /// Asks for the keyboard and the clipboard, then starts serving the
+    /// clipboard once they were granted.
+    fn begin(
+        &self,
+        owner_changes: mpsc::Sender<OwnerChange>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (conn, handle) = (&self.conn, &self.handle);
+        let token = new_token();
+        let options: Options = HashMap::from([
+            ("handle_token", Value::from(token.as_str())),
+            ("types", Value::from(DEVICE_KEYBOARD)),
+            ("persist_mode", Value::from(PERSIST_MODE_PERSISTENT)),
+        ]);
+        request(
+            conn,
+            REMOTE_DESKTOP,
+            "SelectDevices",
+            &(handle, options),
+            &token,
+        )?;
+        conn.call_method(
+            Some(DESTINATION),
+            OBJECT_PATH,
+            Some(CLIPBOARD),
+            "RequestClipboard",
+            &(handle, Options::new()),
+        )?;
+
+        // subscribed before starting, the first signals can come right after
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface(CLIPBOARD)?
+            .build();
+        let signals = MessageIterator::for_match_rule(rule, conn, None)?;
+
+        let token = new_token();
+        let options: Options = HashMap::from([("handle_token", Value::from(token.as_str()))]);
+        let results = request(
+            conn,
+            REMOTE_DESKTOP,
+            "Start",
+            &(handle, "", options),
+            &token,
+        )?;
+        let clipboard_enabled = results
+            .get("clipboard_enabled")
+            .is_some_and(|enabled| bool::try_from(enabled).unwrap_or(false));
+        if !clipboard_enabled {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Clipboard access was not granted",
+            )));
+        }
+
+        let serving = (conn.clone(), handle.clone(), self.offer.clone());
+        thread::spawn(move || serve_clipboard(signals, serving, owner_changes));
+        Ok(())
+    }
+
+    /// Closes the session and the connection, which ends the threads
+    /// serving the clipboard or waiting for a response.
+    fn close(self) {
+        let _ = self.conn.call_method(
+            Some(DESTINATION),
+            self.handle.as_str(),
+            Some("org.freedesktop.portal.Session"),
+            "Close",
+            &(),
+        );
+        let _ = self.conn.close();
+    }
+
+    fn press(&self, keycode: u16, pressed: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.call_method(
+            Some(DESTINATION),
+            OBJECT_PATH,
+            Some(REMOTE_DESKTOP),
+            "NotifyKeyboardKeycode",
+            &(&self.handle, Options::new(), keycode as i32, pressed as u32),
+        )?;
+        Ok(())
+    }
+
+    /// Presses ctrl + `key`.
+    fn shortcut(&self, key: char) -> Result<(), Box<dyn std::error::Error>> {
+        let keycode = evdev_keycode(enigo::Key::Unicode(key))?;
+        self.press(KEY_LEFTCTRL, true)?;
+        self.press(keycode, true)?;
+        self.press(keycode, false)?;
+        self.press(KEY_LEFTCTRL, false)
+    }
+
+    fn read(&self, mime_type: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let reply = self.conn.call_method(
+            Some(DESTINATION),
+            OBJECT_PATH,
+            Some(CLIPBOARD),
+            "SelectionRead",
+            &(&self.handle, mime_type),
+        )?;
+        let fd: zbus::zvariant::OwnedFd = reply.body().deserialize()?;
+        let mut text = String::new();
+        File::from(std::os::fd::OwnedFd::from(fd)).read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    fn read_text(&self) -> Result<String, Box<dyn std::error::Error>> {
+        self.read(MIME_TYPE)
+    }
+
+    /// The copied files, as paths under the document portal's mount, which
+    /// unlike the copier's own paths are reachable from the sandbox.
+    fn read_files(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let key = self.read(FILE_TRANSFER_MIME_TYPE)?;
+        let reply = self.conn.call_method(
+            Some(DOCUMENTS),
+            DOCUMENTS_PATH,
+            Some(FILE_TRANSFER),
+            "RetrieveFiles",
+            &(key.trim_end_matches('\0'), Options::new()),
+        )?;
+        Ok(reply.body().deserialize()?)
+    }
+
+    fn set_text(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        *self.offer.lock() = text.to_string();
+        let options: Options = HashMap::from([("mime_types", Value::from(vec![MIME_TYPE]))]);
+        self.conn.call_method(
+            Some(DESTINATION),
+            OBJECT_PATH,
+            Some(CLIPBOARD),
+            "SetSelection",
+            &(&self.handle, options),
+        )?;
+        Ok(())
+    }
+
+    /// Sends ctrl + `key`, returning the MIME types of the new clipboard
+    /// owner if another app took the clipboard in response.
+    fn shortcut_changing_owner(
+        &self,
+        key: char,
+    ) -> Result<Option<Vec<String>>, Box<dyn std::error::Error>> {
+        while self.owner_changes.try_recv().is_ok() {}
+        self.shortcut(key)?;
+        Ok(self
+            .owner_changes
+            .recv_timeout(COPY_TIMEOUT)
+            .ok()
+            .filter(|change| !change.is_owner)
+            .map(|change| change.mime_types))
+    }
+}
+
+/// Answers the compositor's requests for the text we offer, until the
+/// session is gone.
+fn serve_clipboard(
+    signals: MessageIterator,
+    (conn, handle, offer): (Connection, OwnedObjectPath, Arc<Mutex<String>>),
+    owner_changes: mpsc::Sender<OwnerChange>,
+) {
+    for signal in signals.flatten() {
+        let header = signal.header();
+        match header.member().map(|member| member.as_str()) {
+            Some("SelectionTransfer") => {
+                let Ok((_, _, serial)) = signal
+                    .body()
+                    .deserialize::<(OwnedObjectPath, String, u32)>()
+                else {
+                    continue;
+                };
+                let write = || -> Result<(), Box<dyn std::error::Error>> {
+                    let reply = conn.call_method(
+                        Some(DESTINATION),
+                        OBJECT_PATH,
+                        Some(CLIPBOARD),
+                        "SelectionWrite",
+                        &(&handle, serial),
+                    )?;
+                    let fd: zbus::zvariant::OwnedFd = reply.body().deserialize()?;
+                    File::from(std::os::fd::OwnedFd::from(fd))
+                        .write_all(offer.lock().as_bytes())?;
+                    Ok(())
+                };
+                let success = write().is_ok();
+                let _ = conn.call_method(
+                    Some(DESTINATION),
+                    OBJECT_PATH,
+                    Some(CLIPBOARD),
+                    "SelectionWriteDone",
+                    &(&handle, serial, success),
+                );
+            }
+            Some("SelectionOwnerChanged") => {
+                let Ok((_, options)) = signal
+                    .body()
+                    .deserialize::<(OwnedObjectPath, HashMap<String, OwnedValue>)>()
+                else {
+                    continue;
+                };
+                let is_owner = options
+                    .get("session_is_owner")
+                    .is_some_and(|is_owner| bool::try_from(is_owner).unwrap_or(false));
+                let mime_types = options
+                    .get("mime_types")
+                    .and_then(|mime_types| mime_types.try_clone().ok())
+                    .and_then(|mime_types| Vec::<String>::try_from(mime_types).ok())
+                    .unwrap_or_default();
+                let change = OwnerChange {
+                    is_owner,
+                    mime_types,
+                };
+                if owner_changes.send(change).is_err() {
+                    return;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn session() -> Result<MappedMutexGuard<'static, Session>, Box<dyn std::error::Error>> {
+    let mut session = SESSION.lock();
+    if session.is_none() {
+        if DENIED.load(Ordering::SeqCst) {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Keyboard and clipboard access was not granted",
+            )));
+        }
+        let started = Session::start().inspect_err(|e| {
+            let denied = e
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied);
+            DENIED.store(denied, Ordering::SeqCst);
+        })?;
+        *session = Some(started);
+    }
+    Ok(MutexGuard::map(session, |session| {
+        session.as_mut().unwrap()
+    }))
+}
+
+/// Closes the session, which also ends the thread serving the clipboard, and
+/// forgets a refusal. The next capture asks for a new one.
+pub(crate) fn close_session() {
+    let mut session = SESSION.lock();
+    DENIED.store(false, Ordering::SeqCst);
+    if let Some(session) = session.take() {
+        session.close();
+    }
+}
+
+/// Copies the selection with ctrl+c, then puts the previous clipboard text
+/// back. Nothing is selected when the clipboard doesn't change. Files
+/// selected in a file manager come back one path per line.
+pub(crate) fn get_selected_text(
+    cancel: &crate::CancellationToken,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let session = session()?;
+    if cancel.is_cancelled() {
+        return Err(crate::cancel::cancelled_error());
+    }
+    let _round_trip = crate::round_trip::begin()?;
+    let previous = session.read_text().ok();
+    let Some(mime_types) = session.shortcut_changing_owner('c')? else {
+        return Ok(String::new());
+    };
+    if cancel.is_cancelled() {
+        if let Some(previous) = previous {
+            session.set_text(&previous)?;
+        }
+        return Err(crate::cancel::cancelled_error());
+    }
+    let files = mime_types
+        .iter()
+        .any(|mime_type| mime_type == FILE_TRANSFER_MIME_TYPE)
+        .then(|| session.read_files().ok())
+        .flatten();
+    // put back also when reading the copy failed
+    let text = match files {
+        Some(files) => Ok(files.join("\n")),
+        None => session.read_text(),
+    };
+    if let Some(previous) = previous {
+        session.set_text(&previous)?;
+    }
+    text
+}
+
+/// Pastes `text` with ctrl+v, then puts the previous clipboard text back.
+pub(crate) fn paste_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let session = session()?;
+    let _round_trip = crate::round_trip::begin()?;
+    let previous = session.read_text().ok();
+    session.set_text(text)?;
+    let pasted = session.shortcut('v');
+    // give the app time to read the clipboard before it's restored
+    thread::sleep(Duration::from_millis(200));
+    if let Some(previous) = previous {
+        session.set_text(&previous)?;
+    }
+    pasted
+}
@@ -0,0 +1,138 @@
+//! The capture engine as a value, for apps that want to swap the system out
+//! in their own tests rather than go through the free functions and their
+//! process-wide state.
+
+use crate::CaptureOptions;
+
+/// What a capture returns on this platform, as with
+/// [`get_selected_text`](crate::get_selected_text): a [`SelectedText`](crate::SelectedText)
+/// on macOS, the text elsewhere.
+#[cfg(target_os = "macos")]
+pub type Selection = crate::SelectedText;
+#[cfg(not(target_os = "macos"))]
+pub type Selection = String;
+
+/// Where a [`Capturer`] gets the selection from. [`SystemProvider`] reads
+/// the real one, tests implement it with canned answers.
+pub trait SelectionProvider: Send + Sync {
+    /// See [`get_selected_text_with_options`](crate::get_selected_text_with_options).
+    fn get_selected_text(
+        &self,
+        options: &CaptureOptions,
+    ) -> Result<Selection, Box<dyn std::error::Error>>;
+
+    /// See [`try_get_selected_text`](crate::try_get_selected_text).
+    fn try_get_selected_text(&self) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// See [`replace_selected_text`](crate::replace_selected_text).
+    fn replace_selected_text(&self, text: &str) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// The system's selection, through the same functions the crate exports.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemProvider;
+
+impl SelectionProvider for SystemProvider {
+    fn get_selected_text(
+        &self,
+        options: &CaptureOptions,
+    ) -> Result<Selection, Box<dyn std::error::Error>> {
+        crate::get_selected_text_with_options(options)
+    }
+
+    fn try_get_selected_text(&self) -> Result<String, Box<dyn std::error::Error>> {
+        crate::try_get_selected_text()
+    }
+
+    fn replace_selected_text(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        crate::replace_selected_text(text)
+    }
+}
+
+/// Captures through a [`SelectionProvider`], the system's unless told
+/// otherwise. Code taking a `Capturer<P>` (or a `Capturer<Box<dyn
+/// SelectionProvider>>`) can be handed a fake in tests:
+///
+/// ```
+/// use get_selected_text::{CaptureOptions, Capturer, Selection, SelectionProvider};
+///
+/// struct Fake;
+///
+/// impl SelectionProvider for Fake {
+///     fn get_selected_text(&self, _: &CaptureOptions) -> Result<Selection, Box<dyn std::error::Error>> {
+///         Ok(Selection::default())
+///     }
+///     fn try_get_selected_text(&self) -> Result<String, Box<dyn std::error::Error>> {
+///         Ok("hello".to_string())
+///     }
+///     fn replace_selected_text(&self, _: &str) -> Result<(), Box<dyn std::error::Error>> {
+///         Ok(())
+///     }
+/// }
+///
+/// let capturer = Capturer::new(Fake);
+/// assert_eq!(capturer.try_get_selected_text().unwrap(), "hello");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Capturer<P = SystemProvider> {
+    provider: P,
+    options: CaptureOptions,
+}
+
+impl Default for Capturer {
+    fn default() -> Self {
+        Self::new(SystemProvider)
+    }
+}
+
+impl<P: SelectionProvider> Capturer<P> {
+    /// Captures with [`CaptureOptions::new`].
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider,
+            options: CaptureOptions::new(),
+        }
+    }
+
+    /// Captures with `options` instead.
+    pub fn options(mut self, options: CaptureOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    pub fn get_selected_text(&self) -> Result<Selection, Box<dyn std::error::Error>> {
+        if self.options.cancel.is_cancelled() {
+            return Err(crate::cancel::cancelled_error());
+        }
+        self.provider.get_selected_text(&self.options)
+    }
+
+    pub fn try_get_selected_text(&self) -> Result<String, Box<dyn std::error::Error>> {
+        self.provider.try_get_selected_text()
+    }
+
+    pub fn replace_selected_text(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.provider.replace_selected_text(text)
+    }
+}
+
+impl<P: SelectionProvider + ?Sized> SelectionProvider for Box<P> {
+    fn get_selected_text(
+        &self,
+        options: &CaptureOptions,
+    ) -> Result<Selection, Box<dyn std::error::Error>> {
+        (**self).get_selected_text(options)
+    }
+
+    fn try_get_selected_text(&self) -> Result<String, Box<dyn std::error::Error>> {
+        (**self).try_get_selected_text()
+    }
+
+    fn replace_selected_text(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).replace_selected_text(text)
+    }
+}
@@ -0,0 +1,90 @@
+//! Python bindings, built with [maturin](https://www.maturin.rs) (see `pyproject.toml`).
+//!
+//! ```python
+//! import get_selected_text
+//!
+//! selected = get_selected_text.get_selected_text()
+//! print(selected.app_name, selected.text)
+//!
+//! selected = await get_selected_text.get_selected_text_async()
+//! ```
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+
+#[pyclass(name = "SelectedText", frozen, get_all, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct PySelectedText {
+    is_file_paths: bool,
+    app_name: String,
+    bundle_id: Option<String>,
+    process_id: Option<i32>,
+    app_version: Option<String>,
+    window_id: Option<u32>,
+    text: Vec<String>,
+}
+
+#[pymethods]
+impl PySelectedText {
+    fn __repr__(&self) -> String {
+        format!(
+            "SelectedText(is_file_paths={}, app_name={:?}, bundle_id={:?}, text={:?})",
+            self.is_file_paths, self.app_name, self.bundle_id, self.text
+        )
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl From<crate::SelectedText> for PySelectedText {
+    fn from(selected: crate::SelectedText) -> Self {
+        Self {
+            is_file_paths: selected.is_file_paths,
+            app_name: selected.app_name,
+            bundle_id: selected.bundle_id,
+            process_id: selected.process_id,
+            app_version: selected.app_version,
+            window_id: selected.window_id,
+            text: selected.text,
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+impl From<String> for PySelectedText {
+    fn from(text: String) -> Self {
+        Self {
+            is_file_paths: false,
+            app_name: String::new(),
+            bundle_id: None,
+            process_id: None,
+            app_version: None,
+            window_id: None,
+            text: vec![text],
+        }
+    }
+}
+
+/// Captures the current selection. The GIL is released while capturing.
+#[pyfunction]
+fn get_selected_text(py: Python<'_>) -> PyResult<PySelectedText> {
+    py.detach(|| crate::get_selected_text().map_err(|e| crate::error::error_chain(&*e)))
+        .map(PySelectedText::from)
+        .map_err(PyRuntimeError::new_err)
+}
+
+/// Same as `get_selected_text`, but runs in the event loop's default executor
+/// and returns an awaitable.
+#[pyfunction]
+fn get_selected_text_async<'py>(py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    let capture = wrap_pyfunction!(get_selected_text, py)?;
+    event_loop.call_method1("run_in_executor", (py.None(), capture))
+}
+
+#[pymodule(name = "get_selected_text")]
+fn py_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySelectedText>()?;
+    m.add_function(wrap_pyfunction!(get_selected_text, m)?)?;
+    m.add_function(wrap_pyfunction!(get_selected_text_async, m)?)?;
+    Ok(())
+}
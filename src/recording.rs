@@ -0,0 +1,491 @@
+//! Recording the accessibility queries a capture makes and replaying them,
+//! enabled with the `ax-record` feature.
+//!
+//! Someone hit by an app-specific bug runs [`record_capture`] and sends the
+//! file it wrote; [`Replay`] loads it on any Mac, without the app installed,
+//! and runs the capture again with every accessibility query answered from
+//! the recording, so it takes the same path through the capture logic:
+//!
+//! ```no_run
+//! use get_selected_text::recording::{record_capture, Replay};
+//! use get_selected_text::{CaptureOptions, Capturer};
+//!
+//! let _ = record_capture(&CaptureOptions::new(), "capture.json");
+//!
+//! let replay = Replay::load("capture.json").unwrap();
+//! for query in &replay.recording().queries {
+//!     println!("{} {}: {:?}", query.element, query.attribute, query.value);
+//! }
+//! let selection = Capturer::new(replay).get_selected_text();
+//! ```
+//!
+//! A recording holds what the queries read in clear: the selection, but also
+//! window titles and the focused document's text. With the `redact` feature
+//! each string goes through the redactor before it's written, and a withheld
+//! one fails the recording; without it, look the file over before sending
+//! it.
+
+use std::path::Path;
+
+use parking_lot::Mutex;
+
+use crate::{CaptureOptions, Selection, SelectionProvider};
+
+/// An attribute value as recorded, e.g. `{"string": "hello"}` or
+/// `{"range": {"location": 0, "length": 5}}` in the JSON.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AxValue {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Range {
+        location: i64,
+        length: i64,
+    },
+    Point {
+        x: f64,
+        y: f64,
+    },
+    Size {
+        width: f64,
+        height: f64,
+    },
+    Rect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    /// An element, by its label as in [`AxQuery::element`].
+    Element(String),
+    Array(Vec<AxValue>),
+    /// Anything else (attributed strings, URLs, dictionaries), by its
+    /// `CFCopyDescription`. Replayed as no value.
+    Other(String),
+}
+
+/// One attribute read, as `AXUIElementCopyAttributeValue` or its
+/// parameterized variant answered it.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AxQuery {
+    /// `system-wide`, or `element-N` with elements numbered in the order they
+    /// first came up.
+    pub element: String,
+    /// e.g. `AXSelectedText`.
+    pub attribute: String,
+    /// The parameter of a parameterized attribute, e.g. the range for
+    /// `AXBoundsForRange`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameter: Option<AxValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<AxValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The `AXError` of a failed read, e.g. `-25212` for no value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+}
+
+/// What [`record_capture`] writes: the queries in order and what the
+/// capture returned.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Recording {
+    /// The app the selection was captured from, empty when unknown.
+    pub app_name: String,
+    #[serde(default)]
+    pub bundle_id: Option<String>,
+    pub queries: Vec<AxQuery>,
+    pub text: Vec<String>,
+    /// The capture's error, the selection was read when `None`.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(std::fs::write(path, serde_json::to_string_pretty(self)?)?)
+    }
+
+    /// The first answer to `attribute` of `element`, as the app gave it.
+    pub fn query(&self, element: &str, attribute: &str) -> Option<&AxQuery> {
+        self.queries
+            .iter()
+            .find(|query| query.element == element && query.attribute == attribute)
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    queries: Vec<AxQuery>,
+    #[cfg(target_os = "macos")]
+    elements: Vec<u64>,
+}
+
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+/// Captures the selection like
+/// [`get_selected_text_with_options`](crate::get_selected_text_with_options),
+/// writing the accessibility queries it made and its result to `path` as
+/// JSON. Queries made meanwhile from other threads, e.g. by a watcher, end
+/// up in the recording too.
+pub fn record_capture(
+    options: &CaptureOptions,
+    path: impl AsRef<Path>,
+) -> Result<Selection, Box<dyn std::error::Error>> {
+    *RECORDER.lock() = Some(Recorder::default());
+    let result = crate::get_selected_text_with_options(options);
+    let recorder = RECORDER.lock().take().unwrap_or_default();
+
+    let mut recording = Recording {
+        queries: recorder.queries,
+        ..Default::default()
+    };
+    match &result {
+        #[cfg(target_os = "macos")]
+        Ok(selected) => {
+            recording.app_name = selected.app_name.clone();
+            recording.bundle_id = selected.bundle_id.clone();
+            recording.text = selected.text.clone();
+        }
+        #[cfg(not(target_os = "macos"))]
+        Ok(text) => {
+            recording.app_name = crate::get_window_meta()
+                .map(|meta| meta.app_name)
+                .unwrap_or_default();
+            recording.text = vec![text.clone()];
+        }
+        Err(e) => recording.error = Some(e.to_string()),
+    }
+    #[cfg(feature = "redact")]
+    {
+        let app = crate::redact::App {
+            name: recording.app_name.clone(),
+            bundle_id: recording.bundle_id.clone(),
+        };
+        for query in &mut recording.queries {
+            for value in query.parameter.iter_mut().chain(query.value.iter_mut()) {
+                redact_value(value, &app)?;
+            }
+        }
+    }
+    recording.save(path)?;
+    result
+}
+
+/// `value`'s strings as the redactor has them.
+#[cfg(feature = "redact")]
+fn redact_value(
+    value: &mut AxValue,
+    app: &crate::redact::App,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match value {
+        AxValue::String(text) => {
+            *text = crate::redact::redact_text(std::mem::take(text), || app.clone())?;
+        }
+        AxValue::Array(values) => {
+            for value in values {
+                redact_value(value, app)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn is_recording() -> bool {
+    RECORDER.lock().is_some()
+}
+
+/// `element-N` for the element hashing to `hash`.
+#[cfg(target_os = "macos")]
+pub(crate) fn element_label(hash: u64) -> String {
+    let mut recorder = RECORDER.lock();
+    let Some(recorder) = recorder.as_mut() else {
+        return String::new();
+    };
+    let index = match recorder.elements.iter().position(|&seen| seen == hash) {
+        Some(index) => index,
+        None => {
+            recorder.elements.push(hash);
+            recorder.elements.len() - 1
+        }
+    };
+    format!("element-{index}")
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn push(query: AxQuery) {
+    if let Some(recorder) = RECORDER.lock().as_mut() {
+        recorder.queries.push(query);
+    }
+}
+
+const AX_ERROR_FAILURE: i32 = -25200;
+const AX_ERROR_NO_VALUE: i32 = -25212;
+
+/// The recorded queries a running [`Replay`] answers with, and which of
+/// them it already did.
+struct Replaying {
+    queries: Vec<AxQuery>,
+    answered: Vec<bool>,
+}
+
+static REPLAYING: Mutex<Option<Replaying>> = Mutex::new(None);
+// one replay at a time, they share the answers
+static REPLAY: Mutex<()> = Mutex::new(());
+
+/// Answers the accessibility queries from `queries` until dropped.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+struct ReplayGuard<'a> {
+    _replay: parking_lot::MutexGuard<'a, ()>,
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn start_replay(queries: &[AxQuery]) -> ReplayGuard<'static> {
+    let replay = REPLAY.lock();
+    *REPLAYING.lock() = Some(Replaying {
+        queries: queries.to_vec(),
+        answered: vec![false; queries.len()],
+    });
+    ReplayGuard { _replay: replay }
+}
+
+impl Drop for ReplayGuard<'_> {
+    fn drop(&mut self) {
+        REPLAYING.lock().take();
+    }
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn is_replaying() -> bool {
+    REPLAYING.lock().is_some()
+}
+
+/// The recorded answer to reading `attribute` of `element`, `None` unless a
+/// replay is running. Repeated reads get the recorded answers in order, then
+/// the last one again; reads the capture didn't make fail with no value.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn replayed(
+    element: &str,
+    attribute: &str,
+    parameter: Option<&AxValue>,
+) -> Option<Result<AxValue, i32>> {
+    let mut replaying = REPLAYING.lock();
+    let replaying = replaying.as_mut()?;
+    let matching: Vec<usize> = (0..replaying.queries.len())
+        .filter(|&index| {
+            let query = &replaying.queries[index];
+            query.element == element
+                && query.attribute == attribute
+                && query.parameter.as_ref() == parameter
+        })
+        .collect();
+    let next = matching
+        .iter()
+        .copied()
+        .find(|&index| !replaying.answered[index])
+        .or(matching.last().copied());
+    let Some(index) = next else {
+        return Some(Err(AX_ERROR_NO_VALUE));
+    };
+    replaying.answered[index] = true;
+    let query = &replaying.queries[index];
+    Some(match (&query.value, &query.error) {
+        (Some(value), None) => Ok(value.clone()),
+        (_, Some(_)) => Err(query.error_code.unwrap_or(AX_ERROR_FAILURE)),
+        (None, None) => Err(AX_ERROR_NO_VALUE),
+    })
+}
+
+/// Fails while a replay runs, for the steps it can't answer for (copying,
+/// OCR), which would act on the machine replaying.
+#[cfg(target_os = "macos")]
+pub(crate) fn not_replayed(step: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !is_replaying() {
+        return Ok(());
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("Only accessibility queries are replayed, {step} was skipped"),
+    )
+    .into())
+}
+
+/// A [`SelectionProvider`] answering with a [`Recording`]. On macOS captures
+/// run again with the accessibility queries answered from it, copying and
+/// OCR fail; elsewhere they return what the recorded one did. Pasting
+/// fails.
+#[derive(Debug, Clone)]
+pub struct Replay(Recording);
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self(recording)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Recording::load(path).map(Self)
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.0
+    }
+
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    fn text(&self) -> Result<String, Box<dyn std::error::Error>> {
+        match &self.0.error {
+            Some(error) => Err(error.clone().into()),
+            None => Ok(self.0.text.join("\n")),
+        }
+    }
+}
+
+impl SelectionProvider for Replay {
+    #[cfg(target_os = "macos")]
+    fn get_selected_text(
+        &self,
+        options: &CaptureOptions,
+    ) -> Result<Selection, Box<dyn std::error::Error>> {
+        let _replay = start_replay(&self.0.queries);
+        crate::macos::replay_capture(
+            crate::SelectedText {
+                app_name: self.0.app_name.clone(),
+                bundle_id: self.0.bundle_id.clone(),
+                ..Default::default()
+            },
+            options,
+        )
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn get_selected_text(
+        &self,
+        _: &CaptureOptions,
+    ) -> Result<Selection, Box<dyn std::error::Error>> {
+        self.text()
+    }
+
+    #[cfg(target_os = "macos")]
+    fn try_get_selected_text(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let options = CaptureOptions::default().strategies([crate::Strategy::Accessibility]);
+        Ok(self.get_selected_text(&options)?.text.join("\n"))
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn try_get_selected_text(&self) -> Result<String, Box<dyn std::error::Error>> {
+        self.text()
+    }
+
+    fn replace_selected_text(&self, _: &str) -> Result<(), Box<dyn std::error::Error>> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "A replayed recording can't be pasted into",
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_round_trips() {
+        let recording = Recording {
+            app_name: "Notes".to_string(),
+            bundle_id: Some("com.apple.Notes".to_string()),
+            queries: vec![AxQuery {
+                element: "element-0".to_string(),
+                attribute: "AXSelectedText".to_string(),
+                parameter: None,
+                value: Some(AxValue::String("hello".to_string())),
+                error: None,
+                error_code: None,
+            }],
+            text: vec!["hello".to_string()],
+            error: None,
+        };
+        let path = std::env::temp_dir().join("get-selected-text-test-replay.json");
+        recording.save(&path).unwrap();
+        let replay = Replay::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(replay.recording(), &recording);
+        assert_eq!(
+            replay
+                .recording()
+                .query("element-0", "AXSelectedText")
+                .and_then(|query| query.value.as_ref()),
+            Some(&AxValue::String("hello".to_string()))
+        );
+        #[cfg(not(target_os = "macos"))]
+        assert_eq!(replay.try_get_selected_text().unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_replayed() {
+        let query = |value: Option<AxValue>, error_code: Option<i32>| AxQuery {
+            element: "element-0".to_string(),
+            attribute: "AXSelectedText".to_string(),
+            parameter: None,
+            error: error_code.map(|_| "failed".to_string()),
+            value,
+            error_code,
+        };
+        let text = |text: &str| AxValue::String(text.to_string());
+        let queries = [
+            query(None, Some(-25204)),
+            query(Some(text("first")), None),
+            query(Some(text("second")), None),
+            AxQuery {
+                element: "element-0".to_string(),
+                attribute: "AXStringForRange".to_string(),
+                parameter: Some(AxValue::Range {
+                    location: 0,
+                    length: 3,
+                }),
+                value: Some(text("fir")),
+                error: None,
+                error_code: None,
+            },
+        ];
+        assert!(replayed("element-0", "AXSelectedText", None).is_none());
+
+        let replay = start_replay(&queries);
+        // in the order they were made, then the last answer again
+        assert_eq!(
+            replayed("element-0", "AXSelectedText", None),
+            Some(Err(-25204))
+        );
+        for expected in ["first", "second", "second"] {
+            assert_eq!(
+                replayed("element-0", "AXSelectedText", None),
+                Some(Ok(text(expected)))
+            );
+        }
+        let range = |length| AxValue::Range {
+            location: 0,
+            length,
+        };
+        assert_eq!(
+            replayed("element-0", "AXStringForRange", Some(&range(3))),
+            Some(Ok(text("fir")))
+        );
+        assert_eq!(
+            replayed("element-0", "AXStringForRange", Some(&range(4))),
+            Some(Err(AX_ERROR_NO_VALUE))
+        );
+        assert_eq!(
+            replayed("element-1", "AXSelectedText", None),
+            Some(Err(AX_ERROR_NO_VALUE))
+        );
+        drop(replay);
+        assert!(!is_replaying());
+    }
+}
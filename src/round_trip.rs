@@ -0,0 +1,81 @@
+//! One clipboard round-trip at a time: two interleaved backup, copy and
+//! restore sequences would each put the other's copy back as "the user's
+//! clipboard". Within the process always, across processes with
+//! [`set_cross_process_lock`].
+#![cfg_attr(
+    not(any(
+        all(target_os = "macos", feature = "applescript"),
+        target_os = "windows",
+        target_os = "linux"
+    )),
+    allow(dead_code)
+)]
+
+use std::fs::File;
+use std::time::{Duration, Instant};
+
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::GetTextError;
+
+static ROUND_TRIP: Mutex<()> = Mutex::new(());
+static LOCK_NAME: Mutex<Option<String>> = Mutex::new(None);
+
+// a round-trip takes a few hundred milliseconds, another process's is
+// waited out rather than failed on
+const CROSS_PROCESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Also takes turns on the clipboard and the keyboard with other processes
+/// that embed this crate and set the same `name`, through a lock file in the
+/// temporary directory. A capture waits a moment for another process's to
+/// finish before failing with [`GetTextError::Busy`]. `None`, the default,
+/// only serializes within the process.
+///
+/// ```no_run
+/// get_selected_text::set_cross_process_lock(Some("com.example.suite"));
+/// ```
+pub fn set_cross_process_lock(name: Option<&str>) {
+    *LOCK_NAME.lock() = name.map(str::to_string);
+}
+
+/// Held for the length of a round-trip.
+pub(crate) struct RoundTrip {
+    _guard: MutexGuard<'static, ()>,
+    // closing it releases the lock
+    _file: Option<File>,
+}
+
+/// Holds the clipboard for a round-trip until dropped, failing with
+/// [`GetTextError::Busy`] while another capture or paste is at it.
+pub(crate) fn begin() -> Result<RoundTrip, Box<dyn std::error::Error>> {
+    let guard = ROUND_TRIP
+        .try_lock()
+        .ok_or_else(|| Box::new(GetTextError::Busy) as Box<dyn std::error::Error>)?;
+    let file = match LOCK_NAME.lock().clone() {
+        Some(name) => Some(lock_file(&name)?),
+        None => None,
+    };
+    Ok(RoundTrip {
+        _guard: guard,
+        _file: file,
+    })
+}
+
+fn lock_file(name: &str) -> Result<File, Box<dyn std::error::Error>> {
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(std::env::temp_dir().join(format!("{name}.get-selected-text.lock")))?;
+    let start = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(std::fs::TryLockError::WouldBlock) if start.elapsed() < CROSS_PROCESS_TIMEOUT => {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(std::fs::TryLockError::WouldBlock) => return Err(Box::new(GetTextError::Busy)),
+            Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Exposes the capture as a macOS Service.
+//!
+//! When the app is invoked as a Service (from the Services menu, a keyboard
+//! shortcut configured in System Settings, or a Shortcuts "Run Service"
+//! step), macOS hands over the selection on a services pasteboard, so no copy
+//! keystroke is needed.
+//!
+//! Declare the service in the app's `Info.plist`:
+//!
+//! ```xml
+//! <key>NSServices</key>
+//! <array>
+//!     <dict>
+//!         <key>NSMenuItem</key>
+//!         <dict><key>default</key><string>Send to MyApp</string></dict>
+//!         <key>NSMessage</key>
+//!         <string>getSelectedText</string>
+//!         <key>NSSendTypes</key>
+//!         <array>
+//!             <string>public.utf8-plain-text</string>
+//!             <string>public.file-url</string>
+//!         </array>
+//!     </dict>
+//! </array>
+//! ```
+//!
+//! and register the provider once at startup, after `NSApplication` exists:
+//!
+//! ```no_run
+//! get_selected_text::services::register_service_provider(|selected| {
+//!     println!("{:?}", selected.text);
+//! });
+//! ```
+
+use std::os::raw::c_void;
+use std::sync::Once;
+
+use cocoa::base::id;
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel};
+use objc::{class, msg_send, sel, sel_impl};
+use parking_lot::Mutex;
+
+use crate::SelectedText;
+
+type ServiceCallback = Box<dyn Fn(SelectedText) + Send>;
+
+static SERVICE_CALLBACK: Mutex<Option<ServiceCallback>> = Mutex::new(None);
+
+const PROVIDER_CLASS_NAME: &str = "GSTServiceProvider";
+
+/// Reads the selection from a pasteboard handed over by the system, such as
+/// the one passed to a Service's `pboard` argument.
+///
+/// # Safety
+///
+/// `pasteboard` must be a valid `NSPasteboard` instance.
+pub unsafe fn selected_text_from_pasteboard(pasteboard: id, app_name: &str) -> SelectedText {
+    let paths = crate::macos::pasteboard_file_paths(pasteboard);
+    if !paths.is_empty() {
+        return SelectedText {
+            is_file_paths: true,
+            app_name: app_name.to_string(),
+            text: paths,
+            ..Default::default()
+        };
+    }
+
+    SelectedText {
+        is_file_paths: false,
+        app_name: app_name.to_string(),
+        text: crate::macos::pasteboard_text(pasteboard)
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    }
+}
+
+extern "C" fn get_selected_text_service(
+    _this: &Object,
+    _cmd: Sel,
+    pasteboard: id,
+    _user_data: id,
+    _error: *mut c_void,
+) {
+    // the services menu doesn't tell us which app sent the selection
+    let selected = unsafe { selected_text_from_pasteboard(pasteboard, "") };
+    if let Some(callback) = SERVICE_CALLBACK.lock().as_ref() {
+        callback(selected);
+    }
+}
+
+fn provider_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut decl = ClassDecl::new(PROVIDER_CLASS_NAME, class!(NSObject)).unwrap();
+        unsafe {
+            decl.add_method(
+                sel!(getSelectedText:userData:error:),
+                get_selected_text_service as extern "C" fn(&Object, Sel, id, id, *mut c_void),
+            );
+        }
+        decl.register();
+    });
+    Class::get(PROVIDER_CLASS_NAME).unwrap()
+}
+
+/// Registers a services provider whose `getSelectedText` message calls
+/// `callback` with the selection. Replaces any previously registered callback.
+pub fn register_service_provider<F>(callback: F)
+where
+    F: Fn(SelectedText) + Send + 'static,
+{
+    *SERVICE_CALLBACK.lock() = Some(Box::new(callback));
+
+    unsafe {
+        let provider: id = msg_send![provider_class(), new];
+        let app: id = msg_send![class!(NSApplication), sharedApplication];
+        let _: () = msg_send![app, setServicesProvider: provider];
+        NSUpdateDynamicServices();
+    }
+}
+
+#[link(name = "AppKit", kind = "framework")]
+extern "C" {
+    fn NSUpdateDynamicServices();
+}
@@ -0,0 +1,543 @@
+//! How each strategy did in each app, so captures start with the one likely
+//! to find the selection and stop trying one that keeps coming back empty.
+#![cfg_attr(
+    not(all(
+        feature = "method-cache",
+        any(target_os = "macos", target_os = "windows", target_os = "linux")
+    )),
+    allow(dead_code)
+)]
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "method-cache")]
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+
+use crate::Strategy;
+
+// misses in a row before a strategy goes behind the others
+const DEMOTE_AFTER: u32 = 3;
+// captures a demoted strategy sits out before it gets another chance
+const RETRY_AFTER: u32 = 20;
+// the median is over the last few runs, apps get faster or slower
+const LATENCIES: usize = 15;
+
+#[cfg(feature = "method-cache")]
+static STATS: Mutex<Option<Stats>> = Mutex::new(None);
+#[cfg(feature = "method-cache")]
+static CAPACITY: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(100);
+
+#[derive(Debug, Default, Clone)]
+struct MethodStats {
+    found: u32,
+    /// Came back empty or failed while a later strategy found the selection.
+    missed: u32,
+    missed_in_a_row: u32,
+    /// Captures that found the selection without running it.
+    skipped: u32,
+    latencies: VecDeque<Duration>,
+}
+
+impl MethodStats {
+    fn record(&mut self, found: bool, latency: Duration) {
+        if found {
+            self.found += 1;
+            self.missed_in_a_row = 0;
+        } else {
+            self.missed += 1;
+            self.missed_in_a_row += 1;
+        }
+        self.skipped = 0;
+        if self.latencies.len() == LATENCIES {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+    }
+
+    fn success_rate(&self) -> f64 {
+        self.found as f64 / (self.found + self.missed).max(1) as f64
+    }
+
+    fn median_latency(&self) -> Duration {
+        let mut latencies: Vec<_> = self.latencies.iter().copied().collect();
+        latencies.sort();
+        latencies
+            .get(latencies.len() / 2)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn demoted(&self) -> bool {
+        self.missed_in_a_row >= DEMOTE_AFTER && self.skipped < RETRY_AFTER
+    }
+}
+
+/// The strategies' track record in one app.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct AppStats {
+    methods: HashMap<Strategy, MethodStats>,
+    /// When every strategy last failed here, cleared once one finds a
+    /// selection.
+    unsupported_since: Option<Instant>,
+    /// What [`prewarm`] expects to work, tried first until there are stats.
+    probed: Option<Strategy>,
+}
+
+impl AppStats {
+    /// `strategies` in the order to try them: those that found a selection
+    /// here before, by success rate then median latency, then the probed one,
+    /// then the others, then the demoted ones. Ties keep the given order.
+    pub(crate) fn rank(&self, strategies: &[Strategy]) -> Vec<Strategy> {
+        let mut ranked = strategies.to_vec();
+        ranked.sort_by(|&a, &b| {
+            let tier = |strategy: Strategy| match self.methods.get(&strategy) {
+                Some(stats) if stats.demoted() => 3,
+                Some(stats) if stats.found > 0 => 0,
+                None if self.probed == Some(strategy) => 1,
+                _ => 2,
+            };
+            let (tier_a, tier_b) = (tier(a), tier(b));
+            let (a, b) = (self.methods.get(&a), self.methods.get(&b));
+            tier_a.cmp(&tier_b).then_with(|| match (a, b) {
+                (Some(a), Some(b)) if a.found > 0 && b.found > 0 => b
+                    .success_rate()
+                    .total_cmp(&a.success_rate())
+                    .then(a.median_latency().cmp(&b.median_latency())),
+                _ => std::cmp::Ordering::Equal,
+            })
+        });
+        ranked
+    }
+
+    /// Records a capture that found the selection with the last of `tried`,
+    /// after the others came back empty. The rest of `strategies` sat it out.
+    pub(crate) fn record(&mut self, strategies: &[Strategy], tried: &[(Strategy, Duration)]) {
+        for &strategy in strategies {
+            if !tried.iter().any(|&(s, _)| s == strategy) {
+                if let Some(stats) = self.methods.get_mut(&strategy) {
+                    stats.skipped += 1;
+                }
+            }
+        }
+        if let Some(&(found, _)) = tried.last() {
+            self.unsupported_since = None;
+            for &(strategy, latency) in tried {
+                self.methods
+                    .entry(strategy)
+                    .or_default()
+                    .record(strategy == found, latency);
+            }
+        }
+    }
+
+    /// Records `strategy` coming back empty with nothing else finding a
+    /// selection: a miss, as it may not see one that's there, unless
+    /// `confirmed` by a later strategy coming back empty too.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn record_empty(&mut self, strategy: Strategy, latency: Duration, confirmed: bool) {
+        let stats = self.methods.entry(strategy).or_default();
+        if confirmed {
+            stats.missed_in_a_row = 0;
+        } else {
+            stats.record(false, latency);
+        }
+    }
+}
+
+impl AppStats {
+    fn profile(&self, app: &str) -> AppProfile {
+        let mut methods: Vec<_> = self
+            .methods
+            .iter()
+            .map(|(&strategy, stats)| MethodProfile {
+                strategy,
+                found: stats.found,
+                missed: stats.missed,
+                latency_ms: stats.median_latency().as_millis() as u64,
+            })
+            .collect();
+        methods.sort_by_key(|method| method.strategy as u8);
+        AppProfile {
+            app: app.to_string(),
+            methods,
+        }
+    }
+
+    fn from_profile(profile: &AppProfile) -> Self {
+        let methods = profile
+            .methods
+            .iter()
+            .map(|method| {
+                let stats = MethodStats {
+                    found: method.found,
+                    missed: method.missed,
+                    latencies: (method.latency_ms > 0)
+                        .then(|| Duration::from_millis(method.latency_ms))
+                        .into_iter()
+                        .collect(),
+                    ..Default::default()
+                };
+                (method.strategy, stats)
+            })
+            .collect();
+        Self {
+            methods,
+            ..Default::default()
+        }
+    }
+}
+
+/// The per-app method statistics, as [`export_method_profiles`] returns and
+/// [`import_method_profiles`] takes them. Serializes to JSON like
+///
+/// ```json
+/// {"apps": [{"app": "com.tinyspeck.slackmacgap", "methods": [
+///     {"strategy": "accessibility", "found": 0, "missed": 12},
+///     {"strategy": "clipboard", "found": 12, "latency_ms": 160}
+/// ]}]}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MethodProfiles {
+    pub apps: Vec<AppProfile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AppProfile {
+    /// As in [`AppId`].
+    pub app: String,
+    pub methods: Vec<MethodProfile>,
+}
+
+/// How one strategy did in an app.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MethodProfile {
+    pub strategy: Strategy,
+    /// Captures it found the selection in.
+    #[serde(default)]
+    pub found: u32,
+    /// Captures it came back empty or failed in while another strategy found
+    /// the selection.
+    #[serde(default)]
+    pub missed: u32,
+    /// Median time it took, 0 when unknown.
+    #[serde(default)]
+    pub latency_ms: u64,
+}
+
+/// What the crate learned about each app so far, most recently used first,
+/// e.g. to save it across launches or to curate a profile to ship. Empty
+/// without the `method-cache` feature.
+///
+/// ```no_run
+/// let profiles = get_selected_text::export_method_profiles();
+/// // save it with any serde format, e.g. serde_json::to_string(&profiles),
+/// // and on the next launch
+/// get_selected_text::import_method_profiles(&profiles);
+/// ```
+pub fn export_method_profiles() -> MethodProfiles {
+    #[cfg(feature = "method-cache")]
+    if let Some(stats) = STATS.lock().as_ref() {
+        return MethodProfiles {
+            apps: stats
+                .apps
+                .iter()
+                .filter(|(_, stats)| !stats.methods.is_empty())
+                .map(|(app, stats)| stats.profile(app))
+                .collect(),
+        };
+    }
+    MethodProfiles::default()
+}
+
+/// Loads profiles saved with [`export_method_profiles`] or shipped with
+/// your app, replacing what was learned about the apps they list. Does
+/// nothing without the `method-cache` feature.
+pub fn import_method_profiles(profiles: &MethodProfiles) {
+    #[cfg(feature = "method-cache")]
+    {
+        let mut stats = stats();
+        // the first one listed ends up the most recently used
+        for profile in profiles.apps.iter().rev() {
+            stats
+                .apps
+                .put(profile.app.clone(), AppStats::from_profile(profile));
+        }
+    }
+    #[cfg(not(feature = "method-cache"))]
+    let _ = profiles;
+}
+
+/// The per-app statistics, set up by [`init`] and dropped by [`clear`].
+#[cfg(feature = "method-cache")]
+struct Stats {
+    apps: lru::LruCache<String, AppStats>,
+    /// Forgets apps as they quit or launch, removed with the statistics.
+    #[cfg(target_os = "macos")]
+    _launches: Option<crate::macos::AppObserver>,
+}
+
+#[cfg(feature = "method-cache")]
+impl Stats {
+    fn new() -> Self {
+        Self {
+            apps: lru::LruCache::new(capacity()),
+            #[cfg(target_os = "macos")]
+            _launches: crate::macos::AppObserver::start(),
+        }
+    }
+}
+
+/// The statistics, set up as [`init`] does for apps that didn't call
+/// [`crate::init`].
+#[cfg(feature = "method-cache")]
+fn stats() -> MappedMutexGuard<'static, Stats> {
+    MutexGuard::map(STATS.lock(), |stats| stats.get_or_insert_with(Stats::new))
+}
+
+#[cfg(feature = "method-cache")]
+fn capacity() -> std::num::NonZeroUsize {
+    std::num::NonZeroUsize::new(CAPACITY.load(std::sync::atomic::Ordering::Relaxed))
+        .unwrap_or(std::num::NonZeroUsize::MIN)
+}
+
+/// How many apps the per-app method statistics are kept for, 100 by
+/// default. The least recently captured from are forgotten first, also
+/// right away when shrinking. Does nothing without the `method-cache`
+/// feature.
+pub fn set_method_cache_capacity(apps: usize) {
+    #[cfg(feature = "method-cache")]
+    {
+        CAPACITY.store(apps.max(1), std::sync::atomic::Ordering::Relaxed);
+        if let Some(stats) = STATS.lock().as_mut() {
+            stats.apps.resize(capacity());
+        }
+    }
+    #[cfg(not(feature = "method-cache"))]
+    let _ = apps;
+}
+
+/// Sets up the statistics and, on macOS, the observer keeping them current.
+pub(crate) fn init() {
+    #[cfg(feature = "method-cache")]
+    let _ = stats();
+}
+
+/// Drops the statistics and removes the observer.
+pub(crate) fn clear() {
+    #[cfg(feature = "method-cache")]
+    {
+        // dropped unlocked, the observer's callback locks it too
+        let stats = STATS.lock().take();
+        drop(stats);
+    }
+}
+
+/// Drops what was learned about `app`, which quit or was (re)launched: an
+/// update may have changed what works there.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn forget(app: &str) {
+    #[cfg(feature = "method-cache")]
+    if let Some(stats) = STATS.lock().as_mut() {
+        stats.apps.pop(app);
+    }
+    #[cfg(not(feature = "method-cache"))]
+    let _ = app;
+}
+
+/// `strategies` in the order to try them in `app`, as given without the
+/// `method-cache` feature.
+pub(crate) fn rank(app: &str, strategies: &[Strategy]) -> Vec<Strategy> {
+    #[cfg(feature = "method-cache")]
+    if let Some(stats) = STATS.lock().as_mut().and_then(|stats| stats.apps.get(app)) {
+        return stats.rank(strategies);
+    }
+    let _ = app;
+    strategies.to_vec()
+}
+
+/// See [`AppStats::record`].
+pub(crate) fn record(app: &str, strategies: &[Strategy], tried: &[(Strategy, Duration)]) {
+    #[cfg(feature = "method-cache")]
+    stats()
+        .apps
+        .get_or_insert_mut(app.to_string(), AppStats::default)
+        .record(strategies, tried);
+    #[cfg(not(feature = "method-cache"))]
+    let _ = (app, strategies, tried);
+}
+
+/// See [`AppStats::record_empty`].
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn record_empty(app: &str, strategy: Strategy, latency: Duration, confirmed: bool) {
+    #[cfg(feature = "method-cache")]
+    if !app.is_empty() {
+        stats()
+            .apps
+            .get_or_insert_mut(app.to_string(), AppStats::default)
+            .record_empty(strategy, latency, confirmed);
+    }
+    #[cfg(not(feature = "method-cache"))]
+    let _ = (app, strategy, latency, confirmed);
+}
+
+/// Whether every strategy failed in `app` less than `cooldown` ago.
+pub(crate) fn recently_unsupported(app: &str, cooldown: Duration) -> bool {
+    #[cfg(feature = "method-cache")]
+    if let Some(stats) = STATS.lock().as_mut().and_then(|stats| stats.apps.peek(app)) {
+        return stats
+            .unsupported_since
+            .is_some_and(|since| since.elapsed() < cooldown);
+    }
+    let _ = (app, cooldown);
+    false
+}
+
+/// Remembers that every strategy failed in `app`. Not for unknown apps,
+/// which would all share the one entry.
+pub(crate) fn mark_unsupported(app: &str) {
+    #[cfg(feature = "method-cache")]
+    if !app.is_empty() {
+        stats()
+            .apps
+            .get_or_insert_mut(app.to_string(), AppStats::default)
+            .unsupported_since = Some(Instant::now());
+    }
+    #[cfg(not(feature = "method-cache"))]
+    let _ = app;
+}
+
+/// An app as the per-app statistics know it: its bundle id on macOS
+/// (`com.apple.Safari`), its executable path on Windows and Linux.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AppId(pub String);
+
+impl From<&str> for AppId {
+    fn from(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
+/// Works out in the background which method will likely read the selection
+/// in each of `apps`, so the first capture there doesn't have to find out
+/// the slow way. Meant to be called at startup with the apps your users
+/// capture from most.
+///
+/// Only running apps can be probed, and only on macOS (through their AX
+/// focused element); elsewhere, and without the `method-cache` feature, this
+/// does nothing. Apps that already have statistics are left alone.
+///
+/// ```no_run
+/// use get_selected_text::{prewarm, AppId};
+///
+/// prewarm(&[AppId::from("com.apple.Safari"), AppId::from("com.tinyspeck.slackmacgap")]);
+/// ```
+pub fn prewarm(apps: &[AppId]) -> std::thread::JoinHandle<()> {
+    let apps = apps.to_vec();
+    std::thread::spawn(move || {
+        for app in apps {
+            #[cfg(target_os = "macos")]
+            if let Some(strategy) = crate::macos::probe(&app.0) {
+                set_probed(&app.0, strategy);
+            }
+            #[cfg(not(target_os = "macos"))]
+            let _ = app;
+        }
+    })
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn set_probed(app: &str, strategy: Strategy) {
+    #[cfg(feature = "method-cache")]
+    {
+        let mut all = stats();
+        let stats = all
+            .apps
+            .get_or_insert_mut(app.to_string(), AppStats::default);
+        if stats.methods.is_empty() {
+            stats.probed = Some(strategy);
+        }
+    }
+    #[cfg(not(feature = "method-cache"))]
+    let _ = (app, strategy);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AX: Strategy = Strategy::Accessibility;
+    const COPY: Strategy = Strategy::Clipboard;
+
+    #[test]
+    fn test_rank() {
+        let strategies = [AX, COPY];
+        let ms = Duration::from_millis;
+        let mut stats = AppStats::default();
+        assert_eq!(stats.rank(&strategies), [AX, COPY]);
+
+        // a strategy that found something goes first
+        stats.record(&strategies, &[(AX, ms(5)), (COPY, ms(150))]);
+        assert_eq!(stats.rank(&strategies), [COPY, AX]);
+        stats.record(&strategies, &[(COPY, ms(150))]);
+        stats.record(&strategies, &[(COPY, ms(150))]);
+        stats.record(&strategies, &[(COPY, ms(150)), (AX, ms(5))]);
+        // both found something, the more reliable one first
+        assert_eq!(stats.rank(&strategies), [COPY, AX]);
+
+        // probed, the first capture starts with what the probe found
+        let probed = AppStats {
+            probed: Some(COPY),
+            ..Default::default()
+        };
+        assert_eq!(probed.rank(&strategies), [COPY, AX]);
+
+        // a profile ranks like the stats it came from
+        let profile = stats.profile("app");
+        assert_eq!(
+            AppStats::from_profile(&profile).rank(&strategies),
+            [COPY, AX]
+        );
+        assert_eq!(AppStats::from_profile(&profile).profile("app"), profile);
+
+        // empty three times in a row while the other one found it
+        let mut stats = AppStats::default();
+        stats.record(&strategies, &[(AX, ms(5))]);
+        for _ in 0..DEMOTE_AFTER {
+            stats.record(&strategies, &[(AX, ms(5)), (COPY, ms(150))]);
+        }
+        assert!(stats.methods[&AX].demoted());
+        assert_eq!(stats.rank(&strategies), [COPY, AX]);
+        // and gets another chance eventually
+        for _ in 0..RETRY_AFTER {
+            stats.record(&strategies, &[(COPY, ms(150))]);
+        }
+        assert!(!stats.methods[&AX].demoted());
+    }
+
+    #[test]
+    fn test_record_empty() {
+        let strategies = [AX, COPY];
+        let ms = Duration::from_millis;
+        let mut stats = AppStats::default();
+
+        // AX reporting "" while text is selected, nothing else was tried
+        for _ in 0..DEMOTE_AFTER {
+            stats.record_empty(AX, ms(5), false);
+        }
+        assert!(stats.methods[&AX].demoted());
+        assert_eq!(stats.rank(&strategies), [COPY, AX]);
+        // the clipboard finding text then keeps it first
+        stats.record(&strategies, &[(COPY, ms(150))]);
+        assert_eq!(stats.rank(&strategies), [COPY, AX]);
+
+        // the clipboard coming back empty too, nothing was selected after all
+        let mut stats = AppStats::default();
+        for _ in 0..DEMOTE_AFTER {
+            stats.record_empty(AX, ms(5), false);
+        }
+        stats.record_empty(AX, ms(5), true);
+        assert!(!stats.methods[&AX].demoted());
+        assert_eq!(stats.rank(&strategies), [AX, COPY]);
+    }
+}
@@ -0,0 +1,89 @@
+//! Rows and columns of a table selection, so data-entry tools don't have to
+//! reverse-engineer tabs and newlines.
+
+/// A table selection, rows of cells.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Table {
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Parses tab-separated text, the format spreadsheets (Numbers, Excel,
+/// Google Sheets) and HTML tables are copied as. Returns `None` unless there
+/// are at least two columns and every row has the same number of them.
+///
+/// ```
+/// let table = get_selected_text::parse_table("name\tqty\napples\t3\n").unwrap();
+/// assert_eq!(table.rows, vec![vec!["name", "qty"], vec!["apples", "3"]]);
+/// ```
+pub fn parse_table(text: &str) -> Option<Table> {
+    let rows = text
+        .trim_end_matches(['\n', '\r'])
+        .lines()
+        .map(|line| line.split('\t').map(str::to_string).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let columns = rows.first()?.len();
+    (columns > 1 && rows.iter().all(|row| row.len() == columns)).then_some(Table { rows })
+}
+
+/// Splits `html` on the opening `<tag` elements and returns what's inside
+/// each, up to its closing tag or the next opening one.
+fn elements<'a>(html: &'a str, lower: &str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}");
+    let mut contents = Vec::new();
+    let mut position = 0;
+    while let Some(start) = lower[position..].find(&open).map(|i| i + position) {
+        let Some(content_start) = lower[start..].find('>').map(|i| i + start + 1) else {
+            break;
+        };
+        let end = [
+            lower[content_start..].find(&close),
+            lower[content_start..].find(&open),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .map_or(html.len(), |i| i + content_start);
+        contents.push(&html[content_start..end]);
+        position = end;
+    }
+    contents
+}
+
+/// Extracts the `<table>` elements of an HTML fragment. Row and column spans
+/// are ignored, each cell counts once.
+pub fn extract_tables_from_html(html: &str) -> Vec<Table> {
+    let lower = html.to_ascii_lowercase();
+    let mut tables = Vec::new();
+    let mut position = 0;
+    while let Some(start) = lower[position..].find("<table").map(|i| i + position) {
+        let end = lower[start..]
+            .find("</table")
+            .map_or(html.len(), |i| i + start);
+        let (table, table_lower) = (&html[start..end], &lower[start..end]);
+        let rows = elements(table, table_lower, "tr")
+            .into_iter()
+            .map(|row| {
+                let row_lower = row.to_ascii_lowercase();
+                // th and td are split separately, then put back in document order
+                let mut cells = ["td", "th"]
+                    .into_iter()
+                    .flat_map(|tag| {
+                        elements(row, &row_lower, tag)
+                            .into_iter()
+                            .map(|cell| (cell.as_ptr() as usize, crate::links::strip_tags(cell)))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                cells.sort_by_key(|(offset, _)| *offset);
+                cells.into_iter().map(|(_, text)| text).collect::<Vec<_>>()
+            })
+            .filter(|row| !row.is_empty())
+            .collect::<Vec<_>>();
+        if !rows.is_empty() {
+            tables.push(Table { rows });
+        }
+        position = end;
+    }
+    tables
+}
@@ -0,0 +1,70 @@
+//! Terminal emulators, where ctrl+c interrupts the running program instead
+//! of copying the selection.
+
+use crate::GetTextError;
+
+// executable names, lowercase and without `.exe`
+const TERMINALS: &[&str] = &[
+    "alacritty",
+    "blackbox",
+    "conemu",
+    "conemu64",
+    "foot",
+    "ghostty",
+    "gnome-terminal-server",
+    "guake",
+    "hyper",
+    "kitty",
+    "konsole",
+    "lxterminal",
+    "mate-terminal",
+    "mintty",
+    "ptyxis",
+    "qterminal",
+    "st",
+    "tabby",
+    "terminator",
+    "terminology",
+    "tilix",
+    "urxvt",
+    "wezterm-gui",
+    "xfce4-terminal",
+    "xterm",
+    "yakuake",
+];
+
+/// Whether `app`, an executable path or name as in
+/// [`crate::active_app_id`], is a terminal emulator.
+fn is_terminal(app: &str) -> bool {
+    let name = app.rsplit(['/', '\\']).next().unwrap_or(app).to_lowercase();
+    TERMINALS.contains(&name.strip_suffix(".exe").unwrap_or(&name))
+}
+
+/// Whether the focused app is a terminal emulator.
+pub(crate) fn focused() -> bool {
+    is_terminal(&crate::active_app_id())
+}
+
+/// Fails with [`GetTextError::Terminal`] when the focused app is a terminal,
+/// before anything presses ctrl+c in it.
+pub(crate) fn ensure_not_focused() -> Result<(), Box<dyn std::error::Error>> {
+    let app = crate::active_app_id();
+    if is_terminal(&app) {
+        return Err(Box::new(GetTextError::Terminal { app }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_terminal() {
+        assert!(is_terminal("/usr/libexec/gnome-terminal-server"));
+        assert!(is_terminal(r"C:\Program Files\Alacritty\alacritty.exe"));
+        assert!(is_terminal("Konsole"));
+        assert!(!is_terminal("/usr/bin/gedit"));
+        assert!(!is_terminal(""));
+    }
+}
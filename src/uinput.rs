@@ -0,0 +1,165 @@
+//! A keyboard device created through /dev/uinput, which every app sees like a
+//! plugged in one, for Wayland compositors without the virtual-keyboard
+//! protocol (GNOME). Needs write access to /dev/uinput, e.g. through the
+//! `input` group or a udev rule.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::thread;
+use std::time::Duration;
+
+use enigo::{Direction, InputError, InputResult, Key, Keyboard};
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+
+static DEVICE: Mutex<Option<Uinput>> = Mutex::new(None);
+
+pub(crate) const UINPUT_PATH: &str = "/dev/uinput";
+
+const fn ioctl_write(nr: u64, size: usize) -> u64 {
+    (1 << 30) | ((size as u64) << 16) | ((b'U' as u64) << 8) | nr
+}
+
+const UI_DEV_CREATE: u64 = ((b'U' as u64) << 8) | 1;
+const UI_DEV_DESTROY: u64 = ((b'U' as u64) << 8) | 2;
+const UI_DEV_SETUP: u64 = ioctl_write(3, std::mem::size_of::<libc::uinput_setup>());
+const UI_SET_EVBIT: u64 = ioctl_write(100, std::mem::size_of::<libc::c_int>());
+const UI_SET_KEYBIT: u64 = ioctl_write(101, std::mem::size_of::<libc::c_int>());
+
+const EV_SYN: u16 = 0;
+const EV_KEY: u16 = 1;
+const SYN_REPORT: u16 = 0;
+const BUS_VIRTUAL: u16 = 6;
+
+const KEY_TAB: u16 = 15;
+pub(crate) const KEY_LEFTCTRL: u16 = 29;
+const KEY_C: u16 = 46;
+const KEY_V: u16 = 47;
+pub(crate) const KEY_LEFTSHIFT: u16 = 42;
+pub(crate) const KEY_LEFTALT: u16 = 56;
+const KEY_SPACE: u16 = 57;
+const KEY_RIGHT: u16 = 106;
+
+const KEYS: [u16; 8] = [
+    KEY_TAB,
+    KEY_LEFTCTRL,
+    KEY_C,
+    KEY_V,
+    KEY_LEFTSHIFT,
+    KEY_LEFTALT,
+    KEY_SPACE,
+    KEY_RIGHT,
+];
+
+/// Linux input event code of the keys captures press. They name physical
+/// positions, so `c` is wherever a US layout has it.
+pub(crate) fn evdev_keycode(key: Key) -> InputResult<u16> {
+    Ok(match key {
+        Key::Tab => KEY_TAB,
+        Key::Control => KEY_LEFTCTRL,
+        Key::Unicode('c') => KEY_C,
+        Key::Unicode('v') => KEY_V,
+        Key::Shift => KEY_LEFTSHIFT,
+        Key::Alt => KEY_LEFTALT,
+        Key::Space => KEY_SPACE,
+        Key::RightArrow => KEY_RIGHT,
+        _ => {
+            return Err(InputError::InvalidInput(
+                "key not available on this keyboard",
+            ))
+        }
+    })
+}
+
+fn check(result: libc::c_int) -> std::io::Result<()> {
+    if result < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub(crate) struct Uinput(File);
+
+impl Uinput {
+    fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let file = OpenOptions::new().write(true).open(UINPUT_PATH)?;
+        let fd = file.as_raw_fd();
+        let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id.bustype = BUS_VIRTUAL;
+        for (dst, src) in setup.name.iter_mut().zip(b"get-selected-text") {
+            *dst = *src as libc::c_char;
+        }
+        unsafe {
+            check(libc::ioctl(fd, UI_SET_EVBIT as _, EV_KEY as libc::c_int))?;
+            for key in KEYS {
+                check(libc::ioctl(fd, UI_SET_KEYBIT as _, key as libc::c_int))?;
+            }
+            check(libc::ioctl(fd, UI_DEV_SETUP as _, &setup))?;
+            check(libc::ioctl(fd, UI_DEV_CREATE as _))?;
+        }
+        // the compositor needs a moment to pick up the new device
+        thread::sleep(Duration::from_millis(200));
+        Ok(Self(file))
+    }
+
+    fn emit(&mut self, type_: u16, code: u16, value: i32) -> InputResult<()> {
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        event.type_ = type_;
+        event.code = code;
+        event.value = value;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const libc::input_event as *const u8,
+                std::mem::size_of::<libc::input_event>(),
+            )
+        };
+        self.0
+            .write_all(bytes)
+            .map_err(|_| InputError::Simulate("writing to /dev/uinput failed"))
+    }
+}
+
+impl Drop for Uinput {
+    fn drop(&mut self) {
+        unsafe {
+            libc::ioctl(self.0.as_raw_fd(), UI_DEV_DESTROY as _);
+        }
+    }
+}
+
+impl Keyboard for Uinput {
+    fn fast_text(&mut self, _text: &str) -> InputResult<Option<()>> {
+        Ok(None)
+    }
+
+    fn key(&mut self, key: Key, direction: Direction) -> InputResult<()> {
+        self.raw(evdev_keycode(key)?, direction)
+    }
+
+    fn raw(&mut self, keycode: u16, direction: Direction) -> InputResult<()> {
+        if matches!(direction, Direction::Press | Direction::Click) {
+            self.emit(EV_KEY, keycode, 1)?;
+            self.emit(EV_SYN, SYN_REPORT, 0)?;
+        }
+        if matches!(direction, Direction::Release | Direction::Click) {
+            self.emit(EV_KEY, keycode, 0)?;
+            self.emit(EV_SYN, SYN_REPORT, 0)?;
+        }
+        Ok(())
+    }
+}
+
+/// The device, created on first use and kept for the process's lifetime so
+/// later captures don't wait for the compositor to pick up a new one.
+pub(crate) fn keyboard() -> Result<MappedMutexGuard<'static, Uinput>, Box<dyn std::error::Error>> {
+    let mut device = DEVICE.lock();
+    if device.is_none() {
+        *device = Some(Uinput::new()?);
+    }
+    Ok(MutexGuard::map(device, |device| device.as_mut().unwrap()))
+}
+
+/// Removes the device, the next keystroke creates a new one.
+pub(crate) fn destroy() {
+    DEVICE.lock().take();
+}
@@ -0,0 +1,65 @@
+//! [UniFFI](https://mozilla.github.io/uniffi-rs/) bindings for Swift and Kotlin.
+//!
+//! Generate the foreign sources from the built library, e.g. for Swift:
+//!
+//! ```bash
+//! cargo rustc --release --lib --features uniffi --crate-type cdylib
+//! cargo run --features uniffi-cli --bin uniffi-bindgen -- generate \
+//!     --library target/release/libget_selected_text.dylib --language swift --out-dir bindings
+//! ```
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct SelectedText {
+    pub is_file_paths: bool,
+    pub app_name: String,
+    pub bundle_id: Option<String>,
+    pub process_id: Option<i32>,
+    pub app_version: Option<String>,
+    pub window_id: Option<u32>,
+    pub text: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum CaptureError {
+    #[error("{message}")]
+    Failed { message: String },
+}
+
+#[cfg(target_os = "macos")]
+impl From<crate::SelectedText> for SelectedText {
+    fn from(selected: crate::SelectedText) -> Self {
+        Self {
+            is_file_paths: selected.is_file_paths,
+            app_name: selected.app_name,
+            bundle_id: selected.bundle_id,
+            process_id: selected.process_id,
+            app_version: selected.app_version,
+            window_id: selected.window_id,
+            text: selected.text,
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+impl From<String> for SelectedText {
+    fn from(text: String) -> Self {
+        Self {
+            is_file_paths: false,
+            app_name: String::new(),
+            bundle_id: None,
+            process_id: None,
+            app_version: None,
+            window_id: None,
+            text: vec![text],
+        }
+    }
+}
+
+#[uniffi::export]
+pub fn get_selected_text() -> Result<SelectedText, CaptureError> {
+    crate::get_selected_text()
+        .map(SelectedText::from)
+        .map_err(|e| CaptureError::Failed {
+            message: crate::error::error_chain(&*e),
+        })
+}
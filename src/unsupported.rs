@@ -0,0 +1,61 @@
+//! Fallback for targets without a capture backend (wasm, iOS, Android, BSDs...),
+//! so crates that depend on this one still compile there.
+
+fn unsupported() -> Box<dyn std::error::Error> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Getting the selected text is not supported on this platform",
+    ))
+}
+
+pub fn get_selected_text(
+    _options: &crate::CaptureOptions,
+    _attempts: &mut crate::attempt::Attempts,
+) -> Result<String, Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub(crate) fn get_selected_text_passively() -> Result<String, Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub(crate) fn get_selected_text_and_html(
+) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub fn set_selected_text(_text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub fn replace_selected_text(_text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub fn type_text(
+    _text: &str,
+    _interval: std::time::Duration,
+    _cancel: &crate::CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub fn get_clipboard_text() -> Result<String, Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub fn get_clipboard_files() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub fn get_clipboard_kind() -> Result<crate::ClipboardKind, Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub fn displays() -> Result<Vec<crate::Display>, Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
+
+pub fn mouse_position() -> Result<crate::Point, Box<dyn std::error::Error>> {
+    Err(unsupported())
+}
@@ -5,7 +5,10 @@ use std::{thread, time::Duration};
 static COPY_PASTE_LOCKER: Mutex<()> = Mutex::new(());
 static INPUT_LOCK_LOCKER: Mutex<()> = Mutex::new(());
 
-pub(crate) fn right_arrow_click(enigo: &mut Enigo, n: usize) {
+#[cfg(target_os = "windows")]
+const MODIFIER_RELEASE_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub(crate) fn right_arrow_click(enigo: &mut dyn Keyboard, n: usize) {
     let _guard = INPUT_LOCK_LOCKER.lock();
 
     for _ in 0..n {
@@ -13,7 +16,7 @@ pub(crate) fn right_arrow_click(enigo: &mut Enigo, n: usize) {
     }
 }
 
-pub(crate) fn up_control_keys(enigo: &mut Enigo) {
+pub(crate) fn up_control_keys(enigo: &mut dyn Keyboard) {
     enigo.key(Key::Control, Direction::Release).unwrap();
     enigo.key(Key::Alt, Direction::Release).unwrap();
     enigo.key(Key::Shift, Direction::Release).unwrap();
@@ -21,9 +24,12 @@ pub(crate) fn up_control_keys(enigo: &mut Enigo) {
     enigo.key(Key::Tab, Direction::Release).unwrap();
 }
 
-pub(crate) fn copy(enigo: &mut Enigo) {
+pub(crate) fn copy(enigo: &mut dyn Keyboard) {
     let _guard = COPY_PASTE_LOCKER.lock();
 
+    // releasing by synthetic key-ups is the fallback for keys held past it
+    #[cfg(target_os = "windows")]
+    crate::windows::wait_for_modifiers_released(MODIFIER_RELEASE_TIMEOUT);
     crate::utils::up_control_keys(enigo);
 
     enigo.key(Key::Control, Direction::Press).unwrap();
@@ -34,22 +40,218 @@ pub(crate) fn copy(enigo: &mut Enigo) {
     enigo.key(Key::Control, Direction::Release).unwrap();
 }
 
+pub(crate) fn paste(enigo: &mut dyn Keyboard) {
+    let _guard = COPY_PASTE_LOCKER.lock();
+
+    // releasing by synthetic key-ups is the fallback for keys held past it
+    #[cfg(target_os = "windows")]
+    crate::windows::wait_for_modifiers_released(MODIFIER_RELEASE_TIMEOUT);
+    crate::utils::up_control_keys(enigo);
+
+    enigo.key(Key::Control, Direction::Press).unwrap();
+    #[cfg(target_os = "windows")]
+    enigo.key(Key::V, Direction::Click).unwrap();
+    #[cfg(target_os = "linux")]
+    enigo.key(Key::Unicode('v'), Direction::Click).unwrap();
+    enigo.key(Key::Control, Direction::Release).unwrap();
+}
+
+#[cfg(target_os = "windows")]
+use crate::windows::ClipboardSnapshot as ClipboardBackup;
+
+/// What was on the clipboard before a copy or paste round-trip. arboard only
+/// covers text and images, which is what X11 / Wayland apps mostly offer.
+#[cfg(target_os = "linux")]
+struct ClipboardBackup(
+    Result<String, arboard::Error>,
+    Result<arboard::ImageData<'static>, arboard::Error>,
+);
+
+#[cfg(target_os = "linux")]
+impl ClipboardBackup {
+    fn take() -> Result<Self, Box<dyn std::error::Error>> {
+        use arboard::Clipboard;
+
+        Ok(Self(
+            Clipboard::new()?.get_text(),
+            Clipboard::new()?.get_image(),
+        ))
+    }
+
+    fn restore(self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut clipboard = arboard::Clipboard::new()?;
+        match self {
+            // Old Content is Text
+            #[cfg(feature = "session-bus")]
+            Self(Ok(old_text), _) if crate::klipper::is_running() => {
+                crate::klipper::set_clipboard_contents(&old_text)?
+            }
+            Self(Ok(old_text), _) => clipboard.set_text(old_text)?,
+            // Old Content is Image
+            Self(_, Ok(image)) => clipboard.set_image(image)?,
+            // Old Content is Empty
+            _ => clipboard.clear()?,
+        }
+        Ok(())
+    }
+}
+
+/// Puts the clipboard back when dropped, so a round-trip that fails midway
+/// or panics doesn't leave the user's clipboard replaced.
+struct RestoreOnDrop(Option<ClipboardBackup>);
+
+impl RestoreOnDrop {
+    fn take() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self(Some(ClipboardBackup::take()?)))
+    }
+
+    /// Restores right away, reporting what went wrong.
+    fn restore(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.0.take().map_or(Ok(()), ClipboardBackup::restore)
+    }
+}
+
+impl Drop for RestoreOnDrop {
+    fn drop(&mut self) {
+        if let Some(backup) = self.0.take() {
+            let _ = backup.restore();
+        }
+    }
+}
+
+/// Puts `text` on the clipboard for a round-trip, keeping it out of the
+/// history of clipboard managers (Klipper and the like) on Linux.
+fn set_transient_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(target_os = "linux")]
+    {
+        use arboard::SetExtLinux;
+        arboard::Clipboard::new()?
+            .set()
+            .exclude_from_history()
+            .text(text)?;
+    }
+    #[cfg(target_os = "windows")]
+    arboard::Clipboard::new()?.set_text(text)?;
+    Ok(())
+}
+
+pub(crate) fn paste_text_by_clipboard(
+    enigo: &mut dyn Keyboard,
+    text: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let _round_trip = crate::round_trip::begin()?;
+    let old_clipboard = RestoreOnDrop::take()?;
+
+    set_transient_text(text)?;
+
+    thread::sleep(Duration::from_millis(50));
+
+    paste(enigo);
+
+    // give the app time to read the clipboard before it's restored
+    thread::sleep(Duration::from_millis(200));
+
+    old_clipboard.restore()
+}
+
+pub(crate) fn type_text(
+    enigo: &mut dyn Keyboard,
+    text: &str,
+    interval: Duration,
+    cancel: &crate::CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let _guard = INPUT_LOCK_LOCKER.lock();
+
+    crate::utils::up_control_keys(enigo);
+
+    for ch in text.chars() {
+        if cancel.is_cancelled() {
+            return Err(crate::cancel::cancelled_error());
+        }
+        enigo.text(ch.encode_utf8(&mut [0; 4]))?;
+        thread::sleep(interval);
+    }
+    Ok(())
+}
+
+/// Reads the clipboard text without simulating any key presses, empty when
+/// it holds no text.
+pub fn get_clipboard_text() -> Result<String, Box<dyn std::error::Error>> {
+    match arboard::Clipboard::new()?.get_text() {
+        Ok(text) => Ok(text),
+        Err(arboard::Error::ContentNotAvailable) => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads the file paths on the clipboard without simulating any key presses.
+pub fn get_clipboard_files() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    match arboard::Clipboard::new()?.get().file_list() {
+        Ok(paths) => Ok(paths
+            .into_iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect()),
+        Err(arboard::Error::ContentNotAvailable) => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Inspects what kind of content the clipboard holds.
+pub fn get_clipboard_kind() -> Result<crate::ClipboardKind, Box<dyn std::error::Error>> {
+    use crate::ClipboardKind;
+
+    let mut clipboard = arboard::Clipboard::new()?;
+    Ok(if clipboard.get().file_list().is_ok() {
+        ClipboardKind::Files
+    } else if clipboard.get_text().is_ok() {
+        ClipboardKind::Text
+    } else if clipboard.get_image().is_ok() {
+        ClipboardKind::Image
+    } else {
+        ClipboardKind::Empty
+    })
+}
+
 pub(crate) fn get_selected_text_by_clipboard(
-    enigo: &mut Enigo,
+    enigo: &mut dyn Keyboard,
     cancel_select: bool,
+    cancel: &crate::CancellationToken,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    use arboard::Clipboard;
+    Ok(get_selection_by_clipboard(enigo, cancel_select, false, cancel)?.0)
+}
 
-    let old_clipboard = (Clipboard::new()?.get_text(), Clipboard::new()?.get_image());
+/// Like [`get_selected_text_by_clipboard`], also returning the HTML flavor
+/// the app put on the clipboard, if any.
+pub(crate) fn get_selected_text_and_html_by_clipboard(
+    enigo: &mut dyn Keyboard,
+) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
+    get_selection_by_clipboard(enigo, false, true, &crate::CancellationToken::new())
+}
 
-    let mut write_clipboard = Clipboard::new()?;
+fn get_selection_by_clipboard(
+    enigo: &mut dyn Keyboard,
+    cancel_select: bool,
+    read_html: bool,
+    cancel: &crate::CancellationToken,
+) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
+    use arboard::Clipboard;
+
+    if cancel.is_cancelled() {
+        return Err(crate::cancel::cancelled_error());
+    }
+    let _round_trip = crate::round_trip::begin()?;
+    let old_clipboard = RestoreOnDrop::take()?;
 
     let not_selected_placeholder = "";
 
-    write_clipboard.set_text(not_selected_placeholder)?;
+    set_transient_text(not_selected_placeholder)?;
 
     thread::sleep(Duration::from_millis(50));
 
+    if cancel.is_cancelled() {
+        old_clipboard.restore()?;
+        return Err(crate::cancel::cancelled_error());
+    }
     copy(enigo);
 
     if cancel_select {
@@ -58,47 +260,31 @@ pub(crate) fn get_selected_text_by_clipboard(
 
     thread::sleep(Duration::from_millis(100));
 
-    let new_text = Clipboard::new()?.get_text();
-
-    match old_clipboard {
-        (Ok(old_text), _) => {
-            // Old Content is Text
-            write_clipboard.set_text(old_text.clone())?;
-            if let Ok(new) = new_text {
-                if new.trim() == not_selected_placeholder.trim() {
-                    Ok(String::new())
-                } else {
-                    Ok(new)
-                }
-            } else {
-                Ok(String::new())
-            }
-        }
-        (_, Ok(image)) => {
-            // Old Content is Image
-            write_clipboard.set_image(image)?;
-            if let Ok(new) = new_text {
-                if new.trim() == not_selected_placeholder.trim() {
-                    Ok(String::new())
-                } else {
-                    Ok(new)
-                }
-            } else {
-                Ok(String::new())
-            }
-        }
-        _ => {
-            // Old Content is Empty
-            write_clipboard.clear()?;
-            if let Ok(new) = new_text {
-                if new.trim() == not_selected_placeholder.trim() {
-                    Ok(String::new())
-                } else {
-                    Ok(new)
-                }
-            } else {
-                Ok(String::new())
-            }
-        }
+    if cancel.is_cancelled() {
+        old_clipboard.restore()?;
+        return Err(crate::cancel::cancelled_error());
     }
+
+    let text = match Clipboard::new()?.get_text() {
+        Ok(new) if new.trim() != not_selected_placeholder.trim() => new,
+        _ => String::new(),
+    };
+    let html = if read_html && !text.is_empty() {
+        Clipboard::new()?.get().html().ok()
+    } else {
+        None
+    };
+
+    old_clipboard.restore()?;
+    Ok((text, html))
+}
+
+/// Current mouse position in global screen coordinates.
+pub fn mouse_position() -> Result<crate::Point, Box<dyn std::error::Error>> {
+    let enigo = Enigo::new(&Settings::default())?;
+    let (x, y) = enigo.location()?;
+    Ok(crate::Point {
+        x: x as f64,
+        y: y as f64,
+    })
 }
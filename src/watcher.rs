@@ -0,0 +1,179 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(all(target_os = "linux", feature = "session-bus"))]
+use crate::atspi::SelectionChanges;
+#[cfg(target_os = "windows")]
+use crate::windows::SelectionChanges;
+
+/// Shorter intervals are raised to this, a zero interval would spin the
+/// watcher thread querying the focused app's accessibility tree.
+pub const MIN_WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Bumped by [`stop_all`], which stops the watchers started before.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Stops every running watcher, for [`crate::shutdown`].
+pub(crate) fn stop_all() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Emitted by [`watch_selection`] whenever the selection changes to a new,
+/// non-empty value.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SelectionEvent {
+    pub app_name: String,
+    pub text: String,
+    /// Where the mouse was when the change was noticed.
+    pub mouse_position: Option<crate::Point>,
+}
+
+/// Stops the watcher when dropped or when [`WatchHandle::stop`] is called.
+#[derive(Debug)]
+pub struct WatchHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl WatchHandle {
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Calls `callback` whenever the selection changes: after the app's
+/// selection-changed events on Windows and Linux, checked at least every
+/// `interval`, and by polling every `interval` elsewhere or when the
+/// accessibility bus isn't available. `interval` is at least
+/// [`MIN_WATCH_INTERVAL`].
+///
+/// Only the passive read paths are used (AX on macOS, UI Automation and the
+/// Office object models on Windows, the PRIMARY selection on Linux), the
+/// watcher never simulates copy keystrokes.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let handle = get_selected_text::watch_selection(Duration::from_millis(300), |event| {
+///     println!("{}: {}", event.app_name, event.text);
+/// })
+/// .unwrap();
+/// // ...
+/// handle.stop();
+/// ```
+pub fn watch_selection<F>(
+    interval: Duration,
+    mut callback: F,
+) -> Result<WatchHandle, Box<dyn std::error::Error>>
+where
+    F: FnMut(SelectionEvent) + Send + 'static,
+{
+    if !crate::passive_read_supported() {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Selection watching is not supported on this platform",
+        )));
+    }
+
+    let interval = interval.max(MIN_WATCH_INTERVAL);
+    let stopped = Arc::new(AtomicBool::new(false));
+    let handle = WatchHandle {
+        stopped: stopped.clone(),
+    };
+
+    let generation = GENERATION.load(Ordering::SeqCst);
+    let stopped =
+        move || stopped.load(Ordering::SeqCst) || GENERATION.load(Ordering::SeqCst) != generation;
+    thread::spawn(move || {
+        // apps announce selection changes (UI Automation on Windows, AT-SPI on
+        // Linux), reading only after one saves polling every app's
+        // accessibility tree
+        #[cfg(any(
+            target_os = "windows",
+            all(target_os = "linux", feature = "session-bus")
+        ))]
+        let mut changes = {
+            #[cfg(feature = "mock")]
+            let mocked = crate::mock::is_enabled();
+            #[cfg(not(feature = "mock"))]
+            let mocked = false;
+            (!mocked)
+                .then(SelectionChanges::subscribe)
+                .and_then(Result::ok)
+        };
+
+        let mut last: Option<SelectionEvent> = None;
+        while !stopped() {
+            #[cfg(any(
+                target_os = "windows",
+                all(target_os = "linux", feature = "session-bus")
+            ))]
+            if let Some(subscription) = &changes {
+                // wake up every interval anyway to notice `stop`
+                match subscription.receiver.recv_timeout(interval) {
+                    Ok(()) => {}
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                    // the bus went away, poll from now on
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                        changes = None;
+                        continue;
+                    }
+                }
+                // a drag fires one event per step, read once it settles
+                while subscription
+                    .receiver
+                    .recv_timeout(Duration::from_millis(50))
+                    .is_ok()
+                {}
+                check_selection(&mut last, &mut callback);
+                continue;
+            }
+            check_selection(&mut last, &mut callback);
+            thread::sleep(interval);
+        }
+    });
+
+    Ok(handle)
+}
+
+/// Reads the selection and calls `callback` if it changed since `last`.
+fn check_selection<F>(last: &mut Option<SelectionEvent>, callback: &mut F)
+where
+    F: FnMut(SelectionEvent),
+{
+    let Ok(text) = crate::get_selected_text_passively() else {
+        return;
+    };
+    #[cfg(not(feature = "redact"))]
+    let app_name = crate::active_app_name();
+    #[cfg(feature = "redact")]
+    let app = crate::redact::App::active();
+    #[cfg(feature = "redact")]
+    let Ok(text) = crate::redact::redact_text(text, || app.clone()) else {
+        return;
+    };
+    #[cfg(feature = "redact")]
+    let app_name = app.name;
+    let event = SelectionEvent {
+        app_name,
+        text,
+        mouse_position: crate::mouse_position().ok(),
+    };
+    // moving the mouse alone isn't a selection change
+    let changed = last
+        .as_ref()
+        .is_none_or(|last| last.app_name != event.app_name || last.text != event.text);
+    if !event.text.is_empty() && changed {
+        *last = Some(event.clone());
+        callback(event);
+    }
+}
@@ -0,0 +1,224 @@
+//! What a Wayland session lets us do, and (with the `wayland` feature) a
+//! virtual-keyboard protocol client for compositors that have no data-control.
+
+use std::sync::OnceLock;
+
+#[cfg(feature = "wayland")]
+pub(crate) use protocol::VirtualKeyboard;
+
+/// Protocols of the compositor and devices relevant to capturing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Capabilities {
+    /// The primary selection can be read through data-control (ext, or wlr
+    /// from version 2), no key presses needed.
+    pub data_control: bool,
+    /// Key presses can be sent through the virtual-keyboard protocol
+    /// (wlroots compositors, KDE).
+    pub virtual_keyboard: bool,
+    /// /dev/uinput is writable.
+    pub uinput: bool,
+}
+
+/// How key presses reach the focused app, from least to most invasive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeystrokeMethod {
+    #[cfg(feature = "wayland")]
+    VirtualKeyboard,
+    /// Creates a keyboard device, seen system-wide.
+    Uinput,
+    /// XTest, which on Wayland only reaches XWayland apps.
+    X11,
+}
+
+fn probe() -> Capabilities {
+    // without the protocol client only /dev/uinput is left
+    #[cfg(feature = "wayland")]
+    let globals = protocol::globals();
+    #[cfg(not(feature = "wayland"))]
+    let globals: Vec<(String, u32)> = Vec::new();
+    let version = |name: &str| {
+        globals
+            .iter()
+            .find(|(interface, _)| interface == name)
+            .map(|(_, version)| *version)
+    };
+    Capabilities {
+        data_control: version("ext_data_control_manager_v1").is_some()
+            || version("zwlr_data_control_manager_v1").is_some_and(|v| v >= 2),
+        virtual_keyboard: version("zwp_virtual_keyboard_manager_v1").is_some(),
+        uinput: std::fs::OpenOptions::new()
+            .write(true)
+            .open(crate::uinput::UINPUT_PATH)
+            .is_ok(),
+    }
+}
+
+/// The session's capabilities, probed once. `None` outside Wayland.
+pub(crate) fn capabilities() -> Option<Capabilities> {
+    static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+
+    std::env::var_os("WAYLAND_DISPLAY")?;
+    Some(*CAPABILITIES.get_or_init(probe))
+}
+
+pub(crate) fn keystroke_method() -> KeystrokeMethod {
+    match capabilities() {
+        #[cfg(feature = "wayland")]
+        Some(capabilities) if capabilities.virtual_keyboard => KeystrokeMethod::VirtualKeyboard,
+        Some(capabilities) if capabilities.uinput => KeystrokeMethod::Uinput,
+        _ => KeystrokeMethod::X11,
+    }
+}
+
+#[cfg(feature = "wayland")]
+mod protocol {
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::fd::{AsFd, FromRawFd};
+    use std::time::Instant;
+
+    use enigo::{Direction, InputError, InputResult, Key, Keyboard};
+    use wayland_client::globals::{registry_queue_init, GlobalListContents};
+    use wayland_client::protocol::{wl_registry, wl_seat::WlSeat};
+    use wayland_client::{delegate_noop, Connection, Dispatch, EventQueue, QueueHandle};
+    use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::{
+        zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+        zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+    };
+
+    use crate::uinput::{KEY_LEFTALT, KEY_LEFTCTRL, KEY_LEFTSHIFT};
+
+    // resolved by the compositor, keycodes are Linux input event codes + 8
+    const KEYMAP: &str = r#"xkb_keymap {
+    xkb_keycodes { include "evdev+aliases(qwerty)" };
+    xkb_types { include "complete" };
+    xkb_compat { include "complete" };
+    xkb_symbols { include "pc+us+inet(evdev)" };
+};"#;
+
+    const KEYMAP_FORMAT_XKB_V1: u32 = 1;
+
+    // xkb's modifier bits in the keymap above
+    const MOD_SHIFT: u32 = 1 << 0;
+    const MOD_CONTROL: u32 = 1 << 2;
+    const MOD_ALT: u32 = 1 << 3;
+
+    struct State;
+
+    impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+        fn event(
+            _: &mut Self,
+            _: &wl_registry::WlRegistry,
+            _: wl_registry::Event,
+            _: &GlobalListContents,
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    delegate_noop!(State: ignore WlSeat);
+    delegate_noop!(State: ZwpVirtualKeyboardManagerV1);
+    delegate_noop!(State: ZwpVirtualKeyboardV1);
+
+    /// The interfaces and versions the compositor advertises.
+    pub(super) fn globals() -> Vec<(String, u32)> {
+        Connection::connect_to_env()
+            .ok()
+            .and_then(|conn| registry_queue_init::<State>(&conn).ok())
+            .map(|(globals, _)| {
+                globals.contents().with_list(|list| {
+                    list.iter()
+                        .map(|global| (global.interface.clone(), global.version))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    /// A keyboard of our own on the compositor's seat, typing into the focused
+    /// window.
+    pub(crate) struct VirtualKeyboard {
+        conn: Connection,
+        queue: EventQueue<State>,
+        keyboard: ZwpVirtualKeyboardV1,
+        modifiers: u32,
+        created: Instant,
+    }
+
+    impl VirtualKeyboard {
+        pub(crate) fn new() -> Result<Self, Box<dyn std::error::Error>> {
+            let conn = Connection::connect_to_env()?;
+            let (globals, mut queue) = registry_queue_init::<State>(&conn)?;
+            let qh = queue.handle();
+            let seat: WlSeat = globals.bind(&qh, 1..=1, ())?;
+            let manager: ZwpVirtualKeyboardManagerV1 = globals.bind(&qh, 1..=1, ())?;
+            let keyboard = manager.create_virtual_keyboard(&seat, &qh, ());
+
+            let keymap = unsafe { libc::memfd_create(c"keymap".as_ptr(), libc::MFD_CLOEXEC) };
+            if keymap < 0 {
+                return Err(Box::new(std::io::Error::last_os_error()));
+            }
+            let mut keymap = unsafe { File::from_raw_fd(keymap) };
+            keymap.write_all(KEYMAP.as_bytes())?;
+            keymap.write_all(&[0])?;
+            keyboard.keymap(
+                KEYMAP_FORMAT_XKB_V1,
+                keymap.as_fd(),
+                KEYMAP.len() as u32 + 1,
+            );
+            // keys sent before the compositor has the keymap are dropped
+            queue.roundtrip(&mut State)?;
+
+            Ok(Self {
+                conn,
+                queue,
+                keyboard,
+                modifiers: 0,
+                created: Instant::now(),
+            })
+        }
+    }
+
+    impl Drop for VirtualKeyboard {
+        fn drop(&mut self) {
+            self.keyboard.destroy();
+            let _ = self.queue.roundtrip(&mut State);
+        }
+    }
+
+    impl Keyboard for VirtualKeyboard {
+        fn fast_text(&mut self, _text: &str) -> InputResult<Option<()>> {
+            Ok(None)
+        }
+
+        fn key(&mut self, key: Key, direction: Direction) -> InputResult<()> {
+            self.raw(crate::uinput::evdev_keycode(key)?, direction)
+        }
+
+        fn raw(&mut self, keycode: u16, direction: Direction) -> InputResult<()> {
+            let modifier = match keycode {
+                KEY_LEFTCTRL => MOD_CONTROL,
+                KEY_LEFTSHIFT => MOD_SHIFT,
+                KEY_LEFTALT => MOD_ALT,
+                _ => 0,
+            };
+            let time = self.created.elapsed().as_millis() as u32;
+            if matches!(direction, Direction::Press | Direction::Click) {
+                self.keyboard.key(time, keycode as u32, 1);
+                self.modifiers |= modifier;
+            }
+            if matches!(direction, Direction::Release | Direction::Click) {
+                self.keyboard.key(time, keycode as u32, 0);
+                self.modifiers &= !modifier;
+            }
+            // apps learn about modifiers from this event, not from the key presses
+            if modifier != 0 {
+                self.keyboard.modifiers(self.modifiers, 0, 0, 0);
+            }
+            self.conn
+                .flush()
+                .map_err(|_| InputError::Simulate("the Wayland connection is gone"))
+        }
+    }
+}
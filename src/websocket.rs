@@ -0,0 +1,255 @@
+//! Selection queries and events over a localhost WebSocket, enabled with the
+//! `websocket` feature, for browser extensions and web tools that want the
+//! selection in apps outside the browser.
+//!
+//! Clients connect to `ws://127.0.0.1:<port>/?token=<token>` and send JSON
+//! requests, each answered with the same `id`:
+//!
+//! ```text
+//! → {"id": 1, "method": "get"}
+//! ← {"id": 1, "result": <the selection>}
+//! → {"id": 2, "method": "watch", "interval_ms": 300}
+//! ← {"id": 2, "result": null}
+//! ← {"event": "selection", "data": {"app_name": "Notes", "text": "hello", "mouse_position": null}}
+//! → {"id": 3, "method": "unwatch"}
+//! ← {"id": 3, "result": null}
+//! ```
+//!
+//! The selection is a [`SelectedText`](crate::SelectedText) object on macOS
+//! and a string elsewhere, failures come back as `{"id": 1, "error": "..."}`.
+//! `interval_ms` defaults to 300 and is at least
+//! [`MIN_WATCH_INTERVAL`](crate::MIN_WATCH_INTERVAL).
+//!
+//! ```no_run
+//! let server = get_selected_text::websocket::serve_websocket("127.0.0.1:0", "s3cret").unwrap();
+//! println!("ws://{}/?token=s3cret", server.local_addr());
+//! ```
+
+use std::io;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::Message;
+
+use crate::WatchHandle;
+
+const MAX_MESSAGE: usize = 1024 * 1024;
+/// How often a connection waiting for a request sends queued events.
+const EVENT_POLL: Duration = Duration::from_millis(50);
+
+type Connections = Arc<Mutex<Vec<(u64, TcpStream)>>>;
+
+/// Stops the server when dropped, when [`WebSocketServer::stop`] is called
+/// or on [`shutdown`](crate::shutdown), closing the open connections.
+#[derive(Debug)]
+pub struct WebSocketServer {
+    local_addr: SocketAddr,
+    running: crate::lifecycle::Running,
+}
+
+impl WebSocketServer {
+    /// Where it listens, with the port picked when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn stop(&self) {
+        self.running.stop();
+    }
+}
+
+/// Serves selection queries on `addr`, which has to be a loopback address,
+/// to clients passing `token` in the `token` query parameter. Pick a random,
+/// URL-safe token and hand it to the extension, e.g. through its native
+/// messaging host: any page in the browser can reach localhost.
+pub fn serve_websocket(
+    addr: impl ToSocketAddrs,
+    token: &str,
+) -> Result<WebSocketServer, Box<dyn std::error::Error>> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    if addrs.is_empty() || !addrs.iter().all(|addr| addr.ip().is_loopback()) {
+        return Err("The WebSocket server only listens on loopback addresses".into());
+    }
+    if token.is_empty() {
+        return Err("The WebSocket server needs a token".into());
+    }
+    let listener = TcpListener::bind(&addrs[..])?;
+    // polled, so the thread notices when it's stopped
+    listener.set_nonblocking(true)?;
+
+    let local_addr = listener.local_addr()?;
+    let stopped = Arc::new(AtomicBool::new(false));
+    let connections = Connections::default();
+    let running = {
+        let (stopped, connections) = (stopped.clone(), connections.clone());
+        crate::lifecycle::Running::new(move || {
+            stopped.store(true, Ordering::SeqCst);
+            for (_, connection) in connections.lock().drain(..) {
+                let _ = connection.shutdown(Shutdown::Both);
+            }
+        })
+    };
+    let token = token.to_string();
+    thread::spawn(move || {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        while !stopped.load(Ordering::SeqCst) {
+            let Ok((stream, _)) = listener.accept() else {
+                thread::sleep(Duration::from_millis(50));
+                continue;
+            };
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let Ok(clone) = stream
+                .set_nonblocking(false)
+                .and_then(|_| stream.try_clone())
+            else {
+                continue;
+            };
+            connections.lock().push((id, clone));
+            let connections = connections.clone();
+            let token = token.clone();
+            thread::spawn(move || {
+                let _ = serve_connection(stream, &token);
+                connections.lock().retain(|&(other, _)| other != id);
+            });
+        }
+    });
+    Ok(WebSocketServer {
+        local_addr,
+        running,
+    })
+}
+
+fn serve_connection(stream: TcpStream, token: &str) -> tungstenite::Result<()> {
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE))
+        .max_frame_size(Some(MAX_MESSAGE));
+    // the error response is tungstenite's type
+    #[allow(clippy::result_large_err)]
+    let check_token = |request: &Request, response: Response| {
+        let query = request.uri().query().unwrap_or_default();
+        if query_param(query, "token") == Some(token) {
+            Ok(response)
+        } else {
+            let mut response = ErrorResponse::new(None);
+            *response.status_mut() = tungstenite::http::StatusCode::UNAUTHORIZED;
+            Err(response)
+        }
+    };
+    let mut socket = tungstenite::accept_hdr_with_config(stream, check_token, Some(config))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    // the watcher's events are sent in between reads
+    socket.get_ref().set_read_timeout(Some(EVENT_POLL))?;
+    let (events, queued) = mpsc::channel();
+    // dropping it stops the watcher along with the connection
+    let mut watch = None;
+    loop {
+        match socket.read() {
+            Ok(Message::Text(message)) => {
+                let response = handle(&message, &events, &mut watch);
+                socket.send(Message::text(response.to_string()))?;
+            }
+            Ok(Message::Close(_)) => {}
+            // pings are answered by tungstenite
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        while let Ok(event) = queued.try_recv() {
+            socket.send(Message::text(event))?;
+        }
+    }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find_map(|(key, value)| (key == name).then_some(value))
+}
+
+fn handle(message: &str, events: &mpsc::Sender<String>, watch: &mut Option<WatchHandle>) -> Value {
+    let request: Value = match serde_json::from_str(message) {
+        Ok(request) => request,
+        Err(e) => return json!({"id": null, "error": format!("Invalid request: {e}")}),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let result: Result<Value, Box<dyn std::error::Error>> =
+        match request.get("method").and_then(Value::as_str) {
+            Some("get") => crate::get_selected_text()
+                .and_then(|selection| Ok(serde_json::to_value(selection)?)),
+            Some("watch") => {
+                let interval = request
+                    .get("interval_ms")
+                    .and_then(Value::as_u64)
+                    .map_or(Duration::from_millis(300), Duration::from_millis);
+                let events = events.clone();
+                crate::watch_selection(interval, move |event| {
+                    let event = json!({"event": "selection", "data": event});
+                    let _ = events.send(event.to_string());
+                })
+                .map(|handle| {
+                    *watch = Some(handle);
+                    Value::Null
+                })
+            }
+            Some("unwatch") => {
+                *watch = None;
+                Ok(Value::Null)
+            }
+            Some(method) => Err(format!("Unknown method {method}").into()),
+            None => Err("Missing method".into()),
+        };
+    match result {
+        Ok(result) => json!({"id": id, "result": result}),
+        Err(e) => json!({"id": id, "error": e.to_string()}),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(
+        server: &WebSocketServer,
+        token: &str,
+    ) -> tungstenite::Result<tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>>
+    {
+        let url = format!("ws://{}/?token={token}", server.local_addr());
+        tungstenite::connect(url).map(|(socket, _)| socket)
+    }
+
+    #[test]
+    fn test_serve_websocket() {
+        let server = serve_websocket("127.0.0.1:0", "s3cret").unwrap();
+        match connect(&server, "wrong") {
+            Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 401),
+            other => panic!("expected a 401, got {other:?}"),
+        }
+
+        let mut socket = connect(&server, "s3cret").unwrap();
+        socket
+            .send(Message::text(r#"{"id": 7, "method": "nope"}"#))
+            .unwrap();
+        let response: Value =
+            serde_json::from_str(socket.read().unwrap().to_text().unwrap()).unwrap();
+        assert_eq!(response, json!({"id": 7, "error": "Unknown method nope"}));
+
+        // too large a message closes the connection
+        socket
+            .send(Message::text("x".repeat(MAX_MESSAGE + 1)))
+            .unwrap();
+        assert!(!matches!(socket.read(), Ok(Message::Text(_))));
+    }
+}
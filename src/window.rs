@@ -0,0 +1,216 @@
+//! Metadata about the frontmost window and the displays it can be on.
+//!
+//! # Coordinate space
+//!
+//! Every [`Point`] and [`Rect`] the crate returns (window frames, display
+//! frames, selection bounds, mouse positions) uses the same global space:
+//!
+//! - the origin is the top-left corner of the primary display,
+//! - `y` grows downwards, displays left of or above the primary one have
+//!   negative coordinates,
+//! - units are points on macOS (the Quartz / AX convention) and physical
+//!   pixels on Windows and Linux.
+//!
+//! Multiply by [`Display::scale_factor`] to go from points to pixels on
+//! macOS, and use [`Display::to_local`] / [`display_at`] to get coordinates
+//! relative to a single display.
+
+/// A point in global screen coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A rectangle in global screen coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.x
+            && point.x < self.x + self.width
+            && point.y >= self.y
+            && point.y < self.y + self.height
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    fn intersection_area(&self, other: &Rect) -> f64 {
+        let width = (self.x + self.width).min(other.x + other.width) - self.x.max(other.x);
+        let height = (self.y + self.height).min(other.y + other.height) - self.y.max(other.y);
+        width.max(0.0) * height.max(0.0)
+    }
+}
+
+/// A connected display, as returned by [`crate::displays`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Display {
+    /// Platform display id (`CGDirectDisplayID` on macOS, `HMONITOR` on
+    /// Windows, RandR output on Linux).
+    pub id: u32,
+    pub frame: Rect,
+    /// Physical pixels per logical point.
+    pub scale_factor: f64,
+    pub is_primary: bool,
+}
+
+impl Display {
+    /// Converts a global point to one relative to this display's top-left
+    /// corner, in the same units.
+    pub fn to_local(&self, point: Point) -> Point {
+        Point {
+            x: point.x - self.frame.x,
+            y: point.y - self.frame.y,
+        }
+    }
+
+    pub fn to_local_rect(&self, rect: Rect) -> Rect {
+        let origin = self.to_local(Point {
+            x: rect.x,
+            y: rect.y,
+        });
+        Rect {
+            x: origin.x,
+            y: origin.y,
+            ..rect
+        }
+    }
+}
+
+/// The display containing `point`, if any.
+pub fn display_at(point: Point) -> Result<Option<Display>, Box<dyn std::error::Error>> {
+    Ok(crate::displays()?
+        .into_iter()
+        .find(|display| display.frame.contains(point)))
+}
+
+/// The frontmost window, as returned by [`get_window_meta`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct WindowMeta {
+    pub app_name: String,
+    pub title: String,
+    pub process_id: u64,
+    /// Path of the owning process' executable, stabler than `app_name` for
+    /// per-app settings: it isn't localized and tells apart same-named apps.
+    pub executable_path: Option<String>,
+    pub window_id: String,
+    /// Win32 window class, e.g. `Chrome_WidgetWin_1` or `ConsoleWindowClass`.
+    /// `None` on other platforms.
+    pub window_class: Option<String>,
+    pub frame: Rect,
+    /// The display holding most of the window, `None` if it's off-screen.
+    pub display: Option<Display>,
+}
+
+/// Returns the frontmost window's title, owner, frame and the display it's
+/// on, read together so they describe the same moment.
+pub fn get_window_meta() -> Result<WindowMeta, Box<dyn std::error::Error>> {
+    #[cfg(feature = "mock")]
+    if crate::mock::is_enabled() {
+        return Ok(WindowMeta {
+            app_name: crate::mock::app_name(),
+            title: String::new(),
+            process_id: 0,
+            executable_path: None,
+            window_id: String::new(),
+            window_class: None,
+            frame: Rect::default(),
+            display: None,
+        });
+    }
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    {
+        #[cfg(target_os = "windows")]
+        let _dpi = crate::windows::PhysicalCoordinates::enter();
+        let window = active_window()?;
+        let frame = window.frame;
+        #[cfg(target_os = "windows")]
+        let window_class = Some(crate::windows::foreground_window_class());
+        #[cfg(not(target_os = "windows"))]
+        let window_class = None;
+        let display = display_for(&frame, crate::displays().unwrap_or_default());
+        Ok(WindowMeta {
+            app_name: window.app_name,
+            title: window.title,
+            process_id: window.process_id,
+            executable_path: window.process_path,
+            window_id: window.window_id,
+            window_class,
+            frame,
+            display,
+        })
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Window metadata is not supported on this platform",
+        )
+        .into())
+    }
+}
+
+/// The focused window as the platform reports it, before the display and
+/// window class are looked up.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+pub(crate) struct ActiveWindow {
+    pub(crate) app_name: String,
+    pub(crate) title: String,
+    pub(crate) process_id: u64,
+    pub(crate) process_path: Option<String>,
+    pub(crate) window_id: String,
+    pub(crate) frame: Rect,
+}
+
+/// The focused window, natively on macOS and through `active-win-pos-rs`
+/// elsewhere. Fails with `Unsupported` on Windows and Linux when built without
+/// the `active-window` feature.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+pub(crate) fn active_window() -> Result<ActiveWindow, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
+    {
+        crate::macos::active_window()
+    }
+    #[cfg(all(feature = "active-window", not(target_os = "macos")))]
+    {
+        let window = active_win_pos_rs::get_active_window()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::NotFound, "No active window"))?;
+        Ok(ActiveWindow {
+            app_name: window.app_name,
+            title: window.title,
+            process_id: window.process_id,
+            process_path: (!window.process_path.as_os_str().is_empty())
+                .then(|| window.process_path.to_string_lossy().into_owned()),
+            window_id: window.window_id,
+            frame: Rect {
+                x: window.position.x,
+                y: window.position.y,
+                width: window.position.width,
+                height: window.position.height,
+            },
+        })
+    }
+    #[cfg(all(not(feature = "active-window"), not(target_os = "macos")))]
+    {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Built without the active-window feature",
+        )
+        .into())
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+fn display_for(frame: &Rect, displays: Vec<Display>) -> Option<Display> {
+    displays
+        .into_iter()
+        .map(|display| (frame.intersection_area(&display.frame), display))
+        .filter(|(area, _)| *area > 0.0)
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, display)| display)
+}
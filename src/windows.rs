@@ -1,6 +1,958 @@
+use ::windows::Win32::UI::Accessibility::{
+    IUIAutomationEventHandler, IUIAutomationEventHandler_Impl,
+};
 use enigo::*;
 
-pub fn get_selected_text() -> Result<String, Box<dyn std::error::Error>> {
+use crate::attempt::Attempts;
+use crate::Strategy;
+
+pub fn get_selected_text(
+    options: &crate::CaptureOptions,
+    attempts: &mut Attempts,
+) -> Result<String, Box<dyn std::error::Error>> {
+    check_foreground_not_elevated()?;
+    if let Some(strategies) = options.chain() {
+        return get_selected_text_by_strategies(&strategies, options, attempts);
+    }
+    if let Some(console) = foreground_console() {
+        return attempts.record(Strategy::AppSpecific, "console", || {
+            get_console_selected_text(console)
+        });
+    }
+    if let Some(text) = attempts.record_found(
+        Strategy::Accessibility,
+        "chromium-ui-automation",
+        get_chromium_selected_text,
+    ) {
+        return Ok(text);
+    }
+    {
+        let _com = ComApartment::enter();
+        // falls back to copying when the app is busy (a dialog is open, a cell
+        // is being edited) or the selection isn't text
+        if let Some(office) = crate::office::get_selected_text() {
+            if let Ok(text) = attempts.record(Strategy::AppSpecific, "office", || office) {
+                return Ok(text);
+            }
+        }
+    }
+    // third-party terminals aren't consoles, ctrl+c would still interrupt them
+    if crate::terminal::focused() {
+        if let Ok(text) = attempts.record(
+            Strategy::Accessibility,
+            "ui-automation",
+            get_ui_automation_selected_text,
+        ) {
+            return Ok(text);
+        }
+    }
+    copy_selected_text(options, attempts)
+}
+
+fn copy_selected_text(
+    options: &crate::CaptureOptions,
+    attempts: &mut Attempts,
+) -> Result<String, Box<dyn std::error::Error>> {
+    attempts.record(Strategy::Clipboard, "clipboard", || {
+        if foreground_console().is_some() {
+            return Err(Box::new(crate::GetTextError::Terminal {
+                app: crate::active_app_id(),
+            }));
+        }
+        crate::terminal::ensure_not_focused()?;
+        let mut enigo = Enigo::new(&Settings::default()).unwrap();
+        crate::utils::get_selected_text_by_clipboard(&mut enigo, false, &options.cancel)
+    })
+}
+
+fn get_selected_text_by_strategies(
+    strategies: &[Strategy],
+    options: &crate::CaptureOptions,
+    attempts: &mut Attempts,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let found =
+        crate::options::try_strategies(strategies, &crate::active_app_id(), options, |strategy| {
+            let text = match strategy {
+                Strategy::AppSpecific => match foreground_console() {
+                    Some(console) => attempts.record(Strategy::AppSpecific, "console", || {
+                        get_console_selected_text(console)
+                    })?,
+                    None => {
+                        let _com = ComApartment::enter();
+                        match crate::office::get_selected_text() {
+                            Some(office) => {
+                                attempts.record(Strategy::AppSpecific, "office", || office)?
+                            }
+                            None => return Ok(None),
+                        }
+                    }
+                },
+                Strategy::Accessibility => match attempts.record_found(
+                    Strategy::Accessibility,
+                    "chromium-ui-automation",
+                    get_chromium_selected_text,
+                ) {
+                    Some(text) => text,
+                    None => attempts.record(
+                        Strategy::Accessibility,
+                        "ui-automation",
+                        get_ui_automation_selected_text,
+                    )?,
+                },
+                Strategy::Clipboard => copy_selected_text(options, attempts)?,
+                Strategy::Ocr => return Err(crate::unsupported_error()),
+            };
+            Ok((!text.is_empty()).then_some(text))
+        })?;
+    Ok(found.unwrap_or_default())
+}
+
+pub(crate) fn get_selected_text_and_html(
+) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
+    check_foreground_not_elevated()?;
+    if let Some(console) = foreground_console() {
+        return Ok((get_console_selected_text(console)?, None));
+    }
+    crate::terminal::ensure_not_focused()?;
     let mut enigo = Enigo::new(&Settings::default()).unwrap();
-    crate::utils::get_selected_text_by_clipboard(&mut enigo, false)
+    crate::utils::get_selected_text_and_html_by_clipboard(&mut enigo)
+}
+
+/// Same sources as [`get_selected_text`] minus the clipboard fallback, then
+/// the focused element's UI Automation text pattern.
+pub(crate) fn get_selected_text_passively() -> Result<String, Box<dyn std::error::Error>> {
+    check_foreground_not_elevated()?;
+    if let Some(console) = foreground_console() {
+        return get_console_selected_text(console);
+    }
+    if let Some(text) = get_chromium_selected_text() {
+        return Ok(text);
+    }
+    let _com = ComApartment::enter();
+    if let Some(Ok(text)) = crate::office::get_selected_text() {
+        return Ok(text);
+    }
+    get_ui_automation_selected_text()
+}
+
+/// The selection of the focused element's UI Automation text pattern.
+fn get_ui_automation_selected_text() -> Result<String, Box<dyn std::error::Error>> {
+    let _com = ComApartment::enter();
+    let ranges = unsafe { focused_text_pattern()?.GetSelection() }?;
+    Ok(texts_of_ranges(&ranges)?.join("\n"))
+}
+
+pub fn set_selected_text(_text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err(Box::new(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Setting the selected text is not supported on this platform",
+    )))
+}
+
+pub fn replace_selected_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    check_foreground_not_elevated()?;
+    let mut enigo = Enigo::new(&Settings::default()).unwrap();
+    crate::utils::paste_text_by_clipboard(&mut enigo, text)
+}
+
+pub fn type_text(
+    text: &str,
+    interval: std::time::Duration,
+    cancel: &crate::CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    check_foreground_not_elevated()?;
+    let mut enigo = Enigo::new(&Settings::default())?;
+    crate::utils::type_text(&mut enigo, text, interval, cancel)
+}
+
+pub use crate::utils::{get_clipboard_files, get_clipboard_kind, get_clipboard_text};
+
+/// Switches the calling thread to per-monitor DPI awareness until dropped, so
+/// window, monitor and cursor APIs report physical pixels instead of values
+/// virtualized for the process' DPI awareness.
+pub(crate) struct PhysicalCoordinates(::windows::Win32::UI::HiDpi::DPI_AWARENESS_CONTEXT);
+
+impl PhysicalCoordinates {
+    pub(crate) fn enter() -> Self {
+        use ::windows::Win32::UI::HiDpi::{
+            SetThreadDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        };
+        Self(unsafe { SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) })
+    }
+}
+
+impl Drop for PhysicalCoordinates {
+    fn drop(&mut self) {
+        // a null context means entering failed, there's nothing to restore
+        if !self.0.is_invalid() {
+            unsafe {
+                ::windows::Win32::UI::HiDpi::SetThreadDpiAwarenessContext(self.0);
+            }
+        }
+    }
+}
+
+pub fn mouse_position() -> Result<crate::Point, Box<dyn std::error::Error>> {
+    let _dpi = PhysicalCoordinates::enter();
+    crate::utils::mouse_position()
+}
+
+pub fn displays() -> Result<Vec<crate::Display>, Box<dyn std::error::Error>> {
+    let _dpi = PhysicalCoordinates::enter();
+    use ::windows::core::BOOL;
+    use ::windows::Win32::Foundation::{LPARAM, RECT};
+    use ::windows::Win32::Graphics::Gdi::{
+        EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO,
+    };
+    use ::windows::Win32::UI::HiDpi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
+    use ::windows::Win32::UI::WindowsAndMessaging::MONITORINFOF_PRIMARY;
+
+    unsafe extern "system" fn collect(
+        monitor: HMONITOR,
+        _hdc: HDC,
+        _clip: *mut RECT,
+        data: LPARAM,
+    ) -> BOOL {
+        let monitors = &mut *(data.0 as *mut Vec<HMONITOR>);
+        monitors.push(monitor);
+        true.into()
+    }
+
+    let mut monitors: Vec<HMONITOR> = Vec::new();
+    unsafe {
+        EnumDisplayMonitors(
+            None,
+            None,
+            Some(collect),
+            LPARAM(&mut monitors as *mut _ as isize),
+        )
+        .ok()?;
+    }
+
+    Ok(monitors
+        .into_iter()
+        .filter_map(|monitor| unsafe {
+            let mut info = MONITORINFO {
+                cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+                ..Default::default()
+            };
+            if !GetMonitorInfoW(monitor, &mut info).as_bool() {
+                return None;
+            }
+            let (mut dpi_x, mut dpi_y) = (96, 96);
+            let _ = GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y);
+            let rect = info.rcMonitor;
+            Some(crate::Display {
+                id: monitor.0 as usize as u32,
+                frame: crate::Rect {
+                    x: rect.left as f64,
+                    y: rect.top as f64,
+                    width: (rect.right - rect.left) as f64,
+                    height: (rect.bottom - rect.top) as f64,
+                },
+                scale_factor: dpi_x as f64 / 96.0,
+                is_primary: info.dwFlags & MONITORINFOF_PRIMARY != 0,
+            })
+        })
+        .collect())
+}
+
+/// Initializes COM on the calling thread until dropped.
+pub(crate) struct ComApartment(bool);
+
+impl ComApartment {
+    pub(crate) fn enter() -> Self {
+        use ::windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+        // S_FALSE (already initialized) needs a matching CoUninitialize too,
+        // RPC_E_CHANGED_MODE (the caller picked another apartment) doesn't
+        Self(unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok())
+    }
+}
+
+impl Drop for ComApartment {
+    fn drop(&mut self) {
+        if self.0 {
+            unsafe { ::windows::Win32::System::Com::CoUninitialize() };
+        }
+    }
+}
+
+/// Takes ownership of a SAFEARRAY of doubles, as UIA returns rectangles in.
+unsafe fn safe_array_doubles(array: *mut ::windows::Win32::System::Com::SAFEARRAY) -> Vec<f64> {
+    if array.is_null() {
+        return Vec::new();
+    }
+    let values = if (*array).pvData.is_null() {
+        Vec::new()
+    } else {
+        let len = (*array).rgsabound[0].cElements as usize;
+        std::slice::from_raw_parts((*array).pvData as *const f64, len).to_vec()
+    };
+    let _ = ::windows::Win32::System::Ole::SafeArrayDestroy(array);
+    values
+}
+
+/// Text pattern of the focused element, or of its closest ancestor with one:
+/// browsers focus links and other nodes inside the document that holds the
+/// selection. COM must be initialized on the calling thread.
+fn focused_text_pattern(
+) -> Result<::windows::Win32::UI::Accessibility::IUIAutomationTextPattern, Box<dyn std::error::Error>>
+{
+    use ::windows::Win32::System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER};
+    use ::windows::Win32::UI::Accessibility::{
+        CUIAutomation, IUIAutomation, IUIAutomationTextPattern, UIA_TextPatternId,
+    };
+
+    unsafe {
+        let automation: IUIAutomation =
+            CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER)?;
+        let walker = automation.ControlViewWalker()?;
+        let mut element = automation.GetFocusedElement()?;
+        for _ in 0..20 {
+            if let Ok(pattern) =
+                element.GetCurrentPatternAs::<IUIAutomationTextPattern>(UIA_TextPatternId)
+            {
+                return Ok(pattern);
+            }
+            let Ok(parent) = walker.GetParentElement(&element) else {
+                break;
+            };
+            element = parent;
+        }
+    }
+    Err(Box::new(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "The focused element doesn't expose its text through UI Automation",
+    )))
+}
+
+fn texts_of_ranges(
+    ranges: &::windows::Win32::UI::Accessibility::IUIAutomationTextRangeArray,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut texts = Vec::new();
+    unsafe {
+        for index in 0..ranges.Length()? {
+            texts.push(ranges.GetElement(index)?.GetText(-1)?.to_string());
+        }
+    }
+    Ok(texts)
+}
+
+// the text pattern gives one rectangle per line, this returns the box around them
+pub(crate) fn get_selection_bounds() -> Result<crate::Rect, Box<dyn std::error::Error>> {
+    let _dpi = PhysicalCoordinates::enter();
+    let _com = ComApartment::enter();
+    let rects = unsafe {
+        let ranges = focused_text_pattern()?.GetSelection()?;
+        let mut rects = Vec::new();
+        for index in 0..ranges.Length()? {
+            rects.extend(safe_array_doubles(
+                ranges.GetElement(index)?.GetBoundingRectangles()?,
+            ));
+        }
+        rects
+    };
+
+    let mut rects = rects
+        .chunks_exact(4)
+        .map(|rect| (rect[0], rect[1], rect[0] + rect[2], rect[1] + rect[3]));
+    let first = rects
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "Nothing is selected"))?;
+    let (left, top, right, bottom) =
+        rects.fold(first, |(l, t, r, b), (left, top, right, bottom)| {
+            (l.min(left), t.min(top), r.max(right), b.max(bottom))
+        });
+    Ok(crate::Rect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// Keeps the clipboard open until dropped. Clipboard managers and the app
+/// that just copied hold it for a few milliseconds, so opening is retried.
+struct OpenClipboard;
+
+impl OpenClipboard {
+    fn open() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut attempts = 0;
+        loop {
+            match unsafe { ::windows::Win32::System::DataExchange::OpenClipboard(None) } {
+                Ok(()) => return Ok(Self),
+                Err(e) if attempts >= 10 => return Err(e.into()),
+                Err(_) => {
+                    attempts += 1;
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for OpenClipboard {
+    fn drop(&mut self) {
+        let _ = unsafe { ::windows::Win32::System::DataExchange::CloseClipboard() };
+    }
+}
+
+/// Every format on the clipboard (text, HTML, files, images, app-specific
+/// ones), so a copy or paste round-trip can put back exactly what the user
+/// had.
+pub(crate) struct ClipboardSnapshot(Vec<(u32, Vec<u8>)>);
+
+impl ClipboardSnapshot {
+    pub(crate) fn take() -> Result<Self, Box<dyn std::error::Error>> {
+        use ::windows::Win32::Foundation::HGLOBAL;
+        use ::windows::Win32::System::DataExchange::{EnumClipboardFormats, GetClipboardData};
+        use ::windows::Win32::System::Memory::{GlobalLock, GlobalSize, GlobalUnlock};
+        use ::windows::Win32::System::Ole::{
+            CF_BITMAP, CF_DSPBITMAP, CF_DSPENHMETAFILE, CF_DSPMETAFILEPICT, CF_ENHMETAFILE,
+            CF_METAFILEPICT, CF_OWNERDISPLAY, CF_PALETTE,
+        };
+
+        // GDI handles rather than memory, and synthesized from CF_DIB / the
+        // memory formats anyway
+        let handle_formats = [
+            CF_BITMAP,
+            CF_DSPBITMAP,
+            CF_DSPENHMETAFILE,
+            CF_DSPMETAFILEPICT,
+            CF_ENHMETAFILE,
+            CF_METAFILEPICT,
+            CF_OWNERDISPLAY,
+            CF_PALETTE,
+        ]
+        .map(|format| format.0 as u32);
+
+        let _open = OpenClipboard::open()?;
+        let mut formats = Vec::new();
+        let mut format = 0;
+        loop {
+            format = unsafe { EnumClipboardFormats(format) };
+            if format == 0 {
+                break;
+            }
+            if handle_formats.contains(&format) {
+                continue;
+            }
+            let Ok(handle) = (unsafe { GetClipboardData(format) }) else {
+                continue;
+            };
+            let global = HGLOBAL(handle.0);
+            unsafe {
+                let data = GlobalLock(global);
+                if data.is_null() {
+                    continue;
+                }
+                let bytes = std::slice::from_raw_parts(data as *const u8, GlobalSize(global));
+                formats.push((format, bytes.to_vec()));
+                let _ = GlobalUnlock(global);
+            }
+        }
+        Ok(Self(formats))
+    }
+
+    pub(crate) fn restore(self) -> Result<(), Box<dyn std::error::Error>> {
+        use ::windows::Win32::Foundation::{GlobalFree, HANDLE};
+        use ::windows::Win32::System::DataExchange::{EmptyClipboard, SetClipboardData};
+        use ::windows::Win32::System::Memory::{
+            GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE,
+        };
+
+        let _open = OpenClipboard::open()?;
+        unsafe { EmptyClipboard()? };
+        for (format, bytes) in self.0 {
+            unsafe {
+                let global = GlobalAlloc(GMEM_MOVEABLE, bytes.len())?;
+                let data = GlobalLock(global);
+                if data.is_null() {
+                    let _ = GlobalFree(Some(global));
+                    continue;
+                }
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), data as *mut u8, bytes.len());
+                let _ = GlobalUnlock(global);
+                // on success the clipboard owns the memory
+                if SetClipboardData(format, Some(HANDLE(global.0))).is_err() {
+                    let _ = GlobalFree(Some(global));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Foreground window when it's a console: either a classic console host
+/// window or Windows Terminal. Ctrl+C sends them an interrupt instead of
+/// copying, so they must never get the clipboard fallback.
+fn foreground_console() -> Option<::windows::Win32::Foundation::HWND> {
+    let window = unsafe { ::windows::Win32::UI::WindowsAndMessaging::GetForegroundWindow() };
+    matches!(
+        window_class(window).as_str(),
+        "ConsoleWindowClass" | "CASCADIA_HOSTING_WINDOW_CLASS"
+    )
+    .then_some(window)
+}
+
+pub(crate) fn foreground_window_class() -> String {
+    window_class(unsafe { ::windows::Win32::UI::WindowsAndMessaging::GetForegroundWindow() })
+}
+
+fn window_class(window: ::windows::Win32::Foundation::HWND) -> String {
+    let mut class = [0u16; 256];
+    let len =
+        unsafe { ::windows::Win32::UI::WindowsAndMessaging::GetClassNameW(window, &mut class) };
+    String::from_utf16_lossy(&class[..len.max(0) as usize])
+}
+
+fn get_console_selected_text(
+    window: ::windows::Win32::Foundation::HWND,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // Windows Terminal and conhost on recent Windows expose their buffer
+    // through UI Automation
+    let _com = ComApartment::enter();
+    if let Ok(pattern) = focused_text_pattern() {
+        return Ok(texts_of_ranges(&unsafe { pattern.GetSelection() }?)?.join("\n"));
+    }
+    read_console_selection(window)
+}
+
+static CONSOLE_LOCKER: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+
+/// Reads the selection of a classic console window from its screen buffer.
+/// This needs attaching to the console, which a process can only do while it
+/// has none of its own: it fails for console apps.
+fn read_console_selection(
+    window: ::windows::Win32::Foundation::HWND,
+) -> Result<String, Box<dyn std::error::Error>> {
+    use ::windows::core::w;
+    use ::windows::Win32::Foundation::{CloseHandle, GENERIC_READ, GENERIC_WRITE};
+    use ::windows::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    use ::windows::Win32::System::Console::{
+        AttachConsole, FreeConsole, GetConsoleSelectionInfo, GetConsoleWindow,
+        ReadConsoleOutputCharacterW, CONSOLE_SELECTION_INFO, CONSOLE_SELECTION_NOT_EMPTY, COORD,
+    };
+    use ::windows::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId;
+
+    struct Attached;
+    impl Drop for Attached {
+        fn drop(&mut self) {
+            let _ = unsafe { FreeConsole() };
+        }
+    }
+
+    // attaching is process-wide
+    let _guard = CONSOLE_LOCKER.lock();
+    if !unsafe { GetConsoleWindow() }.is_invalid() {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Can't read another console's selection from a console process",
+        )));
+    }
+    let mut process_id = 0;
+    unsafe {
+        // reports the console's client process (cmd, powershell...), the one to attach to
+        GetWindowThreadProcessId(window, Some(&mut process_id));
+        AttachConsole(process_id)?;
+    }
+    let _attached = Attached;
+
+    let mut info = CONSOLE_SELECTION_INFO::default();
+    unsafe { GetConsoleSelectionInfo(&mut info)? };
+    if info.dwFlags & CONSOLE_SELECTION_NOT_EMPTY == 0 {
+        return Ok(String::new());
+    }
+    // the standard handles may be redirected, CONOUT$ is always the screen buffer
+    let output = unsafe {
+        CreateFileW(
+            w!("CONOUT$"),
+            (GENERIC_READ | GENERIC_WRITE).0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAGS_AND_ATTRIBUTES(0),
+            None,
+        )?
+    };
+
+    // the selection is a block of cells, as with conhost's default mouse selection
+    let rect = info.srSelection;
+    let width = (rect.Right - rect.Left + 1).max(0) as usize;
+    let mut lines = Vec::new();
+    for y in rect.Top..=rect.Bottom {
+        let mut buffer = vec![0u16; width];
+        let mut read = 0;
+        let position = COORD { X: rect.Left, Y: y };
+        if unsafe { ReadConsoleOutputCharacterW(output, &mut buffer, position, &mut read) }.is_err()
+        {
+            break;
+        }
+        lines.push(
+            String::from_utf16_lossy(&buffer[..read as usize])
+                .trim_end()
+                .to_string(),
+        );
+    }
+    let _ = unsafe { CloseHandle(output) };
+    Ok(lines.join("\n"))
+}
+
+/// Chrome, Edge and Electron apps only build their accessibility tree once an
+/// assistive client asks for it. Requesting the render widget's accessible
+/// object is how screen readers do that; the first capture after it may still
+/// find the tree empty.
+fn enable_chromium_accessibility(window: ::windows::Win32::Foundation::HWND) {
+    use ::windows::core::{w, PCWSTR};
+    use ::windows::Win32::Foundation::{LPARAM, WPARAM};
+    use ::windows::Win32::UI::WindowsAndMessaging::{
+        FindWindowExW, SendMessageTimeoutW, OBJID_CLIENT, SMTO_ABORTIFHUNG, WM_GETOBJECT,
+    };
+
+    unsafe {
+        let Ok(render_widget) = FindWindowExW(
+            Some(window),
+            None,
+            w!("Chrome_RenderWidgetHostHWND"),
+            PCWSTR::null(),
+        ) else {
+            return;
+        };
+        SendMessageTimeoutW(
+            render_widget,
+            WM_GETOBJECT,
+            WPARAM(0),
+            LPARAM(OBJID_CLIENT.0 as isize),
+            SMTO_ABORTIFHUNG,
+            100,
+            None,
+        );
+    }
+}
+
+/// Reads a Chromium selection through UI Automation. `None` when it can't
+/// tell, so the caller falls back to the clipboard.
+fn get_chromium_selected_text() -> Option<String> {
+    use ::windows::core::{Interface, BOOL};
+    use ::windows::Win32::UI::Accessibility::IUIAutomationTextPattern2;
+
+    let window = unsafe { ::windows::Win32::UI::WindowsAndMessaging::GetForegroundWindow() };
+    if window_class(window) != "Chrome_WidgetWin_1" {
+        return None;
+    }
+    enable_chromium_accessibility(window);
+
+    let _com = ComApartment::enter();
+    let pattern = focused_text_pattern().ok()?;
+    let text = texts_of_ranges(&unsafe { pattern.GetSelection() }.ok()?)
+        .ok()?
+        .join("\n");
+    if !text.is_empty() {
+        return Some(text);
+    }
+    // an empty selection is only trustworthy when the caret is in this
+    // document, otherwise the tree may just not be built yet
+    let pattern = pattern.cast::<IUIAutomationTextPattern2>().ok()?;
+    let mut is_active = BOOL(0);
+    unsafe { pattern.GetCaretRange(&mut is_active) }.ok()?;
+    is_active.as_bool().then_some(text)
+}
+
+/// Mandatory integrity level RID of a process (0x2000 medium, 0x3000 high for
+/// elevated processes...), `None` when its token can't be read.
+fn integrity_level(process: ::windows::Win32::Foundation::HANDLE) -> Option<u32> {
+    use ::windows::Win32::Foundation::{CloseHandle, HANDLE};
+    use ::windows::Win32::Security::{
+        GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation, TokenIntegrityLevel,
+        TOKEN_MANDATORY_LABEL, TOKEN_QUERY,
+    };
+    use ::windows::Win32::System::Threading::OpenProcessToken;
+
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(process, TOKEN_QUERY, &mut token).ok()?;
+        let mut buffer = [0u64; 16];
+        let mut len = 0;
+        let result = GetTokenInformation(
+            token,
+            TokenIntegrityLevel,
+            Some(buffer.as_mut_ptr() as *mut _),
+            std::mem::size_of_val(&buffer) as u32,
+            &mut len,
+        );
+        let _ = CloseHandle(token);
+        result.ok()?;
+        let sid = (*(buffer.as_ptr() as *const TOKEN_MANDATORY_LABEL))
+            .Label
+            .Sid;
+        let count = *GetSidSubAuthorityCount(sid);
+        Some(*GetSidSubAuthority(sid, count.checked_sub(1)? as u32))
+    }
+}
+
+/// Fails when the foreground window belongs to a process with a higher
+/// integrity level than ours, typically an app run as administrator: Windows
+/// drops the synthetic ctrl+c and UI Automation can't read it (UIPI), which
+/// would otherwise look like an empty selection.
+fn check_foreground_not_elevated() -> Result<(), Box<dyn std::error::Error>> {
+    use ::windows::Win32::Foundation::CloseHandle;
+    use ::windows::Win32::System::Threading::{
+        GetCurrentProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use ::windows::Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetWindowThreadProcessId,
+    };
+
+    let mut process_id = 0;
+    unsafe { GetWindowThreadProcessId(GetForegroundWindow(), Some(&mut process_id)) };
+    if process_id == 0 {
+        return Ok(());
+    }
+    let Some(own) = integrity_level(unsafe { GetCurrentProcess() }) else {
+        return Ok(());
+    };
+    let Ok(process) =
+        (unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id) })
+    else {
+        return Ok(());
+    };
+    // a token we aren't allowed to query belongs to a more privileged process
+    let target = integrity_level(process).unwrap_or(u32::MAX);
+    let _ = unsafe { CloseHandle(process) };
+    if target > own {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "The foreground app runs elevated (as administrator) and can't be read by a \
+             non-elevated process; run this app elevated too, or with uiAccess",
+        )));
+    }
+    Ok(())
+}
+
+#[windows_core::implement(IUIAutomationEventHandler)]
+struct SelectionChangedHandler(std::sync::mpsc::Sender<()>);
+
+impl IUIAutomationEventHandler_Impl for SelectionChangedHandler_Impl {
+    fn HandleAutomationEvent(
+        &self,
+        _sender: ::windows_core::Ref<::windows::Win32::UI::Accessibility::IUIAutomationElement>,
+        _event_id: ::windows::Win32::UI::Accessibility::UIA_EVENT_ID,
+    ) -> ::windows_core::Result<()> {
+        let _ = self.0.send(());
+        Ok(())
+    }
+}
+
+thread_local! {
+    // WinEvent callbacks get no user data, they run on the hooking thread
+    static WIN_EVENT_SENDER: std::cell::RefCell<Option<std::sync::mpsc::Sender<()>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+unsafe extern "system" fn on_win_event(
+    _hook: ::windows::Win32::UI::Accessibility::HWINEVENTHOOK,
+    _event: u32,
+    _window: ::windows::Win32::Foundation::HWND,
+    _object: i32,
+    _child: i32,
+    _thread: u32,
+    _time: u32,
+) {
+    WIN_EVENT_SENDER.with_borrow(|sender| {
+        if let Some(sender) = sender {
+            let _ = sender.send(());
+        }
+    });
+}
+
+/// Text selection change notifications from every app, through UI Automation
+/// (`UIA_Text_TextSelectionChangedEventId`), or WinEvents
+/// (`EVENT_OBJECT_TEXTSELECTIONCHANGED`) when UIA isn't available. They are
+/// received on a dedicated thread until dropped.
+pub(crate) struct SelectionChanges {
+    pub(crate) receiver: std::sync::mpsc::Receiver<()>,
+    thread_id: u32,
+}
+
+impl SelectionChanges {
+    pub(crate) fn subscribe() -> Result<Self, Box<dyn std::error::Error>> {
+        use ::windows::Win32::System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER};
+        use ::windows::Win32::System::Threading::GetCurrentThreadId;
+        use ::windows::Win32::UI::Accessibility::{
+            CUIAutomation, IUIAutomation, SetWinEventHook, TreeScope_Subtree,
+            UIA_Text_TextSelectionChangedEventId, UnhookWinEvent,
+        };
+        use ::windows::Win32::UI::WindowsAndMessaging::{
+            DispatchMessageW, GetMessageW, PeekMessageW, TranslateMessage,
+            EVENT_OBJECT_TEXTSELECTIONCHANGED, MSG, PM_NOREMOVE, WINEVENT_OUTOFCONTEXT,
+            WINEVENT_SKIPOWNPROCESS,
+        };
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let (ready, thread_id) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let _com = ComApartment::enter();
+            let uia = unsafe {
+                CoCreateInstance::<_, IUIAutomation>(&CUIAutomation, None, CLSCTX_INPROC_SERVER)
+                    .and_then(|automation| {
+                        let handler: IUIAutomationEventHandler =
+                            SelectionChangedHandler(sender.clone()).into();
+                        automation.AddAutomationEventHandler(
+                            UIA_Text_TextSelectionChangedEventId,
+                            &automation.GetRootElement()?,
+                            TreeScope_Subtree,
+                            None,
+                            &handler,
+                        )?;
+                        Ok(automation)
+                    })
+            };
+            let hook = uia.is_err().then(|| {
+                WIN_EVENT_SENDER.set(Some(sender));
+                unsafe {
+                    SetWinEventHook(
+                        EVENT_OBJECT_TEXTSELECTIONCHANGED,
+                        EVENT_OBJECT_TEXTSELECTIONCHANGED,
+                        None,
+                        Some(on_win_event),
+                        0,
+                        0,
+                        WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+                    )
+                }
+            });
+
+            let mut msg = MSG::default();
+            unsafe {
+                // creates the message queue, so the quit message can't be lost
+                let _ = PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE);
+                let _ = ready.send(GetCurrentThreadId());
+                // both UIA (from its own threads) and WinEvent hooks need the
+                // subscribing thread alive and pumping
+                while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                    let _ = TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+                if let Ok(automation) = uia {
+                    let _ = automation.RemoveAllEventHandlers();
+                }
+                if let Some(hook) = hook {
+                    let _ = UnhookWinEvent(hook);
+                }
+            }
+        });
+        Ok(Self {
+            receiver,
+            thread_id: thread_id.recv()?,
+        })
+    }
+}
+
+impl Drop for SelectionChanges {
+    fn drop(&mut self) {
+        use ::windows::Win32::Foundation::{LPARAM, WPARAM};
+        use ::windows::Win32::UI::WindowsAndMessaging::{PostThreadMessageW, WM_QUIT};
+
+        let _ = unsafe { PostThreadMessageW(self.thread_id, WM_QUIT, WPARAM(0), LPARAM(0)) };
+    }
+}
+
+/// A `RegisterHotKey` registration, owned by a thread that calls
+/// `on_pressed` for each `WM_HOTKEY`. Dropping it unregisters the hotkey.
+#[derive(Debug)]
+pub(crate) struct HotkeyRegistration {
+    thread_id: u32,
+}
+
+impl HotkeyRegistration {
+    pub(crate) fn register(
+        hotkey: crate::Hotkey,
+        mut on_pressed: impl FnMut() + Send + 'static,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        use std::sync::atomic::{AtomicI32, Ordering};
+
+        use ::windows::Win32::System::Threading::GetCurrentThreadId;
+        use ::windows::Win32::UI::Input::KeyboardAndMouse::{
+            RegisterHotKey, UnregisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT,
+            MOD_WIN,
+        };
+        use ::windows::Win32::UI::WindowsAndMessaging::{
+            GetMessageW, PeekMessageW, MSG, PM_NOREMOVE, WM_HOTKEY,
+        };
+
+        // ids only have to be unique within the thread, but a per-process
+        // counter makes them easy to tell apart
+        static NEXT_ID: AtomicI32 = AtomicI32::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let mut modifiers = MOD_NOREPEAT;
+        for (pressed, modifier) in [
+            (hotkey.ctrl, MOD_CONTROL),
+            (hotkey.alt, MOD_ALT),
+            (hotkey.shift, MOD_SHIFT),
+            (hotkey.win, MOD_WIN),
+        ] {
+            if pressed {
+                modifiers |= modifier;
+            }
+        }
+        let (ready, registered) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut msg = MSG::default();
+            unsafe {
+                // creates the message queue, so the quit message can't be lost
+                let _ = PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE);
+                // WM_HOTKEY is posted to the queue of the registering thread
+                let result = RegisterHotKey(None, id, modifiers, hotkey.key.virtual_key())
+                    .map(|_| GetCurrentThreadId())
+                    .map_err(|e| format!("Couldn't register {hotkey}: {e}"));
+                let failed = result.is_err();
+                let _ = ready.send(result);
+                if failed {
+                    return;
+                }
+                while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                    if msg.message == WM_HOTKEY && msg.wParam.0 == id as usize {
+                        on_pressed();
+                    }
+                }
+                let _ = UnregisterHotKey(None, id);
+            }
+        });
+        Ok(Self {
+            thread_id: registered.recv()??,
+        })
+    }
+}
+
+impl Drop for HotkeyRegistration {
+    fn drop(&mut self) {
+        use ::windows::Win32::Foundation::{LPARAM, WPARAM};
+        use ::windows::Win32::UI::WindowsAndMessaging::{PostThreadMessageW, WM_QUIT};
+
+        let _ = unsafe { PostThreadMessageW(self.thread_id, WM_QUIT, WPARAM(0), LPARAM(0)) };
+    }
+}
+
+/// Waits up to `timeout` for the user to physically let go of Ctrl, Shift, Alt
+/// and Win. Captures are usually triggered by a hotkey, and a ctrl+c injected
+/// while its keys are still down arrives as ctrl+shift+c or similar.
+pub(crate) fn wait_for_modifiers_released(timeout: std::time::Duration) {
+    use ::windows::Win32::UI::Input::KeyboardAndMouse::{
+        GetAsyncKeyState, VK_CONTROL, VK_LWIN, VK_MENU, VK_RWIN, VK_SHIFT,
+    };
+
+    let deadline = std::time::Instant::now() + timeout;
+    let modifiers = [VK_CONTROL, VK_SHIFT, VK_MENU, VK_LWIN, VK_RWIN];
+    // the high bit is set while the key is down
+    while modifiers
+        .iter()
+        .any(|key| unsafe { GetAsyncKeyState(key.0 as i32) } < 0)
+    {
+        if std::time::Instant::now() >= deadline {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
 }
@@ -0,0 +1,167 @@
+//! X11 selection reads over the wire protocol (x11rb), without helper tools
+//! like xclip or xsel, so the Linux backend works on minimal systems.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ConnectionExt, CreateWindowAux, EventMask, Property, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        UTF8_STRING,
+        INCR,
+        GET_SELECTED_TEXT,
+    }
+}
+
+// per GetProperty request, in 32-bit units
+const PROPERTY_CHUNK_LENGTH: u32 = 1 << 16;
+
+/// Waits for the first event `matches` accepts, failing after `timeout`.
+fn wait_for<T>(
+    conn: &RustConnection,
+    timeout: Duration,
+    mut matches: impl FnMut(Event) -> Option<T>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        while let Some(event) = conn.poll_for_event()? {
+            if let Some(found) = matches(event) {
+                return Ok(found);
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "The selection owner didn't answer",
+            )));
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Reads and deletes `property` of `window`, a chunk at a time so values of
+/// any size come through whole. Returns its type and value.
+fn take_property(
+    conn: &RustConnection,
+    window: u32,
+    property: u32,
+) -> Result<(u32, Vec<u8>), Box<dyn std::error::Error>> {
+    let mut value = Vec::new();
+    loop {
+        // the server only deletes it along with the last chunk
+        let reply = conn
+            .get_property(
+                true,
+                window,
+                property,
+                AtomEnum::ANY,
+                (value.len() / 4) as u32,
+                PROPERTY_CHUNK_LENGTH,
+            )?
+            .reply()?;
+        value.extend(reply.value);
+        if reply.bytes_after == 0 {
+            return Ok((reply.type_, value));
+        }
+    }
+}
+
+/// Reads the converted selection from `window`'s property, following the
+/// INCR protocol when the owner sends it in chunks. `timeout` applies to
+/// each chunk, so large transfers only fail when the owner stops sending.
+fn read_property(
+    conn: &RustConnection,
+    window: u32,
+    atoms: &Atoms,
+    timeout: Duration,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let property = atoms.GET_SELECTED_TEXT;
+    // deleting the property is also what tells an INCR owner to start sending
+    let (type_, value) = take_property(conn, window, property)?;
+    if type_ != atoms.INCR {
+        return Ok(value);
+    }
+
+    let mut data = Vec::new();
+    loop {
+        wait_for(conn, timeout, |event| match event {
+            Event::PropertyNotify(e)
+                if e.window == window && e.atom == property && e.state == Property::NEW_VALUE =>
+            {
+                Some(())
+            }
+            _ => None,
+        })?;
+        let (_, chunk) = take_property(conn, window, property)?;
+        // an empty chunk ends the transfer
+        if chunk.is_empty() {
+            return Ok(data);
+        }
+        data.extend(chunk);
+    }
+}
+
+/// Asks the owner of the PRIMARY selection (the text currently highlighted,
+/// whatever app it's in) for its text, giving up when the owner stays silent
+/// for `timeout`. Empty when nothing is selected.
+pub(crate) fn read_primary_selection(
+    timeout: Duration,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let atoms = Atoms::new(&conn)?.reply()?;
+    let root = conn.setup().roots[screen_num].root;
+    let selection = AtomEnum::PRIMARY.into();
+    if conn.get_selection_owner(selection)?.reply()?.owner == NONE {
+        return Ok(String::new());
+    }
+
+    // owners deliver the conversion to a window of ours
+    let window = conn.generate_id()?;
+    conn.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        COPY_FROM_PARENT,
+        &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+    )?;
+
+    for target in [atoms.UTF8_STRING, AtomEnum::STRING.into()] {
+        conn.convert_selection(
+            window,
+            selection,
+            target,
+            atoms.GET_SELECTED_TEXT,
+            CURRENT_TIME,
+        )?;
+        conn.flush()?;
+        let property = wait_for(&conn, timeout, |event| match event {
+            Event::SelectionNotify(e) if e.requestor == window => Some(e.property),
+            _ => None,
+        })?;
+        // the owner can't convert to this target
+        if property == NONE {
+            continue;
+        }
+        let bytes = read_property(&conn, window, &atoms, timeout)?;
+        return Ok(if target == atoms.UTF8_STRING {
+            String::from_utf8_lossy(&bytes).into_owned()
+        } else {
+            // STRING is Latin-1
+            bytes.iter().map(|&byte| byte as char).collect()
+        });
+    }
+    Ok(String::new())
+}
@@ -0,0 +1,525 @@
+//! Capturing from a helper process over XPC, enabled with the `xpc` feature.
+//!
+//! A sandboxed app (e.g. from the Mac App Store) can't query other apps'
+//! accessibility elements or post the copy shortcut. A non-sandboxed helper
+//! built from this crate, and granted Accessibility access, does it for
+//! the app instead:
+//!
+//! ```no_run
+//! // the helper, a launch agent listing the name under `MachServices`;
+//! // sandboxed clients can only reach names prefixed with their app group
+//! let _listener = get_selected_text::xpc::serve_xpc("ABCDE12345.com.example.helper", None).unwrap();
+//! std::thread::park();
+//! ```
+//!
+//! ```no_run
+//! // the app
+//! use get_selected_text::xpc::XpcClient;
+//! use get_selected_text::Capturer;
+//!
+//! let client = XpcClient::connect_mach_service("ABCDE12345.com.example.helper").unwrap();
+//! let selected = Capturer::new(client).get_selected_text();
+//! ```
+//!
+//! Helpers bundled as an XPC service call [`run_xpc_service`] from `main`
+//! instead. Requests and replies are XPC dictionaries, so Swift clients can
+//! talk to the helper directly: `method` is `get` (with an optional `ocr`
+//! bool), `try_get` or `replace` (with `text`). Replies carry `error` on
+//! failure, `text` as an array of strings for `get` along with `app_name`,
+//! `bundle_id`, `process_id` and `is_file_paths`, and as a string for
+//! `try_get`.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::OnceLock;
+
+use block::{Block, ConcreteBlock};
+
+use crate::{CaptureOptions, SelectedText, Selection, SelectionProvider};
+
+#[allow(non_camel_case_types)]
+type xpc_object_t = *mut c_void;
+
+const XPC_CONNECTION_MACH_SERVICE_LISTENER: u64 = 1 << 0;
+const XPC_ARRAY_APPEND: usize = usize::MAX;
+
+/// What a client asks the helper.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XpcRequest {
+    /// [`get_selected_text_with_options`](crate::get_selected_text_with_options),
+    /// with OCR when `ocr` is set.
+    Get { ocr: bool },
+    /// [`try_get_selected_text`](crate::try_get_selected_text).
+    TryGet,
+    /// [`replace_selected_text`](crate::replace_selected_text).
+    Replace { text: String },
+}
+
+/// The helper's answer to an [`XpcRequest`].
+#[derive(Debug, Clone)]
+pub enum XpcReply {
+    /// Answers `Get`, with the app and the text but not the attempts, the
+    /// image or the window.
+    Selection(SelectedText),
+    /// Answers `TryGet`.
+    Text(String),
+    /// Answers `Replace`.
+    Done,
+    Error(String),
+}
+
+/// Stops accepting clients when dropped or on [`shutdown`](crate::shutdown).
+#[derive(Debug)]
+pub struct XpcListener {
+    _running: crate::lifecycle::Running,
+}
+
+struct Listener(xpc_object_t);
+
+// XPC objects may be used from any thread
+unsafe impl Send for Listener {}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        unsafe {
+            xpc_connection_cancel(self.0);
+            xpc_release(self.0);
+        }
+    }
+}
+
+/// Answers requests on the Mach service `name`, which the process must be
+/// launched by launchd for. With `peer_requirement`, a code signing
+/// requirement such as `anchor apple generic and identifier "com.example.app"`,
+/// other clients are turned away; it needs macOS 12.
+pub fn serve_xpc(
+    name: &str,
+    peer_requirement: Option<&str>,
+) -> Result<XpcListener, Box<dyn std::error::Error>> {
+    let requirement = peer_requirement.map(CString::new).transpose()?;
+    if requirement.is_some() && set_peer_requirement_fn().is_none() {
+        return Err("Peer code signing requirements need macOS 12".into());
+    }
+    let name = CString::new(name)?;
+    unsafe {
+        let listener = xpc_connection_create_mach_service(
+            name.as_ptr(),
+            std::ptr::null_mut(),
+            XPC_CONNECTION_MACH_SERVICE_LISTENER,
+        );
+        set_event_handler(listener, move |event| {
+            if is_type(event, &_xpc_type_connection) {
+                accept(event, requirement.as_deref());
+            }
+        });
+        xpc_connection_resume(listener);
+        let listener = Listener(listener);
+        Ok(XpcListener {
+            _running: crate::lifecycle::Running::new(move || drop(listener)),
+        })
+    }
+}
+
+static SERVICE_REQUIREMENT: OnceLock<Option<CString>> = OnceLock::new();
+
+/// Hands the process over to XPC as the `main` of an XPC service bundle,
+/// answering requests until the system stops it. See [`serve_xpc`] for
+/// `peer_requirement`. Only returns, with the reason, when it can't serve as
+/// asked: the requirement holds a NUL, or it's set before macOS 12.
+///
+/// ```no_run
+/// let error = get_selected_text::xpc::run_xpc_service(Some(
+///     r#"anchor apple generic and identifier "com.example.app""#,
+/// ));
+/// eprintln!("{error}");
+/// std::process::exit(1);
+/// ```
+pub fn run_xpc_service(peer_requirement: Option<&str>) -> Box<dyn std::error::Error> {
+    let requirement = match peer_requirement.map(CString::new).transpose() {
+        Ok(requirement) => requirement,
+        Err(e) => return e.into(),
+    };
+    if requirement.is_some() && set_peer_requirement_fn().is_none() {
+        return "Peer code signing requirements need macOS 12".into();
+    }
+    if SERVICE_REQUIREMENT.set(requirement).is_err() {
+        return "The XPC service is already running".into();
+    }
+
+    extern "C" fn new_connection(peer: xpc_object_t) {
+        let requirement = SERVICE_REQUIREMENT.get().and_then(Option::as_deref);
+        unsafe { accept(peer, requirement) }
+    }
+    unsafe { xpc_main(new_connection) }
+}
+
+unsafe fn accept(peer: xpc_object_t, requirement: Option<&CStr>) {
+    if let Some(requirement) = requirement {
+        // checked before serving, turned away all the same rather than let in
+        let Some(set_requirement) = set_peer_requirement_fn() else {
+            xpc_connection_cancel(peer);
+            return;
+        };
+        if set_requirement(peer, requirement.as_ptr()) != 0 {
+            xpc_connection_cancel(peer);
+            return;
+        }
+    }
+    set_event_handler(peer, move |message| {
+        if !is_type(message, &_xpc_type_dictionary) {
+            return;
+        }
+        let reply = match decode_request(message) {
+            Ok(request) => answer(request),
+            Err(e) => XpcReply::Error(e),
+        };
+        let dictionary = xpc_dictionary_create_reply(message);
+        if dictionary.is_null() {
+            return;
+        }
+        encode_reply(&reply, dictionary);
+        xpc_connection_send_message(peer, dictionary);
+        xpc_release(dictionary);
+    });
+    xpc_connection_resume(peer);
+}
+
+fn answer(request: XpcRequest) -> XpcReply {
+    let reply = match request {
+        XpcRequest::Get { ocr } => {
+            crate::get_selected_text_with_options(&CaptureOptions::new().ocr(ocr))
+                .map(XpcReply::Selection)
+        }
+        XpcRequest::TryGet => crate::try_get_selected_text().map(XpcReply::Text),
+        XpcRequest::Replace { text } => crate::replace_selected_text(&text).map(|_| XpcReply::Done),
+    };
+    reply.unwrap_or_else(|e| XpcReply::Error(e.to_string()))
+}
+
+/// A connection to a helper, which captures through it as a
+/// [`SelectionProvider`].
+#[derive(Debug)]
+pub struct XpcClient(xpc_object_t);
+
+unsafe impl Send for XpcClient {}
+unsafe impl Sync for XpcClient {}
+
+impl XpcClient {
+    /// Connects to a launch agent's Mach service, see [`serve_xpc`].
+    pub fn connect_mach_service(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let name = CString::new(name)?;
+        unsafe {
+            Ok(Self::resume(xpc_connection_create_mach_service(
+                name.as_ptr(),
+                std::ptr::null_mut(),
+                0,
+            )))
+        }
+    }
+
+    /// Connects to an XPC service in the app's `Contents/XPCServices`, by
+    /// its bundle identifier, see [`run_xpc_service`].
+    pub fn connect_service(bundle_id: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let bundle_id = CString::new(bundle_id)?;
+        unsafe {
+            Ok(Self::resume(xpc_connection_create(
+                bundle_id.as_ptr(),
+                std::ptr::null_mut(),
+            )))
+        }
+    }
+
+    unsafe fn resume(connection: xpc_object_t) -> Self {
+        // replies come back through `send`, errors there too
+        set_event_handler(connection, |_| {});
+        xpc_connection_resume(connection);
+        Self(connection)
+    }
+
+    /// Sends `request` and waits for the helper's reply.
+    pub fn send(&self, request: &XpcRequest) -> Result<XpcReply, Box<dyn std::error::Error>> {
+        unsafe {
+            let message = encode_request(request);
+            let reply = xpc_connection_send_message_with_reply_sync(self.0, message);
+            xpc_release(message);
+            let result = if is_type(reply, &_xpc_type_error) {
+                let description = get_string(reply, c"XPCErrorDescription");
+                Err(format!(
+                    "The XPC helper is unreachable: {}",
+                    description.as_deref().unwrap_or("unknown error")
+                ))
+            } else {
+                decode_reply(request, reply)
+            };
+            xpc_release(reply);
+            result.map_err(Into::into)
+        }
+    }
+}
+
+impl Drop for XpcClient {
+    fn drop(&mut self) {
+        unsafe {
+            xpc_connection_cancel(self.0);
+            xpc_release(self.0);
+        }
+    }
+}
+
+impl SelectionProvider for XpcClient {
+    fn get_selected_text(
+        &self,
+        options: &CaptureOptions,
+    ) -> Result<Selection, Box<dyn std::error::Error>> {
+        match self.send(&XpcRequest::Get { ocr: options.ocr })? {
+            XpcReply::Selection(selected) => Ok(selected),
+            XpcReply::Error(e) => Err(e.into()),
+            reply => Err(format!("Unexpected XPC reply {reply:?}").into()),
+        }
+    }
+
+    fn try_get_selected_text(&self) -> Result<String, Box<dyn std::error::Error>> {
+        match self.send(&XpcRequest::TryGet)? {
+            XpcReply::Text(text) => Ok(text),
+            XpcReply::Error(e) => Err(e.into()),
+            reply => Err(format!("Unexpected XPC reply {reply:?}").into()),
+        }
+    }
+
+    fn replace_selected_text(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self.send(&XpcRequest::Replace {
+            text: text.to_string(),
+        })? {
+            XpcReply::Done => Ok(()),
+            XpcReply::Error(e) => Err(e.into()),
+            reply => Err(format!("Unexpected XPC reply {reply:?}").into()),
+        }
+    }
+}
+
+unsafe fn encode_request(request: &XpcRequest) -> xpc_object_t {
+    let message = xpc_dictionary_create(std::ptr::null(), std::ptr::null(), 0);
+    match request {
+        XpcRequest::Get { ocr } => {
+            set_string(message, c"method", "get");
+            xpc_dictionary_set_bool(message, c"ocr".as_ptr(), *ocr);
+        }
+        XpcRequest::TryGet => set_string(message, c"method", "try_get"),
+        XpcRequest::Replace { text } => {
+            set_string(message, c"method", "replace");
+            set_string(message, c"text", text);
+        }
+    }
+    message
+}
+
+unsafe fn decode_request(message: xpc_object_t) -> Result<XpcRequest, String> {
+    match get_string(message, c"method").as_deref() {
+        Some("get") => Ok(XpcRequest::Get {
+            ocr: xpc_dictionary_get_bool(message, c"ocr".as_ptr()),
+        }),
+        Some("try_get") => Ok(XpcRequest::TryGet),
+        Some("replace") => Ok(XpcRequest::Replace {
+            text: get_string(message, c"text").ok_or("Missing text to replace with")?,
+        }),
+        Some(method) => Err(format!("Unknown method {method}")),
+        None => Err("Missing method".to_string()),
+    }
+}
+
+unsafe fn encode_reply(reply: &XpcReply, dictionary: xpc_object_t) {
+    match reply {
+        XpcReply::Selection(selected) => {
+            xpc_dictionary_set_bool(
+                dictionary,
+                c"is_file_paths".as_ptr(),
+                selected.is_file_paths,
+            );
+            set_string(dictionary, c"app_name", &selected.app_name);
+            if let Some(bundle_id) = &selected.bundle_id {
+                set_string(dictionary, c"bundle_id", bundle_id);
+            }
+            if let Some(process_id) = selected.process_id {
+                xpc_dictionary_set_int64(dictionary, c"process_id".as_ptr(), process_id.into());
+            }
+            let text = xpc_array_create(std::ptr::null(), 0);
+            for entry in &selected.text {
+                let entry = c_string(entry);
+                xpc_array_set_string(text, XPC_ARRAY_APPEND, entry.as_ptr());
+            }
+            xpc_dictionary_set_value(dictionary, c"text".as_ptr(), text);
+            xpc_release(text);
+        }
+        XpcReply::Text(text) => set_string(dictionary, c"text", text),
+        XpcReply::Done => {}
+        XpcReply::Error(e) => set_string(dictionary, c"error", e),
+    }
+}
+
+unsafe fn decode_reply(request: &XpcRequest, reply: xpc_object_t) -> Result<XpcReply, String> {
+    if let Some(e) = get_string(reply, c"error") {
+        return Ok(XpcReply::Error(e));
+    }
+    Ok(match request {
+        XpcRequest::Get { .. } => {
+            let text = xpc_dictionary_get_value(reply, c"text".as_ptr());
+            if text.is_null() {
+                return Err("The XPC reply has no text".to_string());
+            }
+            let has_process_id = !xpc_dictionary_get_value(reply, c"process_id".as_ptr()).is_null();
+            XpcReply::Selection(SelectedText {
+                is_file_paths: xpc_dictionary_get_bool(reply, c"is_file_paths".as_ptr()),
+                app_name: get_string(reply, c"app_name").unwrap_or_default(),
+                bundle_id: get_string(reply, c"bundle_id"),
+                process_id: has_process_id
+                    .then(|| xpc_dictionary_get_int64(reply, c"process_id".as_ptr()) as i32),
+                text: (0..xpc_array_get_count(text))
+                    .filter_map(|i| string_from_ptr(xpc_array_get_string(text, i)))
+                    .collect(),
+                ..Default::default()
+            })
+        }
+        XpcRequest::TryGet => XpcReply::Text(get_string(reply, c"text").unwrap_or_default()),
+        XpcRequest::Replace { .. } => XpcReply::Done,
+    })
+}
+
+unsafe fn set_event_handler(connection: xpc_object_t, handler: impl Fn(xpc_object_t) + 'static) {
+    let block = ConcreteBlock::new(handler).copy();
+    // XPC copies the block
+    let block: &Block<(xpc_object_t,), ()> = &block;
+    xpc_connection_set_event_handler(connection, block as *const _ as *const c_void);
+}
+
+unsafe fn is_type(object: xpc_object_t, ty: &c_void) -> bool {
+    std::ptr::eq(xpc_get_type(object), ty)
+}
+
+/// `value` without the NULs C strings can't hold.
+fn c_string(value: &str) -> CString {
+    CString::new(value.replace('\0', "")).unwrap_or_default()
+}
+
+unsafe fn set_string(dictionary: xpc_object_t, key: &CStr, value: &str) {
+    let value = c_string(value);
+    xpc_dictionary_set_string(dictionary, key.as_ptr(), value.as_ptr());
+}
+
+unsafe fn get_string(dictionary: xpc_object_t, key: &CStr) -> Option<String> {
+    string_from_ptr(xpc_dictionary_get_string(dictionary, key.as_ptr()))
+}
+
+unsafe fn string_from_ptr(ptr: *const c_char) -> Option<String> {
+    (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+type SetPeerRequirement = unsafe extern "C" fn(xpc_object_t, *const c_char) -> c_int;
+
+/// `xpc_connection_set_peer_code_signing_requirement`, looked up at runtime
+/// as it's missing before macOS 12.
+fn set_peer_requirement_fn() -> Option<SetPeerRequirement> {
+    const RTLD_DEFAULT: *mut c_void = -2isize as *mut c_void;
+    let symbol = unsafe {
+        dlsym(
+            RTLD_DEFAULT,
+            c"xpc_connection_set_peer_code_signing_requirement".as_ptr(),
+        )
+    };
+    (!symbol.is_null())
+        .then(|| unsafe { std::mem::transmute::<*mut c_void, SetPeerRequirement>(symbol) })
+}
+
+extern "C" {
+    static _xpc_type_connection: c_void;
+    static _xpc_type_dictionary: c_void;
+    static _xpc_type_error: c_void;
+
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+
+    fn xpc_main(handler: extern "C" fn(xpc_object_t)) -> !;
+    fn xpc_connection_create(name: *const c_char, queue: *mut c_void) -> xpc_object_t;
+    fn xpc_connection_create_mach_service(
+        name: *const c_char,
+        queue: *mut c_void,
+        flags: u64,
+    ) -> xpc_object_t;
+    fn xpc_connection_set_event_handler(connection: xpc_object_t, handler: *const c_void);
+    fn xpc_connection_resume(connection: xpc_object_t);
+    fn xpc_connection_cancel(connection: xpc_object_t);
+    fn xpc_connection_send_message(connection: xpc_object_t, message: xpc_object_t);
+    fn xpc_connection_send_message_with_reply_sync(
+        connection: xpc_object_t,
+        message: xpc_object_t,
+    ) -> xpc_object_t;
+
+    fn xpc_get_type(object: xpc_object_t) -> *const c_void;
+    fn xpc_release(object: xpc_object_t);
+
+    fn xpc_dictionary_create(
+        keys: *const *const c_char,
+        values: *const xpc_object_t,
+        count: usize,
+    ) -> xpc_object_t;
+    fn xpc_dictionary_create_reply(original: xpc_object_t) -> xpc_object_t;
+    fn xpc_dictionary_set_string(
+        dictionary: xpc_object_t,
+        key: *const c_char,
+        value: *const c_char,
+    );
+    fn xpc_dictionary_get_string(dictionary: xpc_object_t, key: *const c_char) -> *const c_char;
+    fn xpc_dictionary_set_bool(dictionary: xpc_object_t, key: *const c_char, value: bool);
+    fn xpc_dictionary_get_bool(dictionary: xpc_object_t, key: *const c_char) -> bool;
+    fn xpc_dictionary_set_int64(dictionary: xpc_object_t, key: *const c_char, value: i64);
+    fn xpc_dictionary_get_int64(dictionary: xpc_object_t, key: *const c_char) -> i64;
+    fn xpc_dictionary_set_value(dictionary: xpc_object_t, key: *const c_char, value: xpc_object_t);
+    fn xpc_dictionary_get_value(dictionary: xpc_object_t, key: *const c_char) -> xpc_object_t;
+
+    fn xpc_array_create(objects: *const xpc_object_t, count: usize) -> xpc_object_t;
+    fn xpc_array_set_string(array: xpc_object_t, index: usize, value: *const c_char);
+    fn xpc_array_get_count(array: xpc_object_t) -> usize;
+    fn xpc_array_get_string(array: xpc_object_t, index: usize) -> *const c_char;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_round_trips() {
+        let requests = [
+            XpcRequest::Get { ocr: true },
+            XpcRequest::TryGet,
+            XpcRequest::Replace {
+                text: "hello".to_string(),
+            },
+        ];
+        for request in requests {
+            let message = unsafe { encode_request(&request) };
+            assert_eq!(unsafe { decode_request(message) }, Ok(request));
+            unsafe { xpc_release(message) };
+        }
+
+        let selected = SelectedText {
+            app_name: "Finder".to_string(),
+            bundle_id: Some("com.apple.finder".to_string()),
+            process_id: Some(42),
+            is_file_paths: true,
+            text: vec!["/tmp/a".to_string(), "/tmp/b".to_string()],
+            ..Default::default()
+        };
+        unsafe {
+            let dictionary = xpc_dictionary_create(std::ptr::null(), std::ptr::null(), 0);
+            encode_reply(&XpcReply::Selection(selected.clone()), dictionary);
+            let reply = decode_reply(&XpcRequest::Get { ocr: false }, dictionary);
+            xpc_release(dictionary);
+            let Ok(XpcReply::Selection(decoded)) = reply else {
+                panic!("{reply:?}");
+            };
+            assert_eq!(decoded.app_name, selected.app_name);
+            assert_eq!(decoded.bundle_id, selected.bundle_id);
+            assert_eq!(decoded.process_id, selected.process_id);
+            assert!(decoded.is_file_paths);
+            assert_eq!(decoded.text, selected.text);
+        }
+    }
+}
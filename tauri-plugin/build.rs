@@ -0,0 +1,11 @@
+const COMMANDS: &[&str] = &[
+    "get_selected_text",
+    "watch_selection",
+    "unwatch_selection",
+    "check_permissions",
+    "request_permissions",
+];
+
+fn main() {
+    tauri_plugin::Builder::new(COMMANDS).build();
+}
@@ -0,0 +1,93 @@
+//! Tauri v2 plugin exposing the capture API as commands.
+//!
+//! ```ignore
+//! tauri::Builder::default()
+//!     .plugin(tauri_plugin_get_selected_text::init())
+//! ```
+//!
+//! Add `"get-selected-text:default"` to the app's capabilities, then from JS:
+//!
+//! ```js
+//! import { invoke } from "@tauri-apps/api/core";
+//! import { listen } from "@tauri-apps/api/event";
+//!
+//! const selected = await invoke("plugin:get-selected-text|get_selected_text");
+//! await listen("get-selected-text://selection-changed", (e) => console.log(e.payload));
+//! await invoke("plugin:get-selected-text|watch_selection", { intervalMs: 300 });
+//! ```
+
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tauri::plugin::{Builder, TauriPlugin};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use get_selected_text::WatchHandle;
+
+/// Event emitted with a [`get_selected_text::SelectionEvent`] payload while watching.
+pub const SELECTION_CHANGED_EVENT: &str = "get-selected-text://selection-changed";
+
+#[cfg(target_os = "macos")]
+type Selection = get_selected_text::SelectedText;
+#[cfg(not(target_os = "macos"))]
+type Selection = String;
+
+#[derive(Default)]
+struct Watcher(Mutex<Option<WatchHandle>>);
+
+#[tauri::command]
+async fn get_selected_text() -> Result<Selection, String> {
+    tauri::async_runtime::spawn_blocking(|| {
+        // JS only gets the message
+        get_selected_text::get_selected_text().map_err(|e| get_selected_text::error_chain(&*e))
+    })
+    .await
+    .map_err(|e| get_selected_text::error_chain(&e))?
+}
+
+#[tauri::command]
+fn watch_selection<R: Runtime>(
+    app: AppHandle<R>,
+    watcher: State<'_, Watcher>,
+    interval_ms: Option<u64>,
+) -> Result<(), String> {
+    let interval = Duration::from_millis(interval_ms.unwrap_or(300));
+    let handle = get_selected_text::watch_selection(interval, move |event| {
+        let _ = app.emit(SELECTION_CHANGED_EVENT, event);
+    })
+    .map_err(|e| get_selected_text::error_chain(&*e))?;
+    // replacing the previous handle stops it
+    *watcher.0.lock() = Some(handle);
+    Ok(())
+}
+
+#[tauri::command]
+fn unwatch_selection(watcher: State<'_, Watcher>) {
+    watcher.0.lock().take();
+}
+
+#[tauri::command]
+fn check_permissions() -> bool {
+    get_selected_text::has_permissions()
+}
+
+#[tauri::command]
+fn request_permissions() -> bool {
+    get_selected_text::request_permissions()
+}
+
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("get-selected-text")
+        .invoke_handler(tauri::generate_handler![
+            get_selected_text,
+            watch_selection,
+            unwatch_selection,
+            check_permissions,
+            request_permissions
+        ])
+        .setup(|app, _api| {
+            app.manage(Watcher::default());
+            Ok(())
+        })
+        .build()
+}
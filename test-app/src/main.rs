@@ -0,0 +1,105 @@
+//! A window holding one text view with a known text and selection, brought
+//! to the front, for the end-to-end tests in `tests/e2e.rs`:
+//!
+//! ```sh
+//! gst-test-app --text "Hello, world" --select 7:5
+//! ```
+//!
+//! `--select` takes a start and a length in UTF-16 code units, like
+//! `NSRange`.
+
+#[cfg(target_os = "macos")]
+fn main() {
+    let (text, selection) = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("gst-test-app: {e}");
+            std::process::exit(2);
+        }
+    };
+    app::run(&text, selection);
+}
+
+#[cfg(not(target_os = "macos"))]
+fn main() {
+    eprintln!("gst-test-app only runs on macOS");
+    std::process::exit(1);
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(String, (u64, u64)), String> {
+    let mut text = String::new();
+    let mut selection = (0, 0);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(format!("{arg} needs a value"))?;
+        match arg.as_str() {
+            "--text" => text = value,
+            "--select" => {
+                selection = value
+                    .split_once(':')
+                    .and_then(|(start, len)| Some((start.parse().ok()?, len.parse().ok()?)))
+                    .ok_or(format!("--select takes start:len, got {value}"))?
+            }
+            _ => return Err(format!("unknown argument {arg}")),
+        }
+    }
+    Ok((text, selection))
+}
+
+#[cfg(target_os = "macos")]
+mod app {
+    use cocoa::appkit::{
+        NSApp, NSApplication, NSApplicationActivationPolicyRegular, NSBackingStoreBuffered,
+        NSWindow, NSWindowStyleMask,
+    };
+    use cocoa::base::{id, nil, NO, YES};
+    use cocoa::foundation::{NSAutoreleasePool, NSPoint, NSRange, NSRect, NSSize, NSString};
+    use objc::{class, msg_send, sel, sel_impl};
+
+    pub fn run(text: &str, (start, len): (u64, u64)) {
+        unsafe {
+            let _pool = NSAutoreleasePool::new(nil);
+            let app = NSApp();
+            app.setActivationPolicy_(NSApplicationActivationPolicyRegular);
+
+            let frame = NSRect::new(NSPoint::new(200.0, 200.0), NSSize::new(480.0, 240.0));
+            let window = NSWindow::alloc(nil).initWithContentRect_styleMask_backing_defer_(
+                frame,
+                NSWindowStyleMask::NSTitledWindowMask,
+                NSBackingStoreBuffered,
+                NO,
+            );
+            window.setTitle_(NSString::alloc(nil).init_str("gst-test-app"));
+
+            let text_view: id = msg_send![class!(NSTextView), alloc];
+            let text_view: id = msg_send![text_view, initWithFrame: frame];
+            let _: () = msg_send![text_view, setString: NSString::alloc(nil).init_str(text)];
+            let _: () = msg_send![text_view, setSelectedRange: NSRange::new(start, len)];
+            window.setContentView_(text_view);
+            let _: () = msg_send![window, makeFirstResponder: text_view];
+
+            window.makeKeyAndOrderFront_(nil);
+            app.activateIgnoringOtherApps_(YES);
+            app.run();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<(String, (u64, u64)), String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args(&["--text", "Hello, world", "--select", "7:5"]),
+            Ok(("Hello, world".to_string(), (7, 5)))
+        );
+        assert!(args(&["--select", "7"]).is_err());
+        assert!(args(&["--text"]).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! End-to-end tests against `gst-test-app`. They need a logged-in session
+//! and Accessibility access for the test runner, so they're ignored by
+//! default:
+//!
+//! ```sh
+//! cargo test -p get-selected-text-test-app -- --ignored --test-threads 1
+//! ```
+#![cfg(target_os = "macos")]
+
+use std::process::{Child, Command};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use get_selected_text::{CaptureOptions, Strategy};
+
+const TEXT: &str = "The quick brown fox jumps over the lazy dog";
+
+/// The running test app, killed on drop.
+struct TestApp(Child);
+
+impl TestApp {
+    /// Launches the app with `TEXT[start..start + len]` selected and waits
+    /// until its window is the focused one.
+    fn launch(start: usize, len: usize) -> Self {
+        let child = Command::new(env!("CARGO_BIN_EXE_gst-test-app"))
+            .args(["--text", TEXT, "--select", &format!("{start}:{len}")])
+            .spawn()
+            .expect("gst-test-app should start");
+        let app = TestApp(child);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if get_selected_text::get_window_meta().is_ok_and(|meta| meta.title == "gst-test-app") {
+                return app;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        panic!("gst-test-app never came to the front");
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+#[ignore = "needs a logged-in session with Accessibility access"]
+fn test_accessibility_reads_selection() {
+    let _app = TestApp::launch(4, 11);
+    let options = CaptureOptions::new().strategies([Strategy::Accessibility]);
+    let selected = get_selected_text::get_selected_text_with_options(&options).unwrap();
+    assert_eq!(selected.text, vec!["quick brown".to_string()]);
+    assert_eq!(selected.app_name, "gst-test-app");
+}
+
+#[test]
+#[ignore = "needs a logged-in session with Accessibility access"]
+fn test_range_attributes() {
+    let _app = TestApp::launch(16, 3);
+    let bounds = get_selected_text::get_selection_bounds().unwrap();
+    assert!(bounds.width > 0.0 && bounds.height > 0.0, "{bounds:?}");
+    let window = get_selected_text::get_window_meta().unwrap().frame;
+    assert!(bounds.x >= window.x && bounds.x + bounds.width <= window.x + window.width);
+
+    let styled = get_selected_text::get_selected_styled_text().unwrap();
+    assert_eq!(styled.text, "fox");
+}
+
+#[test]
+#[ignore = "needs a logged-in session with Accessibility access"]
+fn test_watcher_sees_selection() {
+    let (sender, events) = mpsc::channel();
+    let handle = get_selected_text::watch_selection(Duration::from_millis(100), move |event| {
+        let _ = sender.send(event);
+    })
+    .unwrap();
+    let _app = TestApp::launch(35, 8);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let event = loop {
+        let event = events
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .expect("the watcher should report the test app's selection");
+        if event.app_name == "gst-test-app" {
+            break event;
+        }
+    };
+    handle.stop();
+    assert_eq!(event.text, "lazy dog");
+}